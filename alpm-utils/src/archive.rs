@@ -0,0 +1,292 @@
+use libarchive3_sys::ffi::*;
+
+use std::ffi::{CStr, CString};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr::{self, NonNull};
+
+const AE_IFMT: u32 = 0o170000;
+const AE_IFREG: u32 = 0o100000;
+const AE_IFDIR: u32 = 0o040000;
+
+/// The compression of an archive written by [`ArchiveWriter`], such as a repo database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    /// A plain tar archive.
+    None,
+    /// gzip, the default of `repo-add`.
+    #[default]
+    Gzip,
+    /// bzip2.
+    Bzip2,
+    /// xz.
+    Xz,
+    /// zstd.
+    Zstd,
+}
+
+impl Compression {
+    /// The file extension of the compression such as `.gz`, empty for
+    /// [`Compression::None`].
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Bzip2 => ".bz2",
+            Compression::Xz => ".xz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    fn filter(self) -> Option<&'static CStr> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(c"gzip"),
+            Compression::Bzip2 => Some(c"bzip2"),
+            Compression::Xz => Some(c"xz"),
+            Compression::Zstd => Some(c"zstd"),
+        }
+    }
+}
+
+/// A tar archive opened for writing, such as a repo database.
+///
+/// Entries are owned by root. The archive is complete once [`ArchiveWriter::finish`]
+/// returns.
+#[derive(Debug)]
+pub struct ArchiveWriter {
+    archive: NonNull<Struct_archive>,
+}
+
+impl Drop for ArchiveWriter {
+    fn drop(&mut self) {
+        unsafe { archive_write_free(self.as_ptr()) };
+    }
+}
+
+impl ArchiveWriter {
+    /// Creates the archive at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P, compression: Compression) -> io::Result<ArchiveWriter> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+        let archive = NonNull::new(unsafe { archive_write_new() })
+            .ok_or_else(|| io::Error::other("failed to create archive writer"))?;
+        let archive = ArchiveWriter { archive };
+
+        unsafe {
+            let ret = match compression.filter() {
+                Some(filter) => archive_write_add_filter_by_name(archive.as_ptr(), filter.as_ptr()),
+                None => archive_write_add_filter_none(archive.as_ptr()),
+            };
+            if ret != ARCHIVE_OK
+                || archive_write_set_format_pax_restricted(archive.as_ptr()) != ARCHIVE_OK
+                || archive_write_open_filename(archive.as_ptr(), path.as_ptr()) != ARCHIVE_OK
+            {
+                return Err(archive.error());
+            }
+        }
+
+        Ok(archive)
+    }
+
+    fn as_ptr(&self) -> *mut Struct_archive {
+        self.archive.as_ptr()
+    }
+
+    fn error(&self) -> io::Error {
+        archive_error(self.as_ptr(), "failed to write archive")
+    }
+
+    /// Adds a file with the permission bits `mode`.
+    pub fn add_file(&mut self, path: &str, data: &[u8], mode: u32, mtime: i64) -> io::Result<()> {
+        self.add(path, data, AE_IFREG | mode, mtime)
+    }
+
+    /// Adds a directory with the permission bits `mode`.
+    pub fn add_dir(&mut self, path: &str, mode: u32, mtime: i64) -> io::Result<()> {
+        self.add(path, &[], AE_IFDIR | mode, mtime)
+    }
+
+    fn add(&mut self, path: &str, data: &[u8], mode: u32, mtime: i64) -> io::Result<()> {
+        let path = CString::new(path)?;
+        let entry = NonNull::new(unsafe { archive_entry_new() })
+            .ok_or_else(|| io::Error::other("failed to create archive entry"))?;
+        let entry = entry.as_ptr();
+
+        let ret = unsafe {
+            archive_entry_set_pathname(entry, path.as_ptr());
+            archive_entry_set_filetype(entry, (mode & AE_IFMT) as _);
+            archive_entry_set_perm(entry, (mode & !AE_IFMT) as _);
+            archive_entry_set_size(entry, data.len() as _);
+            archive_entry_set_mtime(entry, mtime as _, 0);
+            let ret = archive_write_header(self.as_ptr(), entry);
+            archive_entry_free(entry);
+            ret
+        };
+        if ret != ARCHIVE_OK {
+            return Err(self.error());
+        }
+
+        let mut data = data;
+        while !data.is_empty() {
+            let n =
+                unsafe { archive_write_data(self.as_ptr(), data.as_ptr() as _, data.len() as _) };
+            if n <= 0 {
+                return Err(self.error());
+            }
+            data = &data[n as usize..];
+        }
+
+        Ok(())
+    }
+
+    /// Writes the end of the archive and closes the file.
+    pub fn finish(self) -> io::Result<()> {
+        match unsafe { archive_write_close(self.as_ptr()) } {
+            ARCHIVE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+}
+
+// an archive opened for reading with any format and compression libarchive supports. the
+// data of the current entry is read through Read.
+#[derive(Debug)]
+pub(crate) struct ArchiveReader {
+    archive: NonNull<Struct_archive>,
+    // the archive read by from_bytes, freed after the reader
+    data: Vec<u8>,
+}
+
+impl Drop for ArchiveReader {
+    fn drop(&mut self) {
+        unsafe { archive_read_free(self.as_ptr()) };
+    }
+}
+
+impl ArchiveReader {
+    pub(crate) fn open(path: &Path) -> io::Result<ArchiveReader> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let archive = ArchiveReader::new(Vec::new())?;
+
+        let ret = unsafe { archive_read_open_filename(archive.as_ptr(), path.as_ptr(), 16384) };
+        if ret != ARCHIVE_OK {
+            return Err(archive.error());
+        }
+
+        Ok(archive)
+    }
+
+    pub(crate) fn from_bytes(data: Vec<u8>) -> io::Result<ArchiveReader> {
+        let archive = ArchiveReader::new(data)?;
+
+        let ret = unsafe {
+            archive_read_open_memory(
+                archive.as_ptr(),
+                archive.data.as_ptr() as _,
+                archive.data.len() as _,
+            )
+        };
+        if ret != ARCHIVE_OK {
+            return Err(archive.error());
+        }
+
+        Ok(archive)
+    }
+
+    fn new(data: Vec<u8>) -> io::Result<ArchiveReader> {
+        let archive = NonNull::new(unsafe { archive_read_new() })
+            .ok_or_else(|| io::Error::other("failed to create archive reader"))?;
+        let archive = ArchiveReader { archive, data };
+
+        unsafe {
+            archive_read_support_filter_all(archive.as_ptr());
+            archive_read_support_format_all(archive.as_ptr());
+        }
+
+        Ok(archive)
+    }
+
+    fn as_ptr(&self) -> *mut Struct_archive {
+        self.archive.as_ptr()
+    }
+
+    fn error(&self) -> io::Error {
+        archive_error(self.as_ptr(), "failed to read archive")
+    }
+
+    // skips to the next regular file and returns its path, None at the end of the archive
+    pub(crate) fn next_file(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut entry = ptr::null_mut();
+            match unsafe { archive_read_next_header(self.as_ptr(), &mut entry) } {
+                ARCHIVE_OK | ARCHIVE_WARN => (),
+                ARCHIVE_EOF => return Ok(None),
+                _ => return Err(self.error()),
+            }
+
+            let filetype = unsafe { archive_entry_filetype(entry) } as u32 & AE_IFMT;
+            let path = unsafe { archive_entry_pathname(entry) };
+            if filetype == AE_IFREG && !path.is_null() {
+                let path = unsafe { CStr::from_ptr(path) };
+                return Ok(Some(path.to_string_lossy().into_owned()));
+            }
+        }
+    }
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { archive_read_data(self.as_ptr(), buf.as_mut_ptr() as _, buf.len() as _) };
+        if n < 0 {
+            Err(self.error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
+fn archive_error(archive: *mut Struct_archive, default: &str) -> io::Error {
+    let msg = unsafe { archive_error_string(archive) };
+    if msg.is_null() {
+        io::Error::other(default)
+    } else {
+        let msg = unsafe { CStr::from_ptr(msg) };
+        io::Error::other(msg.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_writer() {
+        let tmp = TempDir::new("alpm-utils-archive").unwrap();
+
+        for compression in [Compression::None, Compression::Gzip, Compression::Xz] {
+            let path = tmp
+                .path()
+                .join(format!("test.tar{}", compression.extension()));
+            let mut writer = ArchiveWriter::create(&path, compression).unwrap();
+            writer.add_dir("foo-1-1/", 0o755, 1).unwrap();
+            writer
+                .add_file("foo-1-1/desc", b"%NAME%\nfoo\n", 0o644, 1)
+                .unwrap();
+            writer.finish().unwrap();
+
+            let mut archive = ArchiveReader::open(&path).unwrap();
+            assert_eq!(archive.next_file().unwrap().unwrap(), "foo-1-1/desc");
+            let mut data = Vec::new();
+            archive.read_to_end(&mut data).unwrap();
+            assert_eq!(data, b"%NAME%\nfoo\n");
+            assert!(archive.next_file().unwrap().is_none());
+        }
+
+        let data = fs::read(tmp.path().join("test.tar.xz")).unwrap();
+        let mut archive = ArchiveReader::from_bytes(data).unwrap();
+        assert!(archive.next_file().unwrap().is_some());
+    }
+}
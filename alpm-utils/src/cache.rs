@@ -0,0 +1,808 @@
+use crate::tempdir::TempDir;
+use alpm::{Alpm, ChecksumError, Db, Package, SigLevel, decode_signature};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// A package file in a cache directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPackage {
+    /// The path to the package file.
+    pub path: PathBuf,
+    /// The package name.
+    pub name: String,
+    /// The package version including pkgrel and epoch.
+    pub version: String,
+    /// The package architecture.
+    pub arch: String,
+    /// The detached signature of the package, if there is one.
+    pub sig: Option<PathBuf>,
+    /// The size of the package file and its signature.
+    pub size: u64,
+}
+
+impl CachedPackage {
+    /// Parses the name, version and arch of a package from its file name.
+    ///
+    /// Returns None if the file name is not of the form `name-pkgver-pkgrel-arch.pkg.tar*`.
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Option<CachedPackage> {
+        let path = path.into();
+        let (name, version, arch) = parse_filename(path.file_name()?.to_str()?)?;
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+        Some(CachedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            arch: arch.to_string(),
+            path,
+            sig: None,
+            size,
+        })
+    }
+
+    /// Like [`CachedPackage::from_path`] but reads the name, version and arch from the
+    /// package's metadata instead of the file name.
+    pub fn load<P: Into<PathBuf>>(alpm: &Alpm, path: P) -> alpm::Result<CachedPackage> {
+        let path = path.into();
+        let pkg = alpm.pkg_load(path.as_os_str().as_bytes(), false, SigLevel::NONE)?;
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+        Ok(CachedPackage {
+            name: pkg.name().to_string(),
+            version: pkg.version().to_string(),
+            arch: pkg.arch().unwrap_or("any").to_string(),
+            path,
+            sig: None,
+            size,
+        })
+    }
+}
+
+fn parse_filename(filename: &str) -> Option<(&str, &str, &str)> {
+    let stem = &filename[..filename.find(".pkg.tar")?];
+    if filename.ends_with(".sig") || filename.ends_with(".part") {
+        return None;
+    }
+
+    let mut parts = stem.rsplitn(4, '-');
+    let arch = parts.next()?;
+    let pkgrel = parts.next()?;
+    let pkgver = parts.next()?;
+    let name = parts.next()?;
+
+    if name.is_empty() || pkgver.is_empty() || pkgrel.is_empty() || arch.is_empty() {
+        return None;
+    }
+
+    let version_start = stem.len() - arch.len() - pkgrel.len() - pkgver.len() - 2;
+    let version = &stem[version_start..stem.len() - arch.len() - 1];
+    Some((name, version, arch))
+}
+
+/// Which packages a [`CachePolicy`] considers for removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CacheSelect {
+    /// Every package.
+    #[default]
+    All,
+    /// Packages that are not installed.
+    Uninstalled,
+    /// Versions older than the installed version of a package.
+    OlderThanInstalled,
+}
+
+/// Decides which cached packages to remove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CachePolicy {
+    /// How many of the most recent versions of each package to keep.
+    pub keep: usize,
+    /// Which packages are considered.
+    pub select: CacheSelect,
+}
+
+impl Default for CachePolicy {
+    fn default() -> CachePolicy {
+        CachePolicy {
+            keep: 3,
+            select: CacheSelect::All,
+        }
+    }
+}
+
+/// How [`PackageCache::clean`] removes files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanOptions {
+    /// Only report what would be removed.
+    pub dry_run: bool,
+    /// Move files to this directory instead of deleting them.
+    pub trash: Option<PathBuf>,
+}
+
+/// The files removed by [`PackageCache::clean`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanReport {
+    /// The removed files.
+    pub removed: Vec<PathBuf>,
+    /// The combined size of the removed files.
+    pub bytes: u64,
+}
+
+/// The contents of the package cache directories.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageCache {
+    /// Package files.
+    pub packages: Vec<CachedPackage>,
+    /// Signatures without a package.
+    pub orphan_sigs: Vec<PathBuf>,
+    /// Partial downloads.
+    pub partial: Vec<PathBuf>,
+}
+
+impl PackageCache {
+    /// Scans the cache directories of the handle.
+    ///
+    /// Cache directories that do not exist are skipped.
+    pub fn from_alpm(alpm: &Alpm) -> io::Result<PackageCache> {
+        PackageCache::scan(alpm.cachedirs().iter())
+    }
+
+    /// Scans the given cache directories, parsing package information from the file names.
+    ///
+    /// Directories that do not exist are skipped. Files that are not packages,
+    /// signatures or partial downloads are ignored.
+    pub fn scan<I: IntoIterator<Item = P>, P: AsRef<Path>>(dirs: I) -> io::Result<PackageCache> {
+        PackageCache::scan_with(dirs, |path| Ok(CachedPackage::from_path(path)))
+    }
+
+    /// Scans the given cache directories, loading package information with `pkg_load`.
+    ///
+    /// This is slower than [`PackageCache::scan`] but does not rely on the file names.
+    /// Packages that fail to load are ignored.
+    pub fn scan_loaded<I: IntoIterator<Item = P>, P: AsRef<Path>>(
+        alpm: &Alpm,
+        dirs: I,
+    ) -> io::Result<PackageCache> {
+        PackageCache::scan_with(dirs, |path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if !name.contains(".pkg.tar") || name.ends_with(".sig") || name.ends_with(".part") {
+                return Ok(None);
+            }
+            Ok(CachedPackage::load(alpm, path).ok())
+        })
+    }
+
+    fn scan_with<I, P, F>(dirs: I, mut load: F) -> io::Result<PackageCache>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        F: FnMut(PathBuf) -> io::Result<Option<CachedPackage>>,
+    {
+        let mut cache = PackageCache::default();
+        let mut sigs = Vec::new();
+
+        for dir in dirs {
+            let entries = match fs::read_dir(dir.as_ref()) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            let mut paths = Vec::new();
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    paths.push(entry.path());
+                }
+            }
+            paths.sort();
+
+            for path in paths {
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                if name.ends_with(".part") {
+                    cache.partial.push(path);
+                } else if name.ends_with(".sig") {
+                    sigs.push(path);
+                } else if let Some(pkg) = load(path)? {
+                    cache.packages.push(pkg);
+                }
+            }
+        }
+
+        for sig in sigs {
+            let pkg_path = sig.with_extension("");
+            match cache.packages.iter_mut().find(|p| p.path == pkg_path) {
+                Some(pkg) => {
+                    pkg.size += fs::metadata(&sig).map(|m| m.len()).unwrap_or(0);
+                    pkg.sig = Some(sig);
+                }
+                None => cache.orphan_sigs.push(sig),
+            }
+        }
+
+        Ok(cache)
+    }
+
+    /// Groups the packages by name and arch, newest version first.
+    pub fn groups(&self) -> BTreeMap<(&str, &str), Vec<&CachedPackage>> {
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for pkg in &self.packages {
+            groups
+                .entry((pkg.name.as_str(), pkg.arch.as_str()))
+                .or_default()
+                .push(pkg);
+        }
+        for pkgs in groups.values_mut() {
+            pkgs.sort_by(|a, b| alpm::vercmp(b.version.as_str(), a.version.as_str()));
+        }
+        groups
+    }
+
+    /// The packages that `policy` would remove, using `localdb` to find installed packages.
+    pub fn candidates(&self, policy: &CachePolicy, localdb: &Db) -> Vec<&CachedPackage> {
+        self.candidates_with(policy, |name| {
+            localdb.pkg(name).ok().map(|p| p.version().to_string())
+        })
+    }
+
+    fn candidates_with<F: Fn(&str) -> Option<String>>(
+        &self,
+        policy: &CachePolicy,
+        installed: F,
+    ) -> Vec<&CachedPackage> {
+        let mut candidates = Vec::new();
+
+        for ((name, _), pkgs) in self.groups() {
+            let installed = installed(name);
+            let pkgs = match (policy.select, installed) {
+                (CacheSelect::All, _) => pkgs,
+                (CacheSelect::Uninstalled, None) => pkgs,
+                (CacheSelect::Uninstalled, Some(_)) => continue,
+                (CacheSelect::OlderThanInstalled, None) => continue,
+                (CacheSelect::OlderThanInstalled, Some(ver)) => pkgs
+                    .into_iter()
+                    .filter(|p| alpm::vercmp(p.version.as_str(), ver.as_str()) == Ordering::Less)
+                    .collect(),
+            };
+            candidates.extend(pkgs.into_iter().skip(policy.keep));
+        }
+
+        candidates
+    }
+
+    /// Removes packages and their signatures.
+    pub fn clean(&self, pkgs: &[&CachedPackage], opts: &CleanOptions) -> io::Result<CleanReport> {
+        let files = pkgs
+            .iter()
+            .flat_map(|p| std::iter::once(&p.path).chain(&p.sig))
+            .collect::<Vec<_>>();
+        remove_files(files, opts)
+    }
+
+    /// Removes orphaned signatures and partial downloads.
+    ///
+    /// Fails with [`io::ErrorKind::ResourceBusy`] if the lock file of the handle exists, as
+    /// the files may belong to a download that is still running.
+    pub fn clean_leftovers(&self, alpm: &Alpm, opts: &CleanOptions) -> io::Result<CleanReport> {
+        if fs::symlink_metadata(alpm.lockfile()).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("unable to lock database: {} exists", alpm.lockfile()),
+            ));
+        }
+        remove_files(self.orphan_sigs.iter().chain(&self.partial), opts)
+    }
+}
+
+/// Which checks [`PackageCache::verify`] does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VerifyOptions {
+    /// Compare the sha256 and md5 sums with the sync db.
+    pub checksums: bool,
+    /// Verify the signature of each package.
+    pub signatures: bool,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> VerifyOptions {
+        VerifyOptions {
+            checksums: true,
+            signatures: true,
+        }
+    }
+}
+
+/// A problem found by [`PackageCache::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheProblem {
+    /// No sync db has a package with this file name.
+    Unknown,
+    /// The sync db has a newer version of the package.
+    Stale {
+        /// The version in the sync db.
+        current: String,
+    },
+    /// The checksum of the file does not match the sync db.
+    ChecksumMismatch {
+        /// The kind of checksum, "sha256" or "md5".
+        kind: &'static str,
+        /// The checksum from the sync db.
+        expected: String,
+        /// The checksum of the file.
+        actual: String,
+    },
+    /// The checksum of the file could not be computed.
+    Checksum(ChecksumError),
+    /// The detached signature differs from the signature in the sync db.
+    SignatureMismatch,
+    /// There is no signature for the package.
+    Unsigned,
+    /// The signature failed to verify.
+    BadSignature(alpm::Error),
+    /// The file could not be read.
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for CacheProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheProblem::Unknown => f.write_str("not in any sync db"),
+            CacheProblem::Stale { current } => {
+                write!(f, "outdated, current version is {}", current)
+            }
+            CacheProblem::ChecksumMismatch {
+                kind,
+                expected,
+                actual,
+            } => write!(f, "{} mismatch: expected {} got {}", kind, expected, actual),
+            CacheProblem::Checksum(e) => e.fmt(f),
+            CacheProblem::SignatureMismatch => {
+                f.write_str("signature file differs from the sync db")
+            }
+            CacheProblem::Unsigned => f.write_str("package is not signed"),
+            CacheProblem::BadSignature(e) => write!(f, "invalid signature: {}", e),
+            CacheProblem::Io(e) => write!(f, "failed to read file: {}", e),
+        }
+    }
+}
+
+/// The result of verifying one package file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedPackage {
+    /// The path to the package file.
+    pub path: PathBuf,
+    /// The sync db the file belongs to.
+    pub db: Option<String>,
+    /// Everything that is wrong with the file.
+    pub problems: Vec<CacheProblem>,
+}
+
+impl VerifiedPackage {
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl PackageCache {
+    /// Checks every package against the sync dbs of the handle.
+    ///
+    /// Each file is matched to the sync db package of the same file name. Files that
+    /// match no package are reported as [`CacheProblem::Unknown`], or
+    /// [`CacheProblem::Stale`] if the sync db has a newer version of the package.
+    ///
+    /// Signatures are checked against the signature in the sync db if there is one,
+    /// otherwise against the detached signature next to the package.
+    pub fn verify(&self, alpm: &Alpm, opts: &VerifyOptions) -> Vec<VerifiedPackage> {
+        let mut by_filename = HashMap::new();
+        let mut by_name = HashMap::new();
+        for db in alpm.syncdbs() {
+            for pkg in db.pkgs() {
+                if let Some(filename) = pkg.filename() {
+                    by_filename.entry(filename).or_insert(pkg);
+                }
+                by_name.entry(pkg.name()).or_insert(pkg);
+            }
+        }
+
+        self.packages
+            .iter()
+            .map(|cached| {
+                let filename = cached.path.file_name().and_then(|n| n.to_str());
+                let mut report = VerifiedPackage {
+                    path: cached.path.clone(),
+                    db: None,
+                    problems: Vec::new(),
+                };
+
+                let Some(pkg) = filename.and_then(|n| by_filename.get(n)) else {
+                    let problem = match by_name.get(cached.name.as_str()) {
+                        Some(pkg)
+                            if alpm::vercmp(pkg.version().as_str(), cached.version.as_str())
+                                == Ordering::Greater =>
+                        {
+                            CacheProblem::Stale {
+                                current: pkg.version().to_string(),
+                            }
+                        }
+                        _ => CacheProblem::Unknown,
+                    };
+                    report.problems.push(problem);
+                    return report;
+                };
+
+                report.db = pkg.db().map(|db| db.name().to_string());
+                if opts.checksums {
+                    verify_checksums(pkg, &cached.path, &mut report.problems);
+                }
+                if opts.signatures {
+                    verify_signature(alpm, pkg, cached, &mut report.problems);
+                }
+                report
+            })
+            .collect()
+    }
+}
+
+fn verify_checksums(pkg: &Package, path: &Path, problems: &mut Vec<CacheProblem>) {
+    let path = path.as_os_str().as_bytes();
+    let sums = [("sha256", pkg.sha256sum()), ("md5", pkg.md5sum())];
+
+    for (kind, expected) in sums {
+        let Some(expected) = expected else { continue };
+        let actual = match kind {
+            "sha256" => alpm::compute_sha256sum(path),
+            _ => alpm::compute_md5sum(path),
+        };
+        match actual {
+            Ok(actual) if actual == expected => (),
+            Ok(actual) => problems.push(CacheProblem::ChecksumMismatch {
+                kind,
+                expected: expected.to_string(),
+                actual,
+            }),
+            Err(e) => {
+                problems.push(CacheProblem::Checksum(e));
+                return;
+            }
+        }
+    }
+}
+
+fn verify_signature(
+    alpm: &Alpm,
+    pkg: &Package,
+    cached: &CachedPackage,
+    problems: &mut Vec<CacheProblem>,
+) {
+    let path = cached.path.as_os_str().as_bytes();
+    let db_sig = pkg.base64_sig().and_then(|s| decode_signature(s).ok());
+
+    let ret = match (db_sig, &cached.sig) {
+        (None, None) => {
+            problems.push(CacheProblem::Unsigned);
+            return;
+        }
+        (None, Some(_)) => check_signature(alpm, path),
+        (Some(db_sig), sig_file) => {
+            if let Some(sig_file) = sig_file {
+                match fs::read(sig_file) {
+                    Ok(sig) if sig != db_sig => problems.push(CacheProblem::SignatureMismatch),
+                    Ok(_) => (),
+                    Err(e) => problems.push(CacheProblem::Io(e.kind())),
+                }
+            }
+            match verify_with_sig(alpm, &cached.path, &db_sig) {
+                Ok(ret) => ret,
+                Err(e) => {
+                    problems.push(CacheProblem::Io(e.kind()));
+                    return;
+                }
+            }
+        }
+    };
+
+    if let Err(e) = ret {
+        problems.push(CacheProblem::BadSignature(e));
+    }
+}
+
+// Loads the package without checking it, then judges its signature the way libalpm does when
+// loading a package with SigLevel::PACKAGE.
+fn check_signature(alpm: &Alpm, path: &[u8]) -> alpm::Result<()> {
+    let pkg = alpm.pkg_load(path, false, SigLevel::NONE)?;
+    let report = pkg.verify_with_level(SigLevel::PACKAGE);
+
+    match report.error {
+        Some(e) => Err(e),
+        None if !report.trusted => Err(alpm::Error::PkgInvalidSig),
+        None => Ok(()),
+    }
+}
+
+// libalpm can only check a package against a detached signature, so the signature from the
+// sync db is written next to a link to the package in a temporary directory.
+fn verify_with_sig(alpm: &Alpm, path: &Path, sig: &[u8]) -> io::Result<alpm::Result<()>> {
+    let dir = TempDir::new("alpm-utils-verify")?;
+    let link = dir.path().join(path.file_name().unwrap_or_default());
+    let mut sig_path = link.clone().into_os_string();
+    sig_path.push(".sig");
+    symlink(fs::canonicalize(path)?, &link)?;
+    fs::write(sig_path, sig)?;
+
+    Ok(check_signature(alpm, link.as_os_str().as_bytes()))
+}
+
+fn remove_files<'a, I: IntoIterator<Item = &'a PathBuf>>(
+    files: I,
+    opts: &CleanOptions,
+) -> io::Result<CleanReport> {
+    let mut report = CleanReport::default();
+
+    if let Some(trash) = &opts.trash
+        && !opts.dry_run
+    {
+        fs::create_dir_all(trash)?;
+    }
+
+    for file in files {
+        let size = fs::metadata(file)?.len();
+
+        if !opts.dry_run {
+            match &opts.trash {
+                Some(trash) => move_file(file, &trash_path(trash, file))?,
+                None => fs::remove_file(file)?,
+            }
+        }
+
+        report.bytes += size;
+        report.removed.push(file.clone());
+    }
+
+    Ok(report)
+}
+
+// files already in the trash are kept by adding a number to the name
+fn trash_path(trash: &Path, file: &Path) -> PathBuf {
+    let name = file.file_name().unwrap_or_default();
+    let mut path = trash.join(name);
+    let mut n = 1;
+
+    while fs::symlink_metadata(&path).is_ok() {
+        let mut numbered = name.to_os_string();
+        numbered.push(format!(".{}", n));
+        path = trash.join(numbered);
+        n += 1;
+    }
+
+    path
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // rename does not work across file systems
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNED: &str = "../alpm/tests/signed/pacman-5.1.3-1-x86_64.pkg.tar.xz";
+
+    #[test]
+    fn test_parse_filename() {
+        assert_eq!(
+            parse_filename("linux-5.1.8.arch1-1-x86_64.pkg.tar.xz"),
+            Some(("linux", "5.1.8.arch1-1", "x86_64"))
+        );
+        assert_eq!(
+            parse_filename("python-foo-bar-1:2.0-3-any.pkg.tar.zst"),
+            Some(("python-foo-bar", "1:2.0-3", "any"))
+        );
+        assert_eq!(parse_filename("linux-1-1-x86_64.pkg.tar.xz.sig"), None);
+        assert_eq!(parse_filename("foo-1-any.pkg.tar.zst"), None);
+        assert_eq!(parse_filename("core.db"), None);
+    }
+
+    #[test]
+    fn test_package_cache() {
+        let tmp = TempDir::new("alpm-utils-cache").unwrap();
+        let dir = tmp.path();
+        let files = [
+            "foo-1.0-1-x86_64.pkg.tar.zst",
+            "foo-1.0-1-x86_64.pkg.tar.zst.sig",
+            "foo-1.1-1-x86_64.pkg.tar.zst",
+            "foo-1.2-1-x86_64.pkg.tar.zst",
+            "foo-2.0-1-x86_64.pkg.tar.zst",
+            "bar-1-1-any.pkg.tar.zst",
+            "bar-2-1-any.pkg.tar.zst",
+            "baz-1-1-any.pkg.tar.zst.sig",
+            "baz-2-1-any.pkg.tar.zst.part",
+            "README",
+        ];
+        for file in files {
+            fs::write(dir.join(file), "x").unwrap();
+        }
+
+        let cache = PackageCache::scan([dir, &dir.join("missing")]).unwrap();
+        assert_eq!(cache.packages.len(), 6);
+        assert_eq!(cache.orphan_sigs, [dir.join(files[7])]);
+        assert_eq!(cache.partial, [dir.join(files[8])]);
+
+        let groups = cache.groups();
+        let foo = &groups[&("foo", "x86_64")];
+        assert_eq!(foo[0].version, "2.0-1");
+        assert_eq!(foo[3].sig, Some(dir.join(files[1])));
+        assert_eq!(foo[3].size, 2);
+
+        let installed = |name: &str| (name == "foo").then(|| "1.2-1".to_string());
+        let policy = CachePolicy {
+            keep: 1,
+            select: CacheSelect::All,
+        };
+        let names = |pkgs: Vec<&CachedPackage>| {
+            pkgs.iter()
+                .map(|p| format!("{}-{}", p.name, p.version))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(cache.candidates_with(&policy, installed)),
+            ["bar-1-1", "foo-1.2-1", "foo-1.1-1", "foo-1.0-1"]
+        );
+
+        let policy = CachePolicy {
+            keep: 0,
+            select: CacheSelect::Uninstalled,
+        };
+        assert_eq!(
+            names(cache.candidates_with(&policy, installed)),
+            ["bar-2-1", "bar-1-1"]
+        );
+
+        let policy = CachePolicy {
+            keep: 1,
+            select: CacheSelect::OlderThanInstalled,
+        };
+        let candidates = cache.candidates_with(&policy, installed);
+        assert_eq!(names(candidates.clone()), ["foo-1.0-1"]);
+
+        let dry = CleanOptions {
+            dry_run: true,
+            trash: None,
+        };
+        let report = cache.clean(&candidates, &dry).unwrap();
+        assert_eq!(report.removed, [dir.join(files[0]), dir.join(files[1])]);
+        assert_eq!(report.bytes, 2);
+        assert!(dir.join(files[0]).exists());
+
+        let trash = CleanOptions {
+            dry_run: false,
+            trash: Some(dir.join("trash")),
+        };
+        cache.clean(&candidates, &trash).unwrap();
+        assert!(!dir.join(files[0]).exists());
+        assert!(dir.join("trash").join(files[1]).exists());
+
+        fs::write(dir.join(files[0]), "new").unwrap();
+        let again = CachedPackage::from_path(dir.join(files[0])).unwrap();
+        cache.clean(&[&again], &trash).unwrap();
+        let trashed = dir.join("trash").join(files[0]);
+        assert_eq!(fs::read_to_string(&trashed).unwrap(), "x");
+        let mut numbered = trashed.into_os_string();
+        numbered.push(".1");
+        assert_eq!(fs::read_to_string(numbered).unwrap(), "new");
+
+        let dbpath = dir.join("db");
+        fs::create_dir(&dbpath).unwrap();
+        let handle = Alpm::new("/", dbpath.to_str().unwrap()).unwrap();
+        fs::write(dbpath.join("db.lck"), "").unwrap();
+        let err = cache
+            .clean_leftovers(&handle, &CleanOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        assert!(dir.join(files[8]).exists());
+
+        fs::remove_file(dbpath.join("db.lck")).unwrap();
+        let report = cache
+            .clean_leftovers(&handle, &CleanOptions::default())
+            .unwrap();
+        assert_eq!(report.removed.len(), 2);
+        assert!(!dir.join(files[8]).exists());
+    }
+
+    #[test]
+    fn test_verify() {
+        let tmp = TempDir::new("alpm-utils-verify").unwrap();
+        let dir = tmp.path();
+        let files = [
+            "linux-5.1.8.arch1-1-x86_64.pkg.tar.xz",
+            "linux-5.1.8.arch1-1-x86_64.pkg.tar.xz.sig",
+            "linux-5.0.arch1-1-x86_64.pkg.tar.xz",
+            "nope-1-1-any.pkg.tar.zst",
+        ];
+        for file in files {
+            fs::write(dir.join(file), "x").unwrap();
+        }
+
+        let alpm = Alpm::new("/", "../alpm/tests/db").unwrap();
+        alpm.register_syncdb("core", SigLevel::NONE).unwrap();
+        let cache = PackageCache::scan([dir]).unwrap();
+
+        let opts = VerifyOptions {
+            checksums: true,
+            signatures: false,
+        };
+        let report = cache.verify(&alpm, &opts);
+        assert_eq!(report.len(), 3);
+
+        let stale = report
+            .iter()
+            .find(|r| r.path == dir.join(files[2]))
+            .unwrap();
+        assert_eq!(
+            stale.problems,
+            [CacheProblem::Stale {
+                current: "5.1.8.arch1-1".into()
+            }]
+        );
+        let unknown = report
+            .iter()
+            .find(|r| r.path == dir.join(files[3]))
+            .unwrap();
+        assert_eq!(unknown.problems, [CacheProblem::Unknown]);
+
+        let linux = report
+            .iter()
+            .find(|r| r.path == dir.join(files[0]))
+            .unwrap();
+        assert_eq!(linux.db.as_deref(), Some("core"));
+        assert!(matches!(
+            linux.problems[0],
+            CacheProblem::ChecksumMismatch { kind: "sha256", .. }
+        ));
+
+        let opts = VerifyOptions {
+            checksums: false,
+            signatures: true,
+        };
+        let report = cache.verify(&alpm, &opts);
+        let linux = report
+            .iter()
+            .find(|r| r.path == dir.join(files[0]))
+            .unwrap();
+        assert_eq!(linux.problems[0], CacheProblem::SignatureMismatch);
+        assert!(!linux.is_ok());
+    }
+
+    #[test]
+    fn test_verify_with_sig() {
+        let mut alpm = Alpm::new("/", "../alpm/tests/db").unwrap();
+        alpm.set_gpgdir("../alpm/tests/signed/gnupg").unwrap();
+        let sig = fs::read(format!("{}.sig", SIGNED)).unwrap();
+        let wrong = fs::read("../alpm/tests/signed/file.txt.sig").unwrap();
+
+        assert_eq!(
+            verify_with_sig(&alpm, Path::new(SIGNED), &sig).unwrap(),
+            Ok(())
+        );
+        assert!(
+            verify_with_sig(&alpm, Path::new(SIGNED), &wrong)
+                .unwrap()
+                .is_err()
+        );
+        assert_eq!(check_signature(&alpm, SIGNED.as_bytes()), Ok(()));
+        assert_eq!(
+            check_signature(&alpm, b"../alpm/tests/pacman-5.1.3-1-x86_64.pkg.tar.xz"),
+            Err(alpm::Error::SigMissing)
+        );
+    }
+}
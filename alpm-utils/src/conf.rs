@@ -9,6 +9,8 @@ use pacmanconf::Config;
 
 /// Initiates and configures Alpm using a pacman config.
 ///
+/// Values that can not be applied are skipped. Use [`configure_alpm`] to find out which.
+///
 /// ```no_run
 /// use pacmanconf::Config;
 /// use alpm_utils::alpm_with_conf;
@@ -24,11 +26,52 @@ pub fn alpm_with_conf(conf: &Config) -> alpm::Result<Alpm> {
     Ok(alpm)
 }
 
+/// A value from a pacman config that [`configure_alpm`] did not apply.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConfigValue {
+    /// The section the value is from, `options` or the name of a repo.
+    pub section: String,
+    /// The name of the option as written in pacman.conf.
+    pub option: &'static str,
+    /// The value. Empty for boolean options.
+    pub value: String,
+}
+
+impl ConfigValue {
+    fn new<S: Into<String>, V: Into<String>>(section: S, option: &'static str, value: V) -> Self {
+        ConfigValue {
+            section: section.into(),
+            option,
+            value: value.into(),
+        }
+    }
+}
+
+/// Values of a pacman config that were not applied by [`configure_alpm`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigReport {
+    /// Options that alpm has no use for, such as `Color`, that are left to the frontend.
+    pub ignored: Vec<ConfigValue>,
+    /// Values that were not recognised.
+    pub unknown: Vec<ConfigValue>,
+    /// Values that were recognised but are not valid.
+    pub invalid: Vec<ConfigValue>,
+}
+
+impl ConfigReport {
+    /// Returns true if every value of the config was applied.
+    pub fn is_empty(&self) -> bool {
+        self.ignored.is_empty() && self.unknown.is_empty() && self.invalid.is_empty()
+    }
+}
+
 /// Configures an exsting Alpm handle  using a pacman config.
 ///
 /// You probably just want to use alpm_with_conf unless you need to do something before the
 /// repos are registered such as setting the db ext.
 ///
+/// Values that could not be applied are returned in the [`ConfigReport`].
+///
 /// ```no_run
 /// use pacmanconf::Config;
 /// use alpm_utils::configure_alpm;
@@ -37,82 +80,145 @@ pub fn alpm_with_conf(conf: &Config) -> alpm::Result<Alpm> {
 /// # fn main() {
 /// let conf = Config::new().unwrap();
 /// let mut alpm = Alpm::new(&*conf.root_dir, &*conf.db_path).unwrap();
-/// let alpm = configure_alpm(&mut alpm, &conf).unwrap();
+/// let report = configure_alpm(&mut alpm, &conf).unwrap();
+/// for value in &report.unknown {
+///     eprintln!("unknown value for {}: {}", value.option, value.value);
+/// }
 /// # }
 /// ```
-pub fn configure_alpm(alpm: &mut Alpm, conf: &Config) -> alpm::Result<()> {
+pub fn configure_alpm(alpm: &mut Alpm, conf: &Config) -> alpm::Result<ConfigReport> {
+    let mut report = ConfigReport::default();
+    let options = "options";
+
+    if !same_dir(alpm.root(), &conf.root_dir) {
+        report
+            .ignored
+            .push(ConfigValue::new(options, "RootDir", &*conf.root_dir));
+    }
+    if !same_dir(alpm.dbpath(), &conf.db_path) {
+        report
+            .ignored
+            .push(ConfigValue::new(options, "DBPath", &*conf.db_path));
+    }
+
     alpm.set_cachedirs(conf.cache_dir.iter())?;
     alpm.set_hookdirs(conf.hook_dir.iter())?;
     alpm.set_gpgdir(&*conf.gpg_dir)?;
     alpm.set_logfile(&*conf.log_file)?;
     alpm.set_ignorepkgs(conf.ignore_pkg.iter())?;
-    alpm.set_ignorepkgs(conf.ignore_pkg.iter())?;
+    alpm.set_ignoregroups(conf.ignore_group.iter())?;
     alpm.set_architectures(conf.architecture.iter())?;
     alpm.set_noupgrades(conf.no_upgrade.iter())?;
     alpm.set_noextracts(conf.no_extract.iter())?;
-    alpm.set_default_siglevel(parse_sig_level(&conf.sig_level))?;
-    alpm.set_local_file_siglevel(parse_sig_level(&conf.local_file_sig_level))?;
-    alpm.set_remote_file_siglevel(parse_sig_level(&conf.remote_file_sig_level))?;
+    alpm.set_default_siglevel(parse_sig_level(
+        &conf.sig_level,
+        options,
+        "SigLevel",
+        &mut report,
+    ))?;
+    alpm.set_local_file_siglevel(parse_sig_level(
+        &conf.local_file_sig_level,
+        options,
+        "LocalFileSigLevel",
+        &mut report,
+    ))?;
+    alpm.set_remote_file_siglevel(parse_sig_level(
+        &conf.remote_file_sig_level,
+        options,
+        "RemoteFileSigLevel",
+        &mut report,
+    ))?;
     alpm.set_use_syslog(conf.use_syslog);
     alpm.set_check_space(conf.check_space);
     alpm.set_disable_dl_timeout(conf.disable_download_timeout);
-    alpm.set_parallel_downloads(conf.parallel_downloads as u32);
     alpm.set_disable_sandbox_filesystem(conf.disable_sandbox_filesystem || conf.disable_sandbox);
     alpm.set_disable_sandbox_syscalls(conf.disable_sandbox_syscalls || conf.disable_sandbox);
     alpm.set_sandbox_user(conf.download_user.clone())?;
 
+    match u32::try_from(conf.parallel_downloads) {
+        Ok(n) if n > 0 => alpm.set_parallel_downloads(n),
+        _ => report.invalid.push(ConfigValue::new(
+            options,
+            "ParallelDownloads",
+            conf.parallel_downloads.to_string(),
+        )),
+    }
+
+    for pkg in &conf.hold_pkg {
+        report
+            .ignored
+            .push(ConfigValue::new(options, "HoldPkg", &**pkg));
+    }
+    for method in &conf.clean_method {
+        report
+            .ignored
+            .push(ConfigValue::new(options, "CleanMethod", &**method));
+    }
+    if !conf.xfer_command.is_empty() {
+        report.ignored.push(ConfigValue::new(
+            options,
+            "XferCommand",
+            &*conf.xfer_command,
+        ));
+    }
+
+    let flags = [
+        ("Color", conf.color),
+        ("TotalDownload", conf.total_download),
+        ("VerbosePkgLists", conf.verbose_pkg_lists),
+        ("ILoveCandy", conf.chomp),
+    ];
+    for (option, set) in flags {
+        if set {
+            report.ignored.push(ConfigValue::new(options, option, ""));
+        }
+    }
+
     for repo in &conf.repos {
-        register_db(alpm, repo)?;
+        register_db(alpm, repo, &mut report)?;
     }
 
-    Ok(())
+    Ok(report)
+}
+
+fn same_dir(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
 }
 
-fn parse_sig_level(levels: &[String]) -> SigLevel {
-    let mut sig = SigLevel::NONE;
+fn parse_sig_level(
+    levels: &[String],
+    section: &str,
+    option: &'static str,
+    report: &mut ConfigReport,
+) -> SigLevel {
+    let mut valid = Vec::new();
 
     for level in levels {
-        match level.as_str() {
-            "PackageNever" => sig.remove(SigLevel::PACKAGE),
-            "PackageOptional" => sig.insert(SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL),
-            "PackageRequired" => {
-                sig.insert(SigLevel::PACKAGE);
-                sig.remove(SigLevel::PACKAGE_OPTIONAL);
-            }
-            "PackageTrustOnly" => {
-                sig.remove(SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK)
-            }
-            "PackageTrustAll" => {
-                sig.insert(SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK)
-            }
-            "DatabaseNever" => sig.remove(SigLevel::DATABASE),
-            "DatabaseOptional" => sig.insert(SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL),
-            "DatabaseRequired" => {
-                sig.insert(SigLevel::DATABASE);
-                sig.remove(SigLevel::DATABASE_OPTIONAL);
-            }
-            "DatabaseTrustOnly" => {
-                sig.remove(SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK)
-            }
-            "DatabaseTrustAll" => {
-                sig.insert(SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK)
-            }
-            _ => {}
+        match level.parse::<SigLevel>() {
+            Ok(_) => valid.push(level.as_str()),
+            Err(_) => report
+                .unknown
+                .push(ConfigValue::new(section, option, &**level)),
         }
     }
 
-    sig
+    valid.join(" ").parse().unwrap()
 }
 
-fn register_db(alpm: &mut alpm::Alpm, repo: &pacmanconf::Repository) -> alpm::Result<()> {
+fn register_db(
+    alpm: &mut alpm::Alpm,
+    repo: &pacmanconf::Repository,
+    report: &mut ConfigReport,
+) -> alpm::Result<()> {
     let sig = if repo.sig_level.is_empty() {
         SigLevel::USE_DEFAULT
     } else {
-        parse_sig_level(&repo.sig_level)
+        parse_sig_level(&repo.sig_level, &repo.name, "SigLevel", report)
     };
 
     let db = alpm.register_syncdb_mut(&*repo.name, sig)?;
     db.set_servers(repo.servers.iter())?;
+    db.set_cache_servers(repo.cache_servers.iter())?;
 
     let mut usage = Usage::NONE;
 
@@ -122,14 +228,73 @@ fn register_db(alpm: &mut alpm::Alpm, repo: &pacmanconf::Repository) -> alpm::Re
             "Search" => usage |= Usage::SEARCH,
             "Install" => usage |= Usage::INSTALL,
             "Upgrade" => usage |= Usage::UPGRADE,
-            _ => {}
+            "All" => usage |= Usage::ALL,
+            _ => report
+                .unknown
+                .push(ConfigValue::new(&*repo.name, "Usage", &**v)),
         }
+    }
 
-        if usage == Usage::NONE {
-            usage = Usage::ALL
-        }
+    if usage == Usage::NONE {
+        usage = Usage::ALL
     }
 
     db.set_usage(usage)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacmanconf::Repository;
+
+    #[test]
+    fn test_configure_alpm() {
+        let conf = Config {
+            root_dir: "/".into(),
+            db_path: "../alpm/tests/db/".into(),
+            gpg_dir: "/etc/pacman.d/gnupg/".into(),
+            log_file: "/var/log/pacman.log".into(),
+            ignore_pkg: vec!["foo".into()],
+            ignore_group: vec!["bar".into()],
+            sig_level: vec!["PackageRequired".into(), "DatabaseMaybe".into()],
+            parallel_downloads: 0,
+            color: true,
+            repos: vec![Repository {
+                name: "core".into(),
+                servers: vec!["https://example.com/core".into()],
+                cache_servers: vec!["http://cache.lan/core".into()],
+                usage: vec!["Sync".into(), "Search".into(), "Bogus".into()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut alpm = Alpm::new("/", "../alpm/tests/db").unwrap();
+        let report = configure_alpm(&mut alpm, &conf).unwrap();
+
+        assert_eq!(alpm.ignorepkgs().iter().collect::<Vec<_>>(), ["foo"]);
+        assert_eq!(alpm.ignoregroups().iter().collect::<Vec<_>>(), ["bar"]);
+        assert_eq!(alpm.default_siglevel(), SigLevel::PACKAGE);
+
+        let db = alpm.syncdbs().iter().next().unwrap();
+        assert_eq!(
+            db.cache_servers().iter().collect::<Vec<_>>(),
+            ["http://cache.lan/core"]
+        );
+        assert_eq!(db.usage().unwrap(), Usage::SYNC | Usage::SEARCH);
+
+        assert_eq!(
+            report.unknown,
+            [
+                ConfigValue::new("options", "SigLevel", "DatabaseMaybe"),
+                ConfigValue::new("core", "Usage", "Bogus"),
+            ]
+        );
+        assert_eq!(
+            report.invalid,
+            [ConfigValue::new("options", "ParallelDownloads", "0")]
+        );
+        assert_eq!(report.ignored, [ConfigValue::new("options", "Color", "")]);
+    }
+}
@@ -0,0 +1,952 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the options section.
+pub const OPTIONS: &str = "options";
+
+/// Include directives nested deeper than this are an error.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// An error encountered while reading a pacman.conf.
+#[derive(Debug)]
+pub enum ConfError {
+    /// A file could not be read.
+    Io(PathBuf, io::Error),
+    /// A line could not be parsed.
+    Syntax {
+        /// The file containing the error.
+        file: PathBuf,
+        /// The line number, starting at 1.
+        line: usize,
+        /// A description of the error.
+        msg: &'static str,
+    },
+    /// Include directives were nested too deeply.
+    IncludeDepth(PathBuf),
+}
+
+impl fmt::Display for ConfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfError::Io(path, err) => write!(f, "failed to read {}: {}", path.display(), err),
+            ConfError::Syntax { file, line, msg } => {
+                write!(f, "{}:{}: {}", file.display(), line, msg)
+            }
+            ConfError::IncludeDepth(path) => {
+                write!(f, "{}: includes nested too deeply", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A `Key = Value` or `Key` line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Directive {
+    /// The key of the directive.
+    pub key: String,
+    /// The value of the directive. None for boolean options such as `Color`.
+    pub value: Option<String>,
+}
+
+/// A repository section of a pacman.conf with includes expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoSection {
+    /// The name of the repository.
+    pub name: String,
+    /// The directives of the repository in order.
+    pub directives: Vec<Directive>,
+}
+
+/// A pacman.conf with includes expanded.
+///
+/// Use [`ConfFile::resolve`] to create one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedConf {
+    /// The directives of the options section in order.
+    pub options: Vec<Directive>,
+    /// The repositories in order.
+    pub repos: Vec<RepoSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Other(String),
+    Section {
+        name: String,
+        raw: String,
+    },
+    Directive {
+        key: String,
+        value: Option<String>,
+        raw: String,
+    },
+}
+
+impl Line {
+    fn raw(&self) -> &str {
+        match self {
+            Line::Other(raw) => raw,
+            Line::Section { raw, .. } => raw,
+            Line::Directive { raw, .. } => raw,
+        }
+    }
+
+    fn directive(key: &str, value: Option<&str>) -> Line {
+        let raw = match value {
+            Some(value) => format!("{} = {}", key, value),
+            None => key.to_string(),
+        };
+        Line::Directive {
+            key: key.to_string(),
+            value: value.map(|v| v.to_string()),
+            raw,
+        }
+    }
+
+    fn section(name: &str) -> Line {
+        Line::Section {
+            name: name.to_string(),
+            raw: format!("[{}]", name),
+        }
+    }
+}
+
+/// A pacman.conf file that can be edited and written back.
+///
+/// Comments, blank lines and the order of directives are preserved. Lines that are not
+/// edited are written back exactly as they were read.
+///
+/// ```
+/// use alpm_utils::ConfFile;
+///
+/// let mut conf = ConfFile::parse("[options]\n#Color\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n").unwrap();
+/// conf.set_flag("Color", true);
+/// conf.add_repo("custom");
+/// conf.add_server("custom", "file:///srv/repo").unwrap();
+/// assert_eq!(
+///     conf.to_string(),
+///     "[options]\nColor\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n\n[custom]\nServer = file:///srv/repo\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfFile {
+    path: Option<PathBuf>,
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+impl fmt::Display for ConfFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i != 0 {
+                f.write_str("\n")?;
+            }
+            f.write_str(line.raw())?;
+        }
+        if self.trailing_newline && !self.lines.is_empty() {
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl ConfFile {
+    /// Parses a pacman.conf from a string.
+    pub fn parse(s: &str) -> Result<ConfFile, ConfError> {
+        parse_lines(s, Path::new("<string>"), true).map(|lines| ConfFile {
+            path: None,
+            lines,
+            trailing_newline: s.is_empty() || s.ends_with('\n'),
+        })
+    }
+
+    /// Reads and parses a pacman.conf.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfFile, ConfError> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path).map_err(|e| ConfError::Io(path.into(), e))?;
+        let lines = parse_lines(&s, path, true)?;
+        Ok(ConfFile {
+            path: Some(path.into()),
+            lines,
+            trailing_newline: s.is_empty() || s.ends_with('\n'),
+        })
+    }
+
+    /// The path the file was loaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the file to the given path.
+    ///
+    /// The file is written to a temporary file next to the destination and then renamed
+    /// over it so a partially written config is never observed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        let tmp = path.with_file_name(name);
+        fs::write(&tmp, self.to_string())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// The sections in the order they appear.
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|l| match l {
+            Line::Section { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The repository sections in the order they appear.
+    pub fn repos(&self) -> impl Iterator<Item = &str> {
+        self.sections().filter(|s| *s != OPTIONS)
+    }
+
+    /// The directives of a section as written in this file.
+    ///
+    /// Include directives are not expanded.
+    pub fn directives(&self, section: &str) -> Vec<Directive> {
+        match self.section_range(section) {
+            Some((start, end)) => self.lines[start..end]
+                .iter()
+                .filter_map(|l| match l {
+                    Line::Directive { key, value, .. } => Some(Directive {
+                        key: key.clone(),
+                        value: value.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The value of the first directive with the given key in the options section.
+    pub fn option(&self, key: &str) -> Option<Option<String>> {
+        self.directives(OPTIONS)
+            .into_iter()
+            .find(|d| d.key == key)
+            .map(|d| d.value)
+    }
+
+    /// Adds a repository section to the end of the file.
+    ///
+    /// Returns false if the repository already exists.
+    pub fn add_repo(&mut self, name: &str) -> bool {
+        if self.section_range(name).is_some() {
+            return false;
+        }
+
+        if self
+            .lines
+            .last()
+            .is_some_and(|l| !l.raw().trim().is_empty())
+        {
+            self.lines.push(Line::Other(String::new()));
+        }
+        self.lines.push(Line::section(name));
+        true
+    }
+
+    /// Removes a repository section and its directives.
+    ///
+    /// Comments following the last directive of the section are kept as they usually
+    /// belong to the next section.
+    ///
+    /// Returns false if the repository does not exist.
+    pub fn remove_repo(&mut self, name: &str) -> bool {
+        let Some((start, end)) = self.section_range(name) else {
+            return false;
+        };
+
+        let mut end = self.last_directive(start, end) + 1;
+        if self
+            .lines
+            .get(end)
+            .is_some_and(|l| l.raw().trim().is_empty())
+        {
+            end += 1;
+        }
+        self.lines.drain(start - 1..end);
+        true
+    }
+
+    /// Adds a server to a repository.
+    ///
+    /// Returns an error if the repository does not exist.
+    pub fn add_server(&mut self, repo: &str, url: &str) -> Result<(), ConfEditError> {
+        let (start, end) = self
+            .section_range(repo)
+            .ok_or_else(|| ConfEditError::NoSection(repo.to_string()))?;
+        let pos = self.last_directive(start, end) + 1;
+        self.lines.insert(pos, Line::directive("Server", Some(url)));
+        Ok(())
+    }
+
+    /// Removes a server from a repository.
+    ///
+    /// Returns false if the server was not found.
+    pub fn remove_server(&mut self, repo: &str, url: &str) -> bool {
+        self.remove(repo, |key, value| {
+            key == "Server" && value.map(|v| v.trim()) == Some(url)
+        })
+    }
+
+    /// Sets a directive in a section, replacing any existing directives with the same key.
+    ///
+    /// If the directive does not exist but a commented out version does, the comment is
+    /// replaced. Otherwise the directive is added after the last directive of the section.
+    /// The section is created if it does not exist.
+    pub fn set(&mut self, section: &str, key: &str, value: Option<&str>) {
+        let (start, end) = match self.section_range(section) {
+            Some(range) => range,
+            None if section == OPTIONS => {
+                self.lines.insert(0, Line::section(OPTIONS));
+                (1, 1)
+            }
+            None => {
+                self.add_repo(section);
+                (self.lines.len(), self.lines.len())
+            }
+        };
+
+        let existing = (start..end)
+            .find(|&i| matches!(&self.lines[i], Line::Directive { key: k, .. } if k == key));
+        let commented = (start..end).find(|&i| match &self.lines[i] {
+            Line::Other(raw) => commented_key(raw) == Some(key),
+            _ => false,
+        });
+
+        match existing.or(commented) {
+            Some(pos) => {
+                self.lines[pos] = Line::directive(key, value);
+                let mut i = end - 1;
+                while i > pos {
+                    if matches!(&self.lines[i], Line::Directive { key: k, .. } if k == key) {
+                        self.lines.remove(i);
+                    }
+                    i -= 1;
+                }
+            }
+            None => {
+                let pos = self.last_directive(start, end) + 1;
+                self.lines.insert(pos, Line::directive(key, value));
+            }
+        }
+    }
+
+    /// Comments out every directive with the given key in a section.
+    ///
+    /// Returns false if no directive was found.
+    pub fn unset(&mut self, section: &str, key: &str) -> bool {
+        let Some((start, end)) = self.section_range(section) else {
+            return false;
+        };
+
+        let mut found = false;
+        for line in &mut self.lines[start..end] {
+            if let Line::Directive { key: k, raw, .. } = line
+                && k == key
+            {
+                *line = Line::Other(format!("#{}", raw.trim_start()));
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Sets a directive in the options section.
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        self.set(OPTIONS, key, Some(value))
+    }
+
+    /// Enables or disables a boolean option such as `Color` in the options section.
+    pub fn set_flag(&mut self, key: &str, enabled: bool) {
+        if enabled {
+            self.set(OPTIONS, key, None)
+        } else {
+            self.unset(OPTIONS, key);
+        }
+    }
+
+    /// Expands includes and returns the options and repositories.
+    ///
+    /// Relative includes are resolved relative to the current directory, like pacman does.
+    pub fn resolve(&self) -> Result<ResolvedConf, ConfError> {
+        self.resolve_in(None)
+    }
+
+    /// Like [`ConfFile::resolve`] but absolute include paths are looked up under `sysroot`.
+    ///
+    /// This is useful for resolving the config of a system that is not the running one.
+    pub fn resolve_in(&self, sysroot: Option<&Path>) -> Result<ResolvedConf, ConfError> {
+        let mut conf = ResolvedConf::default();
+        let mut section = None;
+        let path = self
+            .path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("<string>"));
+        resolve_lines(&self.lines, &path, sysroot, &mut section, &mut conf, 0)?;
+        Ok(conf)
+    }
+
+    fn remove<F: Fn(&str, Option<&str>) -> bool>(&mut self, section: &str, f: F) -> bool {
+        let Some((start, end)) = self.section_range(section) else {
+            return false;
+        };
+
+        let before = self.lines.len();
+        let mut i = start;
+        let mut end = end;
+        while i < end {
+            match &self.lines[i] {
+                Line::Directive { key, value, .. } if f(key, value.as_deref()) => {
+                    self.lines.remove(i);
+                    end -= 1;
+                }
+                _ => i += 1,
+            }
+        }
+        before != self.lines.len()
+    }
+
+    /// The range of lines after the section header up to the next header.
+    fn section_range(&self, section: &str) -> Option<(usize, usize)> {
+        let start = self
+            .lines
+            .iter()
+            .position(|l| matches!(l, Line::Section { name, .. } if name == section))?
+            + 1;
+        let end = self.lines[start..]
+            .iter()
+            .position(|l| matches!(l, Line::Section { .. }))
+            .map(|p| p + start)
+            .unwrap_or(self.lines.len());
+        Some((start, end))
+    }
+
+    /// The index of the last directive in the range or the header before it.
+    fn last_directive(&self, start: usize, end: usize) -> usize {
+        (start..end)
+            .rev()
+            .find(|&i| matches!(self.lines[i], Line::Directive { .. }))
+            .unwrap_or(start - 1)
+    }
+}
+
+/// An error encountered while editing a [`ConfFile`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConfEditError {
+    /// The section does not exist.
+    NoSection(String),
+}
+
+impl fmt::Display for ConfEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfEditError::NoSection(s) => write!(f, "section [{}] does not exist", s),
+        }
+    }
+}
+
+impl std::error::Error for ConfEditError {}
+
+impl ResolvedConf {
+    /// Reads a pacman.conf and expands includes.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ResolvedConf, ConfError> {
+        ConfFile::load(path)?.resolve()
+    }
+
+    /// The value of the last directive with the given key in the options section.
+    ///
+    /// Like pacman, later directives override earlier ones.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        value(&self.options, key)
+    }
+
+    /// Returns true if a boolean option is set in the options section.
+    pub fn flag(&self, key: &str) -> bool {
+        self.options.iter().any(|d| d.key == key)
+    }
+
+    /// All the whitespace separated values of a list option such as `IgnorePkg`.
+    pub fn option_list(&self, key: &str) -> Vec<&str> {
+        list(&self.options, key)
+    }
+
+    /// Gets a repository by name.
+    pub fn repo(&self, name: &str) -> Option<&RepoSection> {
+        self.repos.iter().find(|r| r.name == name)
+    }
+}
+
+impl RepoSection {
+    /// The value of the last directive with the given key.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        value(&self.directives, key)
+    }
+
+    /// All the whitespace separated values of a list directive such as `SigLevel`.
+    pub fn option_list(&self, key: &str) -> Vec<&str> {
+        list(&self.directives, key)
+    }
+
+    /// The servers of the repository in order.
+    pub fn servers(&self) -> Vec<&str> {
+        list(&self.directives, "Server")
+    }
+
+    /// The cache servers of the repository in order.
+    pub fn cache_servers(&self) -> Vec<&str> {
+        list(&self.directives, "CacheServer")
+    }
+}
+
+fn value<'a>(directives: &'a [Directive], key: &str) -> Option<&'a str> {
+    directives
+        .iter()
+        .rev()
+        .find(|d| d.key == key)
+        .and_then(|d| d.value.as_deref())
+}
+
+fn list<'a>(directives: &'a [Directive], key: &str) -> Vec<&'a str> {
+    directives
+        .iter()
+        .filter(|d| d.key == key)
+        .filter_map(|d| d.value.as_deref())
+        .flat_map(|v| v.split_whitespace())
+        .collect()
+}
+
+fn commented_key(raw: &str) -> Option<&str> {
+    let line = raw.trim().strip_prefix('#')?.trim_start();
+    let key = line.split('=').next()?.trim();
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+fn parse_lines(s: &str, file: &Path, need_section: bool) -> Result<Vec<Line>, ConfError> {
+    let mut lines = Vec::new();
+    let mut in_section = !need_section;
+
+    for (n, raw) in s.lines().enumerate() {
+        let line = raw.split('#').next().unwrap().trim();
+        let err = |msg| ConfError::Syntax {
+            file: file.into(),
+            line: n + 1,
+            msg,
+        };
+
+        if line.is_empty() {
+            lines.push(Line::Other(raw.to_string()));
+        } else if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| err("section header is missing ']'"))?;
+            if name.is_empty() {
+                return Err(err("section name is empty"));
+            }
+            in_section = true;
+            lines.push(Line::Section {
+                name: name.to_string(),
+                raw: raw.to_string(),
+            });
+        } else {
+            if !in_section {
+                return Err(err("directive does not belong to a section"));
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim().to_string())),
+                None => (line, None),
+            };
+            if key.is_empty() {
+                return Err(err("directive has no key"));
+            }
+            if value.as_deref() == Some("") {
+                return Err(err("directive has an empty value"));
+            }
+            lines.push(Line::Directive {
+                key: key.to_string(),
+                value,
+                raw: raw.to_string(),
+            });
+        }
+    }
+
+    Ok(lines)
+}
+
+fn resolve_lines(
+    lines: &[Line],
+    file: &Path,
+    sysroot: Option<&Path>,
+    section: &mut Option<String>,
+    conf: &mut ResolvedConf,
+    depth: usize,
+) -> Result<(), ConfError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(ConfError::IncludeDepth(file.into()));
+    }
+
+    for line in lines {
+        match line {
+            Line::Other(_) => (),
+            Line::Section { name, .. } => {
+                if name != OPTIONS && conf.repo(name).is_none() {
+                    conf.repos.push(RepoSection {
+                        name: name.clone(),
+                        directives: Vec::new(),
+                    });
+                }
+                *section = Some(name.clone());
+            }
+            Line::Directive { key, value, .. } if key == "Include" => {
+                let pattern = value.as_deref().unwrap_or_default();
+                for path in glob(pattern, sysroot) {
+                    let s =
+                        fs::read_to_string(&path).map_err(|e| ConfError::Io(path.clone(), e))?;
+                    let lines = parse_lines(&s, &path, section.is_none())?;
+                    resolve_lines(&lines, &path, sysroot, section, conf, depth + 1)?;
+                }
+            }
+            Line::Directive { key, value, .. } => {
+                let directive = Directive {
+                    key: key.clone(),
+                    value: value.clone(),
+                };
+                match section.as_deref() {
+                    Some(OPTIONS) => conf.options.push(directive),
+                    Some(name) => {
+                        let repo = conf.repos.iter_mut().find(|r| r.name == name).unwrap();
+                        repo.directives.push(directive);
+                    }
+                    None => unreachable!("directives are checked to be in a section"),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands a glob pattern into the matching paths, sorted.
+///
+/// Like pacman, a pattern matching nothing expands to itself so reading it reports an error.
+fn glob(pattern: &str, sysroot: Option<&Path>) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let base = match sysroot {
+        Some(root) if path.is_absolute() => root.join(path.strip_prefix("/").unwrap()),
+        _ => path.to_path_buf(),
+    };
+
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![base];
+    }
+
+    let (mut dir, components) = match sysroot {
+        Some(root) if path.is_absolute() => (root.to_path_buf(), path.strip_prefix("/").unwrap()),
+        _ if path.is_absolute() => (PathBuf::from("/"), path.strip_prefix("/").unwrap()),
+        _ => (PathBuf::new(), path),
+    };
+
+    let mut candidates = Vec::new();
+    let components = components
+        .iter()
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    // descend literally until the first component with a wildcard
+    let mut i = 0;
+    while i < components.len() && !components[i].contains(['*', '?', '[']) {
+        dir.push(&components[i]);
+        i += 1;
+    }
+    glob_dir(&dir, &components[i..], &mut candidates);
+
+    if candidates.is_empty() {
+        return vec![base];
+    }
+    candidates.sort();
+    candidates
+}
+
+fn glob_dir(dir: &Path, components: &[String], out: &mut Vec<PathBuf>) {
+    let Some((first, rest)) = components.split_first() else {
+        out.push(dir.to_path_buf());
+        return;
+    };
+
+    let read = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let Ok(entries) = read else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') && !first.starts_with('.') {
+            continue;
+        }
+        if wildcard_match(first.as_bytes(), name.as_bytes()) {
+            let path = dir.join(&*name);
+            if rest.is_empty() || path.is_dir() {
+                glob_dir(&path, rest, out);
+            }
+        }
+    }
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| wildcard_match(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+        Some((b'[', rest)) => {
+            let Some(close) = rest.iter().skip(1).position(|&c| c == b']').map(|p| p + 1) else {
+                return name.first() == Some(&b'[') && wildcard_match(rest, &name[1..]);
+            };
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            let (negate, class) = match rest[..close].split_first() {
+                Some((b'!' | b'^', class)) => (true, class),
+                _ => (false, &rest[..close]),
+            };
+            let mut matched = false;
+            let mut j = 0;
+            while j < class.len() {
+                if j + 2 < class.len() && class[j + 1] == b'-' {
+                    matched |= class[j] <= c && c <= class[j + 2];
+                    j += 3;
+                } else {
+                    matched |= class[j] == c;
+                    j += 1;
+                }
+            }
+            matched != negate && wildcard_match(&rest[close + 1..], name_rest)
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && wildcard_match(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = "\
+#
+# /etc/pacman.conf
+#
+[options]
+#RootDir     = /
+HoldPkg     = pacman glibc
+Architecture = auto
+#Color
+IgnorePkg = foo
+IgnorePkg = bar baz
+ParallelDownloads = 5
+SigLevel    = Required DatabaseOptional
+
+# The testing repositories are disabled by default.
+#[core-testing]
+#Include = /etc/pacman.d/mirrorlist
+
+[core]
+Server = https://a.example.com/$repo/os/$arch
+Server = https://b.example.com/$repo/os/$arch
+
+#[extra-testing]
+#Include = /etc/pacman.d/mirrorlist
+
+[extra]
+SigLevel = PackageRequired
+Server = https://a.example.com/$repo/os/$arch
+";
+
+    fn tmpdir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("alpm-utils-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let conf = ConfFile::parse(CONF).unwrap();
+        assert_eq!(conf.to_string(), CONF);
+        assert_eq!(
+            conf.sections().collect::<Vec<_>>(),
+            ["options", "core", "extra"]
+        );
+        assert_eq!(conf.repos().collect::<Vec<_>>(), ["core", "extra"]);
+        assert_eq!(conf.option("Color"), None);
+        assert_eq!(conf.option("ParallelDownloads"), Some(Some("5".into())));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let conf = ConfFile::parse(CONF).unwrap().resolve().unwrap();
+        assert_eq!(conf.option("Architecture"), Some("auto"));
+        assert_eq!(conf.option_list("IgnorePkg"), ["foo", "bar", "baz"]);
+        assert!(!conf.flag("Color"));
+
+        let core = conf.repo("core").unwrap();
+        assert_eq!(
+            core.servers(),
+            [
+                "https://a.example.com/$repo/os/$arch",
+                "https://b.example.com/$repo/os/$arch"
+            ]
+        );
+        assert_eq!(
+            conf.repo("extra").unwrap().option_list("SigLevel"),
+            ["PackageRequired"]
+        );
+        assert!(conf.repo("core-testing").is_none());
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut conf = ConfFile::parse(CONF).unwrap();
+
+        conf.set_flag("Color", true);
+        conf.set_option("ParallelDownloads", "10");
+        conf.set_option("IgnorePkg", "qux");
+        assert!(conf.remove_server("core", "https://b.example.com/$repo/os/$arch"));
+        assert!(!conf.remove_server("core", "https://c.example.com"));
+        conf.add_server("extra", "https://c.example.com/$repo/os/$arch")
+            .unwrap();
+        assert!(conf.add_server("nope", "https://c.example.com").is_err());
+        assert!(conf.remove_repo("core"));
+        assert!(!conf.remove_repo("core"));
+        assert!(conf.add_repo("custom"));
+        assert!(!conf.add_repo("custom"));
+        conf.add_server("custom", "file:///srv/custom").unwrap();
+        conf.set("custom", "SigLevel", Some("Optional TrustAll"));
+
+        let expected = "\
+#
+# /etc/pacman.conf
+#
+[options]
+#RootDir     = /
+HoldPkg     = pacman glibc
+Architecture = auto
+Color
+IgnorePkg = qux
+ParallelDownloads = 10
+SigLevel    = Required DatabaseOptional
+
+# The testing repositories are disabled by default.
+#[core-testing]
+#Include = /etc/pacman.d/mirrorlist
+
+#[extra-testing]
+#Include = /etc/pacman.d/mirrorlist
+
+[extra]
+SigLevel = PackageRequired
+Server = https://a.example.com/$repo/os/$arch
+Server = https://c.example.com/$repo/os/$arch
+
+[custom]
+Server = file:///srv/custom
+SigLevel = Optional TrustAll
+";
+        assert_eq!(conf.to_string(), expected);
+
+        conf.set_flag("Color", false);
+        assert!(conf.to_string().contains("\n#Color\n"));
+        assert!(conf.unset(OPTIONS, "HoldPkg"));
+        assert!(conf.to_string().contains("\n#HoldPkg     = pacman glibc\n"));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = tmpdir("include");
+        fs::create_dir(dir.join("conf.d")).unwrap();
+        fs::write(
+            dir.join("mirrorlist"),
+            "# mirrors\nServer = https://m1.example.com/$repo/os/$arch\n",
+        )
+        .unwrap();
+        fs::write(dir.join("conf.d/10-a.conf"), "[a]\nServer = https://a\n").unwrap();
+        fs::write(dir.join("conf.d/20-b.conf"), "[b]\nServer = https://b\n").unwrap();
+        fs::write(dir.join("conf.d/ignored"), "[c]\nServer = https://c\n").unwrap();
+
+        let s = format!(
+            "[options]\nInclude = {0}/conf.d/*.conf\n[core]\nInclude = {0}/mirrorlist\n",
+            dir.display()
+        );
+        let conf = ConfFile::parse(&s).unwrap().resolve().unwrap();
+        let repos = conf.repos.iter().map(|r| &*r.name).collect::<Vec<_>>();
+        assert_eq!(repos, ["a", "b", "core"]);
+        assert_eq!(
+            conf.repo("core").unwrap().servers(),
+            ["https://m1.example.com/$repo/os/$arch"]
+        );
+
+        let s = "[options]\nInclude = /conf.d/[0-9]*-b.conf\n";
+        let conf = ConfFile::parse(s).unwrap().resolve_in(Some(&dir)).unwrap();
+        assert_eq!(conf.repo("b").unwrap().servers(), ["https://b"]);
+
+        let s = "[options]\nInclude = /does/not/exist\n";
+        let err = ConfFile::parse(s).unwrap().resolve_in(Some(&dir));
+        assert!(matches!(err, Err(ConfError::Io(..))));
+
+        let path = dir.join("pacman.conf");
+        fs::write(&path, CONF).unwrap();
+        let mut conf = ConfFile::load(&path).unwrap();
+        conf.set_flag("Color", true);
+        conf.save(&path).unwrap();
+        let conf = ResolvedConf::load(&path).unwrap();
+        assert!(conf.flag("Color"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            ConfFile::parse("Color\n"),
+            Err(ConfError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            ConfFile::parse("[options]\n[core\n"),
+            Err(ConfError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            ConfFile::parse("[options]\n = foo\n"),
+            Err(ConfError::Syntax { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert!(wildcard_match(b"*.conf", b"a.conf"));
+        assert!(!wildcard_match(b"*.conf", b"a.confx"));
+        assert!(wildcard_match(b"?-[a-c]", b"1-b"));
+        assert!(!wildcard_match(b"?-[!a-c]", b"1-b"));
+    }
+}
@@ -5,17 +5,63 @@
 #![warn(missing_docs)]
 #![allow(mismatched_lifetime_syntaxes)]
 
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "alpm")]
+mod cache;
 #[cfg(feature = "conf")]
 mod conf;
+mod conf_file;
 #[cfg(feature = "alpm")]
 mod db;
 /// Utils for dependency checking.
 #[cfg(feature = "alpm")]
 pub mod depends;
+mod mirrorlist;
+#[cfg(feature = "alpm")]
+mod progress;
+#[cfg(feature = "alpm")]
+mod remove;
+#[cfg(feature = "archive")]
+mod repo;
+#[cfg(feature = "alpm")]
+mod signers;
+#[cfg(feature = "alpm")]
+mod spec;
+#[cfg(feature = "archive")]
+mod sync_archive;
 mod target;
+#[cfg(any(feature = "alpm", test))]
+mod tempdir;
+#[cfg(all(feature = "alpm", feature = "archive"))]
+mod test_root;
+#[cfg(feature = "alpm")]
+mod transcript;
 
+#[cfg(feature = "archive")]
+pub use crate::archive::*;
+#[cfg(feature = "alpm")]
+pub use crate::cache::*;
 #[cfg(feature = "conf")]
 pub use crate::conf::*;
+pub use crate::conf_file::*;
 #[cfg(feature = "alpm")]
 pub use crate::db::*;
+pub use crate::mirrorlist::*;
+#[cfg(feature = "alpm")]
+pub use crate::progress::*;
+#[cfg(feature = "alpm")]
+pub use crate::remove::*;
+#[cfg(feature = "archive")]
+pub use crate::repo::*;
+#[cfg(feature = "alpm")]
+pub use crate::signers::*;
+#[cfg(feature = "alpm")]
+pub use crate::spec::*;
+#[cfg(feature = "archive")]
+pub use crate::sync_archive::*;
 pub use crate::target::*;
+#[cfg(all(feature = "alpm", feature = "archive"))]
+pub use crate::test_root::*;
+#[cfg(feature = "alpm")]
+pub use crate::transcript::*;
@@ -0,0 +1,515 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "alpm")]
+use alpm::Alpm;
+
+/// URL schemes libalpm can download from.
+pub const SUPPORTED_SCHEMES: &[&str] = &["http", "https", "ftp", "ftps", "file"];
+
+/// An error with a mirror URL.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MirrorError {
+    /// The URL is not of the form `scheme://...`.
+    InvalidUrl(String),
+    /// The URL scheme is not supported by libalpm.
+    UnsupportedScheme(String),
+    /// The URL contains a variable other than `$repo` and `$arch`.
+    UnknownVariable(String),
+    /// The URL uses `$arch` but no architecture is configured.
+    NoArchitecture(String),
+}
+
+impl fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MirrorError::InvalidUrl(url) => write!(f, "invalid server url: {}", url),
+            MirrorError::UnsupportedScheme(url) => write!(f, "unsupported url scheme: {}", url),
+            MirrorError::UnknownVariable(url) => write!(f, "unknown variable in url: {}", url),
+            MirrorError::NoArchitecture(url) => {
+                write!(f, "url uses $arch but no architecture is set: {}", url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MirrorError {}
+
+/// A `Server =` line of a mirrorlist.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mirror {
+    /// The unexpanded server URL.
+    pub url: String,
+    /// False if the line is commented out.
+    pub enabled: bool,
+    /// The closest `##` comment before the server, usually the country.
+    pub comment: Option<String>,
+}
+
+impl Mirror {
+    /// Expands the server for the given repo and architecture.
+    pub fn expand(&self, repo: &str, arch: Option<&str>) -> Result<String, MirrorError> {
+        expand_server(&self.url, repo, arch)
+    }
+}
+
+/// A parsed mirrorlist such as `/etc/pacman.d/mirrorlist`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mirrorlist {
+    /// Every server in the order they appear, including commented out ones.
+    pub mirrors: Vec<Mirror>,
+}
+
+impl Mirrorlist {
+    /// Parses a mirrorlist from a string.
+    ///
+    /// Lines other than active or commented `Server =` lines and `##` comments are ignored.
+    pub fn parse(s: &str) -> Mirrorlist {
+        let mut mirrors = Vec::new();
+        let mut comment = None;
+
+        for line in s.lines() {
+            let line = line.trim();
+
+            if let Some(text) = line.strip_prefix("##") {
+                let text = text.trim();
+                if !text.is_empty() {
+                    comment = Some(text.to_string());
+                }
+                continue;
+            }
+
+            let (enabled, line) = match line.strip_prefix('#') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if key.trim() != "Server" {
+                continue;
+            }
+            let url = value.split('#').next().unwrap().trim();
+            if url.is_empty() {
+                continue;
+            }
+
+            mirrors.push(Mirror {
+                url: url.to_string(),
+                enabled,
+                comment: comment.clone(),
+            });
+        }
+
+        Mirrorlist { mirrors }
+    }
+
+    /// Reads and parses a mirrorlist.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Mirrorlist> {
+        fs::read_to_string(path).map(|s| Mirrorlist::parse(&s))
+    }
+
+    /// The mirrors that are not commented out.
+    pub fn enabled(&self) -> impl Iterator<Item = &Mirror> {
+        self.mirrors.iter().filter(|m| m.enabled)
+    }
+
+    /// The enabled servers expanded for the given repo and architecture.
+    ///
+    /// Every server is validated with [`validate_server`].
+    pub fn servers(&self, repo: &str, arch: Option<&str>) -> Result<Vec<String>, MirrorError> {
+        self.enabled()
+            .map(|m| {
+                let url = m.expand(repo, arch)?;
+                validate_server(&url)?;
+                Ok(url)
+            })
+            .collect()
+    }
+
+    /// The enabled servers expanded for the given repo using the first architecture of the
+    /// handle, like pacman does.
+    #[cfg(feature = "alpm")]
+    pub fn servers_for(&self, alpm: &Alpm, repo: &str) -> Result<Vec<String>, MirrorError> {
+        self.servers(repo, alpm.architectures().first())
+    }
+}
+
+/// Expands `$repo` and `$arch` in a server URL.
+pub fn expand_server(url: &str, repo: &str, arch: Option<&str>) -> Result<String, MirrorError> {
+    let mut out = String::with_capacity(url.len());
+    let mut rest = url;
+
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(r) = rest.strip_prefix("$repo") {
+            out.push_str(repo);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("$arch") {
+            let arch = arch.ok_or_else(|| MirrorError::NoArchitecture(url.to_string()))?;
+            out.push_str(arch);
+            rest = r;
+        } else {
+            return Err(MirrorError::UnknownVariable(url.to_string()));
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Checks a server URL can be used by libalpm.
+///
+/// The URL must be of the form `scheme://...` with a scheme from [`SUPPORTED_SCHEMES`] and
+/// must not contain unexpanded variables.
+pub fn validate_server(url: &str) -> Result<(), MirrorError> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(MirrorError::InvalidUrl(url.to_string()));
+    };
+
+    if rest.is_empty() || rest.contains(char::is_whitespace) {
+        return Err(MirrorError::InvalidUrl(url.to_string()));
+    }
+    if !SUPPORTED_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) {
+        return Err(MirrorError::UnsupportedScheme(url.to_string()));
+    }
+    if url.contains('$') {
+        return Err(MirrorError::UnknownVariable(url.to_string()));
+    }
+    if !scheme.eq_ignore_ascii_case("file") && rest.starts_with('/') {
+        return Err(MirrorError::InvalidUrl(url.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Finds out how fresh a file on a mirror is.
+pub trait FreshnessProbe {
+    /// Returns the modification time of the file at the given URL.
+    fn probe(&self, url: &str) -> io::Result<SystemTime>;
+}
+
+impl<F: Fn(&str) -> io::Result<SystemTime>> FreshnessProbe for F {
+    fn probe(&self, url: &str) -> io::Result<SystemTime> {
+        self(url)
+    }
+}
+
+/// A [`FreshnessProbe`] for `http://` and `file://` URLs.
+///
+/// HTTP servers are sent a `HEAD` request and the `Last-Modified` header is used.
+/// Other schemes, including `https://`, are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HttpProbe {
+    /// How long to wait for the server to connect and respond.
+    pub timeout: Duration,
+}
+
+impl Default for HttpProbe {
+    fn default() -> Self {
+        HttpProbe {
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl FreshnessProbe for HttpProbe {
+    fn probe(&self, url: &str) -> io::Result<SystemTime> {
+        let (scheme, rest) = url.split_once("://").unwrap_or_default();
+        if scheme.eq_ignore_ascii_case("file") {
+            return fs::metadata(rest)?.modified();
+        }
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported url: {}", url),
+            ));
+        }
+
+        let (host, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        let addr = socket_addr(host)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, host.to_string()))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "HEAD {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: alpm-utils\r\nConnection: close\r\n\r\n",
+            path, host
+        )?;
+
+        let mut response = Vec::new();
+        stream.take(64 * 1024).read_to_end(&mut response)?;
+        let response = String::from_utf8_lossy(&response);
+        let mut lines = response.lines();
+
+        let status = lines.next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(io::Error::other(format!("{}: {}", url, status)));
+        }
+
+        lines
+            .take_while(|l| !l.is_empty())
+            .filter_map(|l| l.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("last-modified"))
+            .and_then(|(_, v)| parse_http_date(v.trim()))
+            .ok_or_else(|| io::Error::other(format!("{}: no valid Last-Modified header", url)))
+    }
+}
+
+// adds the default port to host unless it has one. a bracketed IPv6 address only has a port
+// if a ':' follows the ']'.
+fn socket_addr(host: &str) -> String {
+    let has_port = match host.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once(']')
+            .is_some_and(|(_, port)| port.starts_with(':')),
+        None => host.contains(':'),
+    };
+    match has_port {
+        true => host.to_string(),
+        false => format!("{}:80", host),
+    }
+}
+
+/// The result of probing a mirror with [`rank_mirrors`].
+#[derive(Debug)]
+pub struct RankedMirror<'a> {
+    /// The mirror that was probed.
+    pub mirror: &'a Mirror,
+    /// The modification time of the repo database, or the error probing it.
+    pub last_modified: io::Result<SystemTime>,
+    /// How long the probe took.
+    pub latency: Duration,
+}
+
+/// Ranks the enabled mirrors of a mirrorlist by how recently they synced.
+///
+/// Each mirror is probed for the `<repo>.db` file of the given repo. Mirrors with the most
+/// recent database come first, ties are broken by latency and mirrors that failed the probe
+/// come last.
+pub fn rank_mirrors<'a, P: FreshnessProbe>(
+    list: &'a Mirrorlist,
+    repo: &str,
+    arch: Option<&str>,
+    probe: &P,
+) -> Vec<RankedMirror<'a>> {
+    let mut ranked = list
+        .enabled()
+        .map(|mirror| {
+            let start = Instant::now();
+            let last_modified = mirror
+                .expand(repo, arch)
+                .and_then(|url| validate_server(&url).map(|_| url))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+                .and_then(|url| probe.probe(&format!("{}/{}.db", url.trim_end_matches('/'), repo)));
+
+            RankedMirror {
+                mirror,
+                last_modified,
+                latency: start.elapsed(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|a, b| match (&a.last_modified, &b.last_modified) {
+        (Ok(a_time), Ok(b_time)) => b_time.cmp(a_time).then(a.latency.cmp(&b.latency)),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+
+    ranked
+}
+
+/// Parses an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(s: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = s.split_once(", ")?.1.split_whitespace();
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<i64>().ok());
+    let (h, m, sec) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + h * 3600 + m * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const MIRRORLIST: &str = "\
+##
+## Arch Linux repository mirrorlist
+##
+
+## Germany
+#Server = https://de.example.com/$repo/os/$arch
+Server = http://de2.example.com/archlinux/$repo/os/$arch
+
+## Worldwide
+Server = https://geo.example.com/$repo/os/$arch # fast
+IgnoreMe = http://nope
+";
+
+    #[test]
+    fn test_parse() {
+        let list = Mirrorlist::parse(MIRRORLIST);
+        assert_eq!(list.mirrors.len(), 3);
+        assert!(!list.mirrors[0].enabled);
+        assert_eq!(list.mirrors[0].comment.as_deref(), Some("Germany"));
+        assert_eq!(list.mirrors[2].comment.as_deref(), Some("Worldwide"));
+        assert_eq!(
+            list.mirrors[2].url,
+            "https://geo.example.com/$repo/os/$arch"
+        );
+
+        let servers = list.servers("core", Some("x86_64")).unwrap();
+        assert_eq!(
+            servers,
+            [
+                "http://de2.example.com/archlinux/core/os/x86_64",
+                "https://geo.example.com/core/os/x86_64"
+            ]
+        );
+        assert_eq!(
+            list.servers("core", None),
+            Err(MirrorError::NoArchitecture(
+                "http://de2.example.com/archlinux/$repo/os/$arch".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_server("https://example.com/core").is_ok());
+        assert!(validate_server("file:///srv/repo").is_ok());
+        assert!(validate_server("FILE:///srv/repo").is_ok());
+        assert!(matches!(
+            validate_server("example.com/core"),
+            Err(MirrorError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            validate_server("rsync://example.com/core"),
+            Err(MirrorError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            validate_server("http:///core"),
+            Err(MirrorError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            expand_server("http://example.com/$foo", "core", None),
+            Err(MirrorError::UnknownVariable(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(784111777))
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn test_socket_addr() {
+        assert_eq!(socket_addr("example.com"), "example.com:80");
+        assert_eq!(socket_addr("example.com:8080"), "example.com:8080");
+        assert_eq!(socket_addr("[::1]"), "[::1]:80");
+        assert_eq!(socket_addr("[2001:db8::1]"), "[2001:db8::1]:80");
+        assert_eq!(socket_addr("[2001:db8::1]:8080"), "[2001:db8::1]:8080");
+    }
+
+    #[test]
+    fn test_probe_file() {
+        let dir = std::env::temp_dir();
+        let mtime = fs::metadata(&dir).unwrap().modified().unwrap();
+        let probe = HttpProbe::default();
+        let url = format!("FILE://{}", dir.display());
+        assert_eq!(probe.probe(&url).unwrap(), mtime);
+        let url = format!("File://{}", dir.display());
+        assert_eq!(probe.probe(&url).unwrap(), mtime);
+        assert!(probe.probe("https://example.com").is_err());
+    }
+
+    #[test]
+    fn test_rank() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let response = if request.starts_with("HEAD /old/core/os/x86_64/core.db ") {
+                    "HTTP/1.1 200 OK\r\nLast-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"
+                } else if request.starts_with("HEAD /new/core/os/x86_64/core.db ") {
+                    "HTTP/1.1 200 OK\r\nlast-modified: Mon, 07 Nov 1994 08:49:37 GMT\r\n\r\n"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\n\r\n"
+                };
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let list = Mirrorlist::parse(&format!(
+            "Server = http://{0}/old/$repo/os/$arch\n\
+             Server = http://{0}/missing/$repo/os/$arch\n\
+             Server = http://{0}/new/$repo/os/$arch\n\
+             Server = bogus\n",
+            addr
+        ));
+        let ranked = rank_mirrors(&list, "core", Some("x86_64"), &HttpProbe::default());
+        server.join().unwrap();
+
+        let urls = ranked.iter().map(|r| &*r.mirror.url).collect::<Vec<_>>();
+        assert_eq!(urls[0], format!("http://{}/new/$repo/os/$arch", addr));
+        assert_eq!(urls[1], format!("http://{}/old/$repo/os/$arch", addr));
+        assert!(ranked[2].last_modified.is_err());
+        assert!(ranked[3].last_modified.is_err());
+    }
+}
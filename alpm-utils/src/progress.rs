@@ -0,0 +1,414 @@
+use alpm::{AnyDownloadEvent, AnyEvent, DownloadEvent, DownloadResult, Event};
+
+use std::time::{Duration, Instant};
+
+/// The state of a single file tracked by a [`DownloadTracker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileState {
+    /// The file is being downloaded.
+    Downloading,
+    /// The file was downloaded.
+    Completed,
+    /// The file was already up to date.
+    UpToDate,
+    /// The download failed.
+    Failed,
+}
+
+/// The progress of a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileProgress {
+    /// The name of the file as given to the download callback.
+    pub filename: String,
+    /// Whether errors downloading this file are ignored, such as for signatures.
+    pub optional: bool,
+    /// The state of the download.
+    pub state: FileState,
+    /// Bytes downloaded so far.
+    pub downloaded: i64,
+    /// Size of the file, 0 if not known yet.
+    pub total: i64,
+    /// Average download rate in bytes per second.
+    pub rate: f64,
+    /// Estimated time until the file is downloaded.
+    pub eta: Option<Duration>,
+    /// How often the download was retried.
+    pub retries: u32,
+    /// Time since the download started.
+    pub elapsed: Duration,
+}
+
+/// The progress of all files tracked by a [`DownloadTracker`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadSnapshot {
+    /// Every file in the order the downloads started.
+    pub files: Vec<FileProgress>,
+    /// Bytes downloaded so far over all files.
+    pub downloaded: i64,
+    /// The total number of bytes to download.
+    ///
+    /// This is the size given by the package retrieve event if there was one, otherwise
+    /// the sum of the known file sizes.
+    pub total: i64,
+    /// Average download rate in bytes per second.
+    pub rate: f64,
+    /// Estimated time until every file is downloaded.
+    pub eta: Option<Duration>,
+    /// The number of files expected to be downloaded, if known.
+    pub expected_files: Option<usize>,
+    /// Files that were downloaded.
+    pub completed: usize,
+    /// Files that were already up to date.
+    pub up_to_date: usize,
+    /// Non optional files that failed to download.
+    pub failed: usize,
+    /// Retries over all files.
+    pub retries: u32,
+    /// Time since the first download started.
+    pub elapsed: Duration,
+    /// Whether libalpm reported the package retrieval as finished.
+    pub done: bool,
+}
+
+impl DownloadSnapshot {
+    /// Download progress over all files as a percentage.
+    pub fn percent(&self) -> u8 {
+        if self.total <= 0 {
+            return if self.done { 100 } else { 0 };
+        }
+        (self.downloaded.clamp(0, self.total) * 100 / self.total) as u8
+    }
+}
+
+#[derive(Debug, Clone)]
+struct File {
+    filename: String,
+    optional: bool,
+    state: FileState,
+    downloaded: i64,
+    total: i64,
+    retries: u32,
+    started: Instant,
+    finished: Option<Instant>,
+}
+
+/// Keeps track of the state of parallel downloads.
+///
+/// Feed it every download event and the package retrieve events. The totals are
+/// accounted like pacman's total progress bar: up to date and failed files do not count
+/// towards the total and a retry that does not resume discards the bytes downloaded so
+/// far.
+///
+/// ```no_run
+/// use alpm::Alpm;
+/// use alpm_utils::DownloadTracker;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// let alpm = Alpm::new("/", "/var/lib/pacman").unwrap();
+/// let tracker = Rc::new(RefCell::new(DownloadTracker::new()));
+///
+/// alpm.set_dl_cb(tracker.clone(), |filename, event, tracker| {
+///     let mut tracker = tracker.borrow_mut();
+///     tracker.on_dl(filename, event);
+///     let snapshot = tracker.snapshot();
+///     println!("{}% {:.0} B/s", snapshot.percent(), snapshot.rate);
+/// });
+/// alpm.set_event_cb(tracker, |event, tracker| tracker.borrow_mut().on_event(&event));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DownloadTracker {
+    files: Vec<File>,
+    started: Option<Instant>,
+    expected_files: Option<usize>,
+    expected_total: Option<i64>,
+    done: bool,
+}
+
+impl DownloadTracker {
+    /// Creates an empty tracker.
+    pub fn new() -> DownloadTracker {
+        DownloadTracker::default()
+    }
+
+    /// Forgets every file.
+    pub fn reset(&mut self) {
+        *self = DownloadTracker::default();
+    }
+
+    /// Handles an event from the download callback.
+    pub fn on_dl(&mut self, filename: &str, event: AnyDownloadEvent) {
+        self.on_download(filename, event.event());
+    }
+
+    /// Handles an event from the event callback.
+    ///
+    /// Only package retrieve events are used, the rest are ignored.
+    pub fn on_event(&mut self, event: &AnyEvent) {
+        match event.event() {
+            Event::PkgRetrieveStart(e) => self.on_retrieve_start(e.num(), e.total_size()),
+            Event::PkgRetrieveDone(_) | Event::PkgRetrieveFailed(_) => self.done = true,
+            _ => (),
+        }
+    }
+
+    /// Starts tracking a new batch of `num` files of `total_size` bytes.
+    ///
+    /// A `total_size` of 0 means the size is not known.
+    pub fn on_retrieve_start(&mut self, num: usize, total_size: i64) {
+        self.reset();
+        self.expected_files = Some(num);
+        self.expected_total = Some(total_size).filter(|&size| size > 0);
+    }
+
+    /// Handles a download event.
+    pub fn on_download(&mut self, filename: &str, event: DownloadEvent) {
+        self.on_download_at(filename, event, Instant::now())
+    }
+
+    /// Handles a download event that happened at `now`.
+    pub fn on_download_at(&mut self, filename: &str, event: DownloadEvent, now: Instant) {
+        self.started.get_or_insert(now);
+
+        if let DownloadEvent::Init(init) = event {
+            let file = File {
+                filename: filename.to_string(),
+                optional: init.optional,
+                state: FileState::Downloading,
+                downloaded: 0,
+                total: 0,
+                retries: 0,
+                started: now,
+                finished: None,
+            };
+            match self.files.iter_mut().find(|f| f.filename == filename) {
+                Some(f) => *f = file,
+                None => self.files.push(file),
+            }
+            return;
+        }
+
+        let Some(file) = self.files.iter_mut().find(|f| f.filename == filename) else {
+            return;
+        };
+
+        match event {
+            DownloadEvent::Init(_) => unreachable!(),
+            DownloadEvent::Progress(progress) => {
+                file.downloaded = progress.downloaded;
+                if progress.total > 0 {
+                    file.total = progress.total;
+                }
+            }
+            DownloadEvent::Retry(retry) => {
+                file.retries += 1;
+                if !retry.resume {
+                    file.downloaded = 0;
+                    file.started = now;
+                }
+            }
+            DownloadEvent::Completed(completed) => {
+                file.finished = Some(now);
+                file.state = match completed.result {
+                    DownloadResult::Success => FileState::Completed,
+                    DownloadResult::UpToDate => FileState::UpToDate,
+                    DownloadResult::Failed => FileState::Failed,
+                };
+                if file.state == FileState::Completed {
+                    if completed.total > 0 {
+                        file.total = completed.total;
+                    }
+                    file.downloaded = file.total.max(file.downloaded);
+                }
+            }
+        }
+    }
+
+    /// The current state of the downloads.
+    pub fn snapshot(&self) -> DownloadSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    /// The state of the downloads at `now`.
+    pub fn snapshot_at(&self, now: Instant) -> DownloadSnapshot {
+        let files = self
+            .files
+            .iter()
+            .map(|f| {
+                let elapsed = f
+                    .finished
+                    .unwrap_or(now)
+                    .saturating_duration_since(f.started);
+                let rate = rate(f.downloaded, elapsed);
+                FileProgress {
+                    filename: f.filename.clone(),
+                    optional: f.optional,
+                    state: f.state,
+                    downloaded: f.downloaded,
+                    total: f.total,
+                    rate,
+                    eta: match f.state {
+                        FileState::Downloading => eta(f.total - f.downloaded, rate),
+                        _ => None,
+                    },
+                    retries: f.retries,
+                    elapsed,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let counted = || {
+            self.files
+                .iter()
+                .filter(|f| matches!(f.state, FileState::Downloading | FileState::Completed))
+        };
+        let downloaded = counted().map(|f| f.downloaded).sum::<i64>();
+        let total = self
+            .expected_total
+            .unwrap_or_else(|| counted().map(|f| f.total).sum());
+
+        let count = |state| self.files.iter().filter(|f| f.state == state).count();
+        let failed = self
+            .files
+            .iter()
+            .filter(|f| f.state == FileState::Failed && !f.optional)
+            .count();
+
+        let last = self
+            .files
+            .iter()
+            .map(|f| f.finished)
+            .collect::<Option<Vec<_>>>();
+        let end = match last {
+            Some(last) if !last.is_empty() => last.into_iter().max().unwrap_or(now),
+            _ => now,
+        };
+        let elapsed = self
+            .started
+            .map(|s| end.saturating_duration_since(s))
+            .unwrap_or_default();
+        let rate = rate(downloaded, elapsed);
+        let downloading = self.files.iter().any(|f| f.state == FileState::Downloading);
+
+        DownloadSnapshot {
+            files,
+            downloaded,
+            total,
+            rate,
+            eta: if downloading {
+                eta(total - downloaded, rate)
+            } else {
+                None
+            },
+            expected_files: self.expected_files,
+            completed: count(FileState::Completed),
+            up_to_date: count(FileState::UpToDate),
+            failed,
+            retries: self.files.iter().map(|f| f.retries).sum(),
+            elapsed,
+            done: self.done,
+        }
+    }
+}
+
+fn rate(bytes: i64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { bytes as f64 / secs } else { 0.0 }
+}
+
+fn eta(remaining: i64, rate: f64) -> Option<Duration> {
+    if remaining > 0 && rate > 0.0 {
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alpm::{
+        DownloadEventCompleted, DownloadEventInit, DownloadEventProgress, DownloadEventRetry,
+    };
+
+    fn init(optional: bool) -> DownloadEvent {
+        DownloadEvent::Init(DownloadEventInit { optional })
+    }
+
+    fn progress(downloaded: i64, total: i64) -> DownloadEvent {
+        DownloadEvent::Progress(DownloadEventProgress { downloaded, total })
+    }
+
+    fn completed(total: i64, result: DownloadResult) -> DownloadEvent {
+        DownloadEvent::Completed(DownloadEventCompleted { total, result })
+    }
+
+    #[test]
+    fn test_download_tracker() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut tracker = DownloadTracker::new();
+
+        tracker.on_retrieve_start(3, 300);
+        tracker.on_download_at("a", init(false), at(0));
+        tracker.on_download_at("b", init(false), at(0));
+        tracker.on_download_at("b.sig", init(true), at(0));
+        tracker.on_download_at("c", init(false), at(0));
+        tracker.on_download_at("a", progress(50, 100), at(1));
+        tracker.on_download_at("b", progress(100, 200), at(1));
+        tracker.on_download_at("c", completed(0, DownloadResult::UpToDate), at(1));
+
+        let snapshot = tracker.snapshot_at(at(2));
+        assert_eq!(snapshot.downloaded, 150);
+        assert_eq!(snapshot.total, 300);
+        assert_eq!(snapshot.rate, 75.0);
+        assert_eq!(snapshot.eta, Some(Duration::from_secs(2)));
+        assert_eq!(snapshot.up_to_date, 1);
+        assert_eq!(snapshot.percent(), 50);
+        assert_eq!(snapshot.files[0].rate, 25.0);
+        assert_eq!(snapshot.files[0].eta, Some(Duration::from_secs(2)));
+
+        tracker.on_download_at(
+            "a",
+            DownloadEvent::Retry(DownloadEventRetry { resume: false }),
+            at(2),
+        );
+        tracker.on_download_at("b.sig", completed(0, DownloadResult::Failed), at(2));
+        let snapshot = tracker.snapshot_at(at(2));
+        assert_eq!(snapshot.downloaded, 100);
+        assert_eq!(snapshot.retries, 1);
+        assert_eq!(snapshot.failed, 0);
+
+        tracker.on_download_at("a", completed(100, DownloadResult::Success), at(3));
+        tracker.on_download_at("b", completed(200, DownloadResult::Success), at(4));
+        let snapshot = tracker.snapshot_at(at(10));
+        assert_eq!(snapshot.downloaded, 300);
+        assert_eq!(snapshot.completed, 2);
+        assert_eq!(snapshot.elapsed, Duration::from_secs(4));
+        assert_eq!(snapshot.eta, None);
+        assert_eq!(snapshot.percent(), 100);
+        assert_eq!(snapshot.expected_files, Some(3));
+    }
+
+    #[test]
+    fn test_download_tracker_unknown_total() {
+        let start = Instant::now();
+        let mut tracker = DownloadTracker::new();
+
+        tracker.on_download_at("core.db", init(false), start);
+        tracker.on_download_at("extra.db", init(false), start);
+        tracker.on_download_at("core.db", progress(10, 40), start);
+        tracker.on_download_at("extra.db", completed(0, DownloadResult::Failed), start);
+
+        let snapshot = tracker.snapshot_at(start);
+        assert_eq!(snapshot.total, 40);
+        assert_eq!(snapshot.downloaded, 10);
+        assert_eq!(snapshot.failed, 1);
+        assert_eq!(snapshot.rate, 0.0);
+        assert_eq!(snapshot.eta, None);
+        assert_eq!(snapshot.expected_files, None);
+
+        tracker.on_download_at("unknown", progress(10, 10), start);
+        assert_eq!(tracker.snapshot_at(start).files.len(), 2);
+    }
+}
@@ -0,0 +1,227 @@
+use alpm::{Alpm, AlpmList, AlpmListMut, DependMissing, Package, PackageReason, Pkg, TransFlag};
+
+/// The result of simulating the removal of packages with [`removal_impact`].
+#[derive(Debug)]
+pub struct RemovalImpact<'a> {
+    /// Every package that would be removed, including the requested targets.
+    pub remove: Vec<&'a Package>,
+    /// Packages that would additionally be removed because of [`TransFlag::CASCADE`].
+    pub cascade: Vec<&'a Package>,
+    /// Packages that would additionally be removed because of [`TransFlag::RECURSE`].
+    pub recurse: Vec<&'a Package>,
+    /// Targets that would be kept because of [`TransFlag::UNNEEDED`].
+    pub kept: Vec<&'a Package>,
+    /// Dependencies of the remaining installed packages that would be left unsatisfied.
+    pub unsatisfied: AlpmListMut<DependMissing>,
+    /// Installed packages that would become orphans.
+    pub orphans: Vec<&'a Package>,
+}
+
+impl RemovalImpact<'_> {
+    /// Returns true if the removal would leave every remaining dependency satisfied.
+    pub fn is_safe(&self) -> bool {
+        self.unsatisfied.is_empty()
+    }
+}
+
+/// Simulates removing packages from the local database.
+///
+/// This mirrors what a remove transaction with the given flags would do during
+/// `trans_prepare` but does not initialize a transaction so the database lock is never taken.
+///
+/// Only [`TransFlag::NO_DEPS`], [`TransFlag::CASCADE`], [`TransFlag::RECURSE`],
+/// [`TransFlag::RECURSE_ALL`] and [`TransFlag::UNNEEDED`] affect the result.
+pub fn removal_impact<'a, I>(alpm: &'a Alpm, pkgs: I, flags: TransFlag) -> RemovalImpact<'a>
+where
+    I: IntoIterator<Item = &'a Package>,
+{
+    let localdb = alpm.localdb();
+    let local = localdb.pkgs().iter().collect::<Vec<_>>();
+
+    let mut impact = RemovalImpact {
+        remove: Vec::new(),
+        cascade: Vec::new(),
+        recurse: Vec::new(),
+        kept: Vec::new(),
+        unsatisfied: AlpmListMut::new(),
+        orphans: Vec::new(),
+    };
+
+    for pkg in pkgs {
+        if let Ok(pkg) = localdb.pkg(pkg.name())
+            && !contains(&impact.remove, pkg)
+        {
+            impact.remove.push(pkg);
+        }
+    }
+
+    let recurse = flags.intersects(TransFlag::RECURSE | TransFlag::RECURSE_ALL);
+    let recurse_all = flags.intersects(TransFlag::RECURSE_ALL);
+
+    if recurse && !flags.intersects(TransFlag::CASCADE) {
+        recurse_deps(
+            localdb.pkgs(),
+            &mut impact.remove,
+            &mut impact.recurse,
+            recurse_all,
+        );
+    }
+
+    if !flags.intersects(TransFlag::NO_DEPS) {
+        if flags.intersects(TransFlag::CASCADE) {
+            loop {
+                let missing = check_deps(alpm, &local, &impact.remove);
+                if missing.is_empty() {
+                    break;
+                }
+
+                for miss in &missing {
+                    if let Ok(pkg) = localdb.pkg(miss.target())
+                        && !contains(&impact.remove, pkg)
+                    {
+                        impact.remove.push(pkg);
+                        impact.cascade.push(pkg);
+                    }
+                }
+
+                if recurse {
+                    recurse_deps(
+                        localdb.pkgs(),
+                        &mut impact.remove,
+                        &mut impact.recurse,
+                        recurse_all,
+                    );
+                }
+            }
+        } else if flags.intersects(TransFlag::UNNEEDED) {
+            loop {
+                let missing = check_deps(alpm, &local, &impact.remove);
+                if missing.is_empty() {
+                    break;
+                }
+
+                for miss in &missing {
+                    let causing = miss.causing_pkg().unwrap_or_default();
+                    if let Some(pos) = impact.remove.iter().position(|p| p.name() == causing) {
+                        let pkg = impact.remove.remove(pos);
+                        impact.recurse.retain(|p| p.name() != causing);
+                        impact.kept.push(pkg);
+                    }
+                }
+            }
+        }
+    }
+
+    impact.unsatisfied = check_deps(alpm, &local, &impact.remove);
+    impact.orphans = local
+        .iter()
+        .copied()
+        .filter(|pkg| pkg.reason() == PackageReason::Depend)
+        .filter(|pkg| !contains(&impact.remove, pkg))
+        .filter(|pkg| {
+            let required_by = pkg.required_by();
+            !required_by.is_empty()
+                && required_by
+                    .iter()
+                    .all(|name| impact.remove.iter().any(|p| p.name() == name))
+        })
+        .collect();
+
+    impact
+}
+
+fn check_deps(alpm: &Alpm, local: &[&Package], remove: &[&Package]) -> AlpmListMut<DependMissing> {
+    alpm.check_deps(
+        local.iter(),
+        remove.iter(),
+        AlpmListMut::<&Pkg>::new(),
+        true,
+    )
+}
+
+fn contains(list: &[&Package], pkg: &Package) -> bool {
+    list.iter().any(|p| p.name() == pkg.name())
+}
+
+fn recurse_deps<'a>(
+    local: AlpmList<&'a Package>,
+    remove: &mut Vec<&'a Package>,
+    added: &mut Vec<&'a Package>,
+    include_explicit: bool,
+) {
+    let mut i = 0;
+
+    while i < remove.len() {
+        let pkg = remove[i];
+        i += 1;
+
+        for dep in pkg.depends() {
+            let Some(deppkg) = local.find_satisfier(dep.to_string()) else {
+                continue;
+            };
+
+            if can_remove(deppkg, remove, include_explicit) {
+                remove.push(deppkg);
+                added.push(deppkg);
+            }
+        }
+    }
+}
+
+fn can_remove(pkg: &Package, remove: &[&Package], include_explicit: bool) -> bool {
+    if contains(remove, pkg) {
+        return false;
+    }
+
+    if !include_explicit && pkg.reason() == PackageReason::Explicit {
+        return false;
+    }
+
+    pkg.required_by()
+        .iter()
+        .all(|name| remove.iter().any(|p| p.name() == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removal_impact() {
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        let curl = handle.localdb().pkg("curl").unwrap();
+
+        let impact = removal_impact(&handle, [curl], TransFlag::NONE);
+        assert!(!impact.is_safe());
+        assert_eq!(impact.remove.len(), 1);
+        assert!(impact.cascade.is_empty());
+
+        let broken = impact.unsatisfied.iter().collect::<Vec<_>>();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].target(), "pacman");
+        assert_eq!(broken[0].depend().name(), "curl");
+    }
+
+    #[test]
+    fn test_removal_impact_cascade() {
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        let curl = handle.localdb().pkg("curl").unwrap();
+
+        let impact = removal_impact(&handle, [curl], TransFlag::CASCADE);
+        assert!(impact.is_safe());
+        assert!(impact.cascade.iter().any(|p| p.name() == "pacman"));
+        assert!(impact.remove.iter().any(|p| p.name() == "curl"));
+    }
+
+    #[test]
+    fn test_removal_impact_unneeded() {
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        let curl = handle.localdb().pkg("curl").unwrap();
+
+        let impact = removal_impact(&handle, [curl], TransFlag::UNNEEDED);
+        assert!(impact.is_safe());
+        assert!(impact.remove.is_empty());
+        assert_eq!(impact.kept.len(), 1);
+        assert_eq!(impact.kept[0].name(), "curl");
+    }
+}
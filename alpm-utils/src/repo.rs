@@ -0,0 +1,545 @@
+#[cfg(feature = "alpm")]
+use crate::archive::{ArchiveWriter, Compression};
+#[cfg(feature = "alpm")]
+use crate::tempdir::TempDir;
+#[cfg(feature = "alpm")]
+use alpm::{Alpm, Pkg, SigLevel, compute_sha256sum, vercmp};
+#[cfg(feature = "alpm")]
+use base64::Engine;
+#[cfg(feature = "alpm")]
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+#[cfg(feature = "alpm")]
+use std::cmp::Ordering;
+#[cfg(feature = "alpm")]
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "alpm")]
+use std::fs;
+#[cfg(feature = "alpm")]
+use std::io;
+#[cfg(feature = "alpm")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "alpm")]
+use std::os::unix::fs::symlink;
+#[cfg(feature = "alpm")]
+use std::path::{Path, PathBuf};
+
+/// An error while building a repository.
+#[cfg(feature = "alpm")]
+#[derive(Debug)]
+pub enum RepoError {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// libalpm failed to load a package or database.
+    Alpm(alpm::Error),
+}
+
+#[cfg(feature = "alpm")]
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoError::Io(e) => e.fmt(f),
+            RepoError::Alpm(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "alpm")]
+impl std::error::Error for RepoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepoError::Io(e) => Some(e),
+            RepoError::Alpm(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "alpm")]
+impl From<io::Error> for RepoError {
+    fn from(e: io::Error) -> RepoError {
+        RepoError::Io(e)
+    }
+}
+
+#[cfg(feature = "alpm")]
+impl From<alpm::Error> for RepoError {
+    fn from(e: alpm::Error) -> RepoError {
+        RepoError::Alpm(e)
+    }
+}
+
+/// The entry of a package in a sync database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoPackage {
+    /// The file name of the package.
+    pub filename: String,
+    /// The package name.
+    pub name: String,
+    /// The package base.
+    pub base: Option<String>,
+    /// The package version.
+    pub version: String,
+    /// The package description.
+    pub desc: Option<String>,
+    /// The groups the package is in.
+    pub groups: Vec<String>,
+    /// The size of the package file.
+    pub csize: i64,
+    /// The installed size of the package.
+    pub isize: i64,
+    /// The md5sum of the package file.
+    pub md5sum: Option<String>,
+    /// The sha256sum of the package file.
+    pub sha256sum: Option<String>,
+    /// The base64 encoded signature of the package.
+    pub pgpsig: Option<String>,
+    /// The package URL.
+    pub url: Option<String>,
+    /// The package licenses.
+    pub licenses: Vec<String>,
+    /// The package architecture.
+    pub arch: Option<String>,
+    /// The build date of the package.
+    pub builddate: i64,
+    /// The packager of the package.
+    pub packager: Option<String>,
+    /// Packages this package replaces.
+    pub replaces: Vec<String>,
+    /// Packages this package conflicts with.
+    pub conflicts: Vec<String>,
+    /// What this package provides.
+    pub provides: Vec<String>,
+    /// The dependencies of the package.
+    pub depends: Vec<String>,
+    /// The optional dependencies of the package.
+    pub optdepends: Vec<String>,
+    /// The make dependencies of the package.
+    pub makedepends: Vec<String>,
+    /// The check dependencies of the package.
+    pub checkdepends: Vec<String>,
+    /// The files in the package.
+    pub files: Vec<String>,
+}
+
+pub(crate) fn section<T: fmt::Display, I: IntoIterator<Item = T>>(
+    s: &mut String,
+    key: &str,
+    values: I,
+) {
+    let mut values = values.into_iter().peekable();
+    if values.peek().is_none() {
+        return;
+    }
+
+    s.push('%');
+    s.push_str(key);
+    s.push_str("%\n");
+    for value in values {
+        s.push_str(&value.to_string());
+        s.push('\n');
+    }
+    s.push('\n');
+}
+
+#[cfg(feature = "alpm")]
+fn strings<T: fmt::Display, I: IntoIterator<Item = T>>(iter: I) -> Vec<String> {
+    iter.into_iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(feature = "alpm")]
+impl RepoPackage {
+    /// Creates an entry from the metadata of a package.
+    ///
+    /// For packages from a database this is a copy of the package's entry. For packages
+    /// loaded from a file use [`RepoPackage::from_file`] which also fills in the checksums
+    /// and signature.
+    pub fn from_pkg(pkg: &Pkg) -> RepoPackage {
+        RepoPackage {
+            filename: pkg.filename().unwrap_or_default().to_string(),
+            name: pkg.name().to_string(),
+            base: pkg.base().map(|s| s.to_string()),
+            version: pkg.version().to_string(),
+            desc: pkg.desc().map(|s| s.to_string()),
+            groups: strings(pkg.groups()),
+            csize: pkg.size(),
+            isize: pkg.isize(),
+            #[cfg(not(feature = "git"))]
+            md5sum: pkg.md5sum().map(|s| s.to_string()),
+            #[cfg(feature = "git")]
+            md5sum: None,
+            sha256sum: pkg.sha256sum().map(|s| s.to_string()),
+            pgpsig: pkg.base64_sig().map(|s| s.to_string()),
+            url: pkg.url().map(|s| s.to_string()),
+            licenses: strings(pkg.licenses()),
+            arch: pkg.arch().map(|s| s.to_string()),
+            builddate: pkg.build_date(),
+            packager: pkg.packager().map(|s| s.to_string()),
+            replaces: strings(pkg.replaces()),
+            conflicts: strings(pkg.conflicts()),
+            provides: strings(pkg.provides()),
+            depends: strings(pkg.depends()),
+            optdepends: strings(pkg.optdepends()),
+            makedepends: strings(pkg.makedepends()),
+            checkdepends: strings(pkg.checkdepends()),
+            files: pkg
+                .files()
+                .files()
+                .iter()
+                .map(|f| String::from_utf8_lossy(f.name()).into_owned())
+                .collect(),
+        }
+    }
+
+    /// Loads a package file and creates its entry.
+    ///
+    /// The signature is read from `<path>.sig` if it exists.
+    pub fn from_file<P: AsRef<Path>>(alpm: &Alpm, path: P) -> Result<RepoPackage, RepoError> {
+        let path = path.as_ref();
+        let bytes = path.as_os_str().as_bytes();
+        let pkg = alpm.pkg_load(bytes, true, SigLevel::NONE)?;
+        let mut entry = RepoPackage::from_pkg(&pkg);
+
+        let mut sig = path.as_os_str().to_owned();
+        sig.push(".sig");
+        let checksum = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        entry.filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        entry.csize = fs::metadata(path)?.len() as i64;
+        #[cfg(not(feature = "git"))]
+        {
+            entry.md5sum = Some(alpm::compute_md5sum(bytes).map_err(checksum)?);
+        }
+        entry.sha256sum = Some(compute_sha256sum(bytes).map_err(checksum)?);
+        entry.pgpsig = match fs::read(sig) {
+            Ok(sig) => Some(BASE64.encode(sig)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(entry)
+    }
+}
+
+impl RepoPackage {
+    /// The contents of the package's `desc` entry.
+    pub fn desc_entry(&self) -> String {
+        let mut s = String::new();
+        section(&mut s, "FILENAME", [&self.filename]);
+        section(&mut s, "NAME", [&self.name]);
+        section(&mut s, "BASE", &self.base);
+        section(&mut s, "VERSION", [&self.version]);
+        section(&mut s, "DESC", &self.desc);
+        section(&mut s, "GROUPS", &self.groups);
+        section(&mut s, "CSIZE", [self.csize]);
+        section(&mut s, "ISIZE", [self.isize]);
+        section(&mut s, "MD5SUM", &self.md5sum);
+        section(&mut s, "SHA256SUM", &self.sha256sum);
+        section(&mut s, "PGPSIG", &self.pgpsig);
+        section(&mut s, "URL", &self.url);
+        section(&mut s, "LICENSE", &self.licenses);
+        section(&mut s, "ARCH", &self.arch);
+        section(&mut s, "BUILDDATE", [self.builddate]);
+        section(&mut s, "PACKAGER", &self.packager);
+        section(&mut s, "REPLACES", &self.replaces);
+        section(&mut s, "CONFLICTS", &self.conflicts);
+        section(&mut s, "PROVIDES", &self.provides);
+        section(&mut s, "DEPENDS", &self.depends);
+        section(&mut s, "OPTDEPENDS", &self.optdepends);
+        section(&mut s, "MAKEDEPENDS", &self.makedepends);
+        section(&mut s, "CHECKDEPENDS", &self.checkdepends);
+        s
+    }
+
+    /// The contents of the package's `files` entry.
+    pub fn files_entry(&self) -> String {
+        let mut s = String::new();
+        section(&mut s, "FILES", &self.files);
+        s
+    }
+}
+
+/// What [`Repo::add`] did with a package.
+#[cfg(feature = "alpm")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RepoAdd {
+    /// The package was not in the repo.
+    Added,
+    /// The package replaced an older or equal version.
+    Updated {
+        /// The version that was replaced.
+        old: String,
+    },
+    /// The repo already has a newer version of the package.
+    Skipped {
+        /// The version in the repo.
+        newer: String,
+    },
+}
+
+/// A sync repository, like the ones created by `repo-add`.
+///
+/// The databases are written as `<name>.db.tar.gz` and `<name>.files.tar.gz` with
+/// `<name>.db` and `<name>.files` symlinks pointing to them. Use
+/// [`Repo::set_compression`] to pick another compression.
+///
+/// ```no_run
+/// use alpm::Alpm;
+/// use alpm_utils::Repo;
+///
+/// let alpm = Alpm::new("/", "/var/lib/pacman").unwrap();
+/// let mut repo = Repo::open("/srv/repo", "custom").unwrap();
+/// repo.add(&alpm, "/srv/repo/foo-1.0-1-x86_64.pkg.tar.zst").unwrap();
+/// repo.remove("bar");
+/// repo.write().unwrap();
+/// ```
+#[cfg(feature = "alpm")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repo {
+    dir: PathBuf,
+    name: String,
+    compression: Compression,
+    packages: BTreeMap<String, RepoPackage>,
+}
+
+#[cfg(feature = "alpm")]
+impl Repo {
+    /// Creates an empty repo named `name` in `dir`.
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(dir: P, name: S) -> Repo {
+        Repo {
+            dir: dir.into(),
+            name: name.into(),
+            compression: Compression::Gzip,
+            packages: BTreeMap::new(),
+        }
+    }
+
+    /// Opens the repo named `name` in `dir`, reading its existing database if there is one.
+    ///
+    /// The files database is preferred as it also contains the file lists.
+    pub fn open<P: Into<PathBuf>, S: Into<String>>(dir: P, name: S) -> Result<Repo, RepoError> {
+        let mut repo = Repo::new(dir, name);
+
+        let files = repo.db_path("files");
+        let db = repo.db_path("db");
+        let (path, ext) = if files.exists() {
+            (files, ".files")
+        } else if db.exists() {
+            (db, ".db")
+        } else {
+            return Ok(repo);
+        };
+
+        let tmp = TempDir::new("alpm-utils-repo")?;
+        fs::create_dir(tmp.path().join("sync"))?;
+        let link = tmp
+            .path()
+            .join("sync")
+            .join(format!("{}{}", repo.name, ext));
+        symlink(fs::canonicalize(path)?, link)?;
+
+        let dbpath = tmp.path().as_os_str().as_bytes();
+        let mut alpm = Alpm::new("/".as_bytes(), dbpath)?;
+        alpm.set_dbext(ext);
+        let syncdb = alpm.register_syncdb(repo.name.as_str(), SigLevel::NONE)?;
+        for pkg in syncdb.pkgs() {
+            repo.packages
+                .insert(pkg.name().to_string(), RepoPackage::from_pkg(pkg));
+        }
+
+        Ok(repo)
+    }
+
+    /// The name of the repo.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The directory the repo is written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The compression used when writing the databases.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Sets the compression used when writing the databases.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// The packages in the repo sorted by name.
+    pub fn packages(&self) -> impl Iterator<Item = &RepoPackage> {
+        self.packages.values()
+    }
+
+    /// Finds a package in the repo.
+    pub fn package(&self, name: &str) -> Option<&RepoPackage> {
+        self.packages.get(name)
+    }
+
+    /// Loads a package file and adds it to the repo.
+    pub fn add<P: AsRef<Path>>(&mut self, alpm: &Alpm, path: P) -> Result<RepoAdd, RepoError> {
+        Ok(self.add_package(RepoPackage::from_file(alpm, path)?))
+    }
+
+    /// Adds an entry to the repo unless the repo has a newer version of the package.
+    pub fn add_package(&mut self, pkg: RepoPackage) -> RepoAdd {
+        let ret = match self.packages.get(&pkg.name) {
+            None => RepoAdd::Added,
+            Some(old) => match vercmp(old.version.as_str(), pkg.version.as_str()) {
+                Ordering::Greater => {
+                    return RepoAdd::Skipped {
+                        newer: old.version.clone(),
+                    };
+                }
+                _ => RepoAdd::Updated {
+                    old: old.version.clone(),
+                },
+            },
+        };
+
+        self.packages.insert(pkg.name.clone(), pkg);
+        ret
+    }
+
+    /// Removes a package from the repo.
+    pub fn remove(&mut self, name: &str) -> Option<RepoPackage> {
+        self.packages.remove(name)
+    }
+
+    /// Writes the db and files databases.
+    ///
+    /// Each database is written to a temporary file first and then renamed over the old
+    /// one.
+    pub fn write(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        self.write_db("db", false)?;
+        self.write_db("files", true)
+    }
+
+    fn db_path(&self, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.name, ext))
+    }
+
+    fn write_db(&self, ext: &str, files: bool) -> io::Result<()> {
+        let archive = format!("{}.{}.tar{}", self.name, ext, self.compression.extension());
+        let path = self.dir.join(&archive);
+        let tmp = self.dir.join(format!(".{}.tmp", archive));
+
+        let mut writer = ArchiveWriter::create(&tmp, self.compression)?;
+        for pkg in self.packages.values() {
+            let dir = format!("{}-{}/", pkg.name, pkg.version);
+            let mtime = pkg.builddate.max(0);
+            writer.add_dir(&dir, 0o755, mtime)?;
+            let desc = pkg.desc_entry();
+            writer.add_file(&format!("{}desc", dir), desc.as_bytes(), 0o644, mtime)?;
+            if files {
+                let files = pkg.files_entry();
+                writer.add_file(&format!("{}files", dir), files.as_bytes(), 0o644, mtime)?;
+            }
+        }
+        writer.finish()?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        let link = self.db_path(ext);
+        let old = fs::read_link(&link).ok();
+        if fs::symlink_metadata(&link).is_ok() {
+            fs::remove_file(&link)?;
+        }
+        symlink(&archive, link)?;
+
+        // the compression changed, remove the archive the link pointed to. only an archive of
+        // this database in the repo dir is removed, not an arbitrary link target.
+        let prefix = format!("{}.{}.tar", self.name, ext);
+        if let Some(old) = old.as_deref().and_then(Path::to_str)
+            && old != archive
+            && old.starts_with(&prefix)
+            && !old.contains('/')
+        {
+            match fs::remove_file(self.dir.join(old)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "alpm"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo() {
+        let tmp = TempDir::new("alpm-utils-repo-test").unwrap();
+        let dir = tmp.path();
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        let pkg = "../alpm/tests/pacman-5.1.3-1-x86_64.pkg.tar.xz";
+
+        let mut repo = Repo::open(dir.join("sync"), "custom").unwrap();
+        assert_eq!(repo.packages().count(), 0);
+        assert_eq!(repo.add(&handle, pkg).unwrap(), RepoAdd::Added);
+
+        let mut old = repo.package("pacman").unwrap().clone();
+        old.version = "5.0.0-1".into();
+        assert_eq!(
+            repo.add_package(old),
+            RepoAdd::Skipped {
+                newer: "5.1.3-1".into()
+            }
+        );
+        let mut foo = repo.package("pacman").unwrap().clone();
+        foo.name = "foo".into();
+        assert_eq!(repo.add_package(foo), RepoAdd::Added);
+        repo.write().unwrap();
+
+        let handle = Alpm::new("/", dir.to_str().unwrap()).unwrap();
+        let db = handle.register_syncdb("custom", SigLevel::NONE).unwrap();
+        let pacman = db.pkg("pacman").unwrap();
+        assert_eq!(pacman.version().as_str(), "5.1.3-1");
+        assert_eq!(pacman.filename(), Some("pacman-5.1.3-1-x86_64.pkg.tar.xz"));
+        assert_eq!(pacman.packager(), Some("Allan McRae <allan@archlinux.org>"));
+        assert!(pacman.sha256sum().is_some());
+        assert_eq!(db.pkgs().len(), 2);
+
+        let mut repo = Repo::open(dir.join("sync"), "custom").unwrap();
+        assert_eq!(repo.package("pacman").unwrap().name, "pacman");
+        assert!(repo.remove("foo").is_some());
+        repo.set_compression(Compression::Xz);
+        let signed = "../alpm/tests/signed/pacman-5.1.3-1-x86_64.pkg.tar.xz";
+        assert_eq!(
+            repo.add(&handle, signed).unwrap(),
+            RepoAdd::Updated {
+                old: "5.1.3-1".into()
+            }
+        );
+        let sig = repo.package("pacman").unwrap().pgpsig.as_deref().unwrap();
+        assert_eq!(
+            alpm::decode_signature(sig).unwrap(),
+            fs::read(format!("{}.sig", signed)).unwrap()
+        );
+        repo.write().unwrap();
+        assert_eq!(
+            Repo::open(dir.join("sync"), "custom")
+                .unwrap()
+                .packages()
+                .count(),
+            1
+        );
+        assert!(dir.join("sync/custom.db.tar.xz").is_file());
+        assert!(!dir.join("sync/custom.db.tar.gz").exists());
+        assert!(!dir.join("sync/custom.files.tar.gz").exists());
+        assert_eq!(
+            fs::read_link(dir.join("sync/custom.files")).unwrap(),
+            Path::new("custom.files.tar.xz")
+        );
+    }
+}
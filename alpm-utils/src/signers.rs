@@ -0,0 +1,336 @@
+use alpm::{Alpm, Db, Pkg, decode_signature};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The key IDs that signed a sync package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSigners {
+    /// The name of the db the package is in.
+    pub db: String,
+    /// The package name.
+    pub name: String,
+    /// The package version.
+    pub version: String,
+    /// The packager field of the package.
+    pub packager: Option<String>,
+    /// The key IDs that signed the package.
+    pub keys: Vec<String>,
+    /// Set if the package has a signature that could not be read.
+    pub invalid: bool,
+}
+
+impl PackageSigners {
+    /// Reads the key IDs from the signature of a package.
+    pub fn new(alpm: &Alpm, db: &str, pkg: &Pkg) -> PackageSigners {
+        let mut keys = Vec::new();
+        let mut invalid = false;
+
+        if let Some(sig) = pkg.base64_sig() {
+            let ids = decode_signature(sig)
+                .ok()
+                .and_then(|sig| alpm.extract_keyid(pkg.name(), &sig).ok());
+            match ids {
+                Some(ids) => keys.extend(ids.iter().map(|k| k.to_uppercase())),
+                None => invalid = true,
+            }
+        }
+
+        PackageSigners {
+            db: db.to_string(),
+            name: pkg.name().to_string(),
+            version: pkg.version().to_string(),
+            packager: pkg.packager().map(|p| p.to_string()),
+            keys,
+            invalid,
+        }
+    }
+
+    /// Returns true if the package has no signature.
+    pub fn is_unsigned(&self) -> bool {
+        self.keys.is_empty() && !self.invalid
+    }
+}
+
+/// A key passed to [`KeyOwners::add`] that is not a fingerprint or long key ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvalidKeyId(pub String);
+
+impl fmt::Display for InvalidKeyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid key id: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidKeyId {}
+
+/// Maps key IDs to the user IDs of their owners.
+///
+/// Keys may be given as full fingerprints or long key IDs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyOwners {
+    owners: HashMap<String, Vec<String>>,
+}
+
+impl KeyOwners {
+    /// Creates an empty mapping.
+    pub fn new() -> KeyOwners {
+        KeyOwners::default()
+    }
+
+    /// Adds a user ID such as `Name <email>` to a key.
+    ///
+    /// Spaces in the key are ignored. Fails if the key is not at least 16 hex digits.
+    pub fn add<K: AsRef<str>, U: Into<String>>(
+        &mut self,
+        key: K,
+        uid: U,
+    ) -> Result<(), InvalidKeyId> {
+        let key = key.as_ref();
+        let id = key_id(key).ok_or_else(|| InvalidKeyId(key.to_string()))?;
+        self.owners.entry(id).or_default().push(uid.into());
+        Ok(())
+    }
+
+    /// The user IDs of a key, None if the key is not known.
+    pub fn owners(&self, key: &str) -> Option<&[String]> {
+        self.owners.get(&key_id(key)?).map(|o| o.as_slice())
+    }
+}
+
+// the long key ID of a fingerprint or key ID
+fn key_id(key: &str) -> Option<String> {
+    let key = key.replace(' ', "");
+    if key.len() < 16 || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(key[key.len() - 16..].to_ascii_uppercase())
+}
+
+/// A problem found while auditing the signers of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SignerIssue {
+    /// The package has no signature.
+    Unsigned,
+    /// The signature of the package could not be read.
+    InvalidSignature,
+    /// The package was signed by a key with no known owner.
+    UnknownKey(String),
+    /// None of the keys that signed the package belong to the packager.
+    PackagerMismatch,
+}
+
+impl fmt::Display for SignerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerIssue::Unsigned => f.write_str("package is not signed"),
+            SignerIssue::InvalidSignature => f.write_str("signature could not be read"),
+            SignerIssue::UnknownKey(key) => write!(f, "signed by unknown key {}", key),
+            SignerIssue::PackagerMismatch => f.write_str("signer does not match the packager"),
+        }
+    }
+}
+
+/// A package flagged by [`SignerAudit::findings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerFinding<'a> {
+    /// The flagged package.
+    pub package: &'a PackageSigners,
+    /// What is wrong with the package.
+    pub issue: SignerIssue,
+}
+
+/// The signers of every package in a set of sync dbs.
+///
+/// Only the signatures stored in the dbs are read so the audit can be run offline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignerAudit {
+    /// The packages in the order they appear in the dbs.
+    pub packages: Vec<PackageSigners>,
+}
+
+impl SignerAudit {
+    /// Reads the signers of every package in the registered sync dbs.
+    pub fn from_alpm(alpm: &Alpm) -> SignerAudit {
+        SignerAudit::from_dbs(alpm, alpm.syncdbs())
+    }
+
+    /// Reads the signers of every package in the given dbs.
+    pub fn from_dbs<'a, I: IntoIterator<Item = &'a Db>>(alpm: &Alpm, dbs: I) -> SignerAudit {
+        let packages = dbs
+            .into_iter()
+            .flat_map(|db| {
+                db.pkgs()
+                    .into_iter()
+                    .map(move |pkg| PackageSigners::new(alpm, db.name(), pkg))
+            })
+            .collect();
+        SignerAudit { packages }
+    }
+
+    /// Groups the packages by the key IDs that signed them.
+    ///
+    /// Packages signed by several keys appear under each key.
+    pub fn by_key(&self) -> BTreeMap<&str, Vec<&PackageSigners>> {
+        let mut keys = BTreeMap::<_, Vec<_>>::new();
+        for pkg in &self.packages {
+            for key in &pkg.keys {
+                keys.entry(key.as_str()).or_default().push(pkg);
+            }
+        }
+        keys
+    }
+
+    /// Groups the packages by their packager field.
+    ///
+    /// Packages without a packager are grouped under "Unknown Packager".
+    pub fn by_packager(&self) -> BTreeMap<&str, Vec<&PackageSigners>> {
+        let mut packagers = BTreeMap::<_, Vec<_>>::new();
+        for pkg in &self.packages {
+            let packager = pkg.packager.as_deref().unwrap_or("Unknown Packager");
+            packagers.entry(packager).or_default().push(pkg);
+        }
+        packagers
+    }
+
+    /// Packages that have no signature.
+    pub fn unsigned(&self) -> Vec<&PackageSigners> {
+        self.packages.iter().filter(|p| p.is_unsigned()).collect()
+    }
+
+    /// Flags packages that are unsigned, have unreadable signatures or whose signers do not
+    /// match their packager.
+    ///
+    /// A signer matches the packager if one of the owner's user IDs has the same email
+    /// address as the packager field.
+    pub fn findings(&self, owners: &KeyOwners) -> Vec<SignerFinding<'_>> {
+        let mut findings = Vec::new();
+
+        for pkg in &self.packages {
+            let mut issues = Vec::new();
+
+            if pkg.invalid {
+                issues.push(SignerIssue::InvalidSignature);
+            } else if pkg.keys.is_empty() {
+                issues.push(SignerIssue::Unsigned);
+            }
+
+            let packager = pkg.packager.as_deref().map(email);
+            let mut matched = false;
+            for key in &pkg.keys {
+                match owners.owners(key) {
+                    Some(uids) => {
+                        matched |= uids.iter().any(|uid| Some(email(uid)) == packager);
+                    }
+                    None => issues.push(SignerIssue::UnknownKey(key.clone())),
+                }
+            }
+            if !matched && pkg.keys.iter().any(|k| owners.owners(k).is_some()) {
+                issues.push(SignerIssue::PackagerMismatch);
+            }
+
+            findings.extend(issues.into_iter().map(|issue| SignerFinding {
+                package: pkg,
+                issue,
+            }));
+        }
+
+        findings
+    }
+}
+
+fn email(uid: &str) -> String {
+    let addr = match (uid.rfind('<'), uid.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &uid[start + 1..end],
+        _ => uid,
+    };
+    addr.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alpm::SigLevel;
+
+    fn signers(name: &str, packager: &str, keys: &[&str]) -> PackageSigners {
+        PackageSigners {
+            db: "core".into(),
+            name: name.into(),
+            version: "1-1".into(),
+            packager: Some(packager.into()),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            invalid: false,
+        }
+    }
+
+    #[test]
+    fn test_signer_audit() {
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        handle.register_syncdb("core", SigLevel::NONE).unwrap();
+
+        let audit = SignerAudit::from_alpm(&handle);
+        let linux = audit.packages.iter().find(|p| p.name == "linux").unwrap();
+        assert_eq!(linux.db, "core");
+        assert_eq!(linux.keys, ["A5E9288C4FA415FA"]);
+        assert!(audit.unsigned().is_empty());
+
+        let by_key = audit.by_key();
+        assert!(by_key[linux.keys[0].as_str()].contains(&linux));
+        let by_packager = audit.by_packager();
+        let heftig = "Jan Alexander Steffens (heftig) <jan.steffens@gmail.com>";
+        assert!(by_packager[heftig].iter().all(|p| p.keys == linux.keys));
+
+        let mut owners = KeyOwners::new();
+        owners
+            .add(&linux.keys[0], "heftig <JAN.STEFFENS@gmail.com>")
+            .unwrap();
+        assert!(
+            audit
+                .findings(&owners)
+                .iter()
+                .all(|f| f.package.packager.as_deref() != Some(heftig))
+        );
+    }
+
+    #[test]
+    fn test_findings() {
+        let audit = SignerAudit {
+            packages: vec![
+                signers("a", "Foo <foo@example.org>", &["0123456789ABCDEF"]),
+                signers("b", "Foo <foo@example.org>", &["FEDCBA9876543210"]),
+                signers("c", "Bar <bar@example.org>", &["0123456789ABCDEF"]),
+                signers("d", "Bar <bar@example.org>", &[]),
+            ],
+        };
+        let mut owners = KeyOwners::new();
+        owners
+            .add(
+                "AAAA BBBB CCCC DDDD EEEE  0123 4567 89ab cdef",
+                "Foo <foo@example.org>",
+            )
+            .unwrap();
+        assert!(owners.owners("89abcdef01234567").is_none());
+        assert!(owners.owners("0123456789abcdé").is_none());
+        assert_eq!(
+            owners.add("ééééééééééééééééé", "Bar <bar@example.org>"),
+            Err(InvalidKeyId("ééééééééééééééééé".into()))
+        );
+        assert!(owners.add("0123456789ABCDE", "Bar").is_err());
+
+        let findings = audit
+            .findings(&owners)
+            .into_iter()
+            .map(|f| (f.package.name.as_str(), f.issue))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            findings,
+            [
+                ("b", SignerIssue::UnknownKey("FEDCBA9876543210".into())),
+                ("c", SignerIssue::PackagerMismatch),
+                ("d", SignerIssue::Unsigned),
+            ]
+        );
+        assert_eq!(audit.by_key()["0123456789ABCDEF"].len(), 2);
+        assert_eq!(audit.unsigned()[0].name, "d");
+    }
+}
@@ -0,0 +1,363 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use alpm::{Alpm, DepMod, Depend, LoadedPackage, Package, Result};
+
+use crate::{DbListExt, Targ};
+
+/// Extensions recognised as package files.
+const PKG_EXTS: &[&str] = &[
+    ".pkg.tar",
+    ".pkg.tar.gz",
+    ".pkg.tar.bz2",
+    ".pkg.tar.xz",
+    ".pkg.tar.zst",
+    ".pkg.tar.lz4",
+    ".pkg.tar.lrz",
+    ".pkg.tar.lzo",
+    ".pkg.tar.Z",
+];
+
+/// An error encountered while parsing a [`TargetSpec`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TargetSpecError {
+    /// The target was empty.
+    Empty,
+    /// The repository part of `repo/pkg` was empty.
+    EmptyRepo,
+    /// The package name was empty.
+    EmptyName,
+    /// A version constraint was given without a version.
+    EmptyVersion,
+    /// The target contained more than one `/`.
+    TooManySlashes,
+    /// The package name contained invalid characters.
+    InvalidName(String),
+    /// The repository name contained invalid characters.
+    InvalidRepo(String),
+    /// The url had no host or path.
+    InvalidUrl(String),
+}
+
+impl fmt::Display for TargetSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetSpecError::Empty => f.write_str("target is empty"),
+            TargetSpecError::EmptyRepo => f.write_str("repository name is empty"),
+            TargetSpecError::EmptyName => f.write_str("package name is empty"),
+            TargetSpecError::EmptyVersion => f.write_str("version constraint has no version"),
+            TargetSpecError::TooManySlashes => f.write_str("target contains too many '/'"),
+            TargetSpecError::InvalidName(name) => write!(f, "invalid package name: {}", name),
+            TargetSpecError::InvalidRepo(repo) => write!(f, "invalid repository name: {}", repo),
+            TargetSpecError::InvalidUrl(url) => write!(f, "invalid url: {}", url),
+        }
+    }
+}
+
+impl std::error::Error for TargetSpecError {}
+
+/// A fully classified target as accepted by pacman.
+///
+/// A target is one of:
+/// - `pkg`, `pkg>=1.2`: a package, provider or group from any repository.
+/// - `repo/pkg`, `repo/pkg=1.0-2`: a package, provider or group from a specific repository.
+/// - `./foo-1.0-1-x86_64.pkg.tar.zst`: a package file on disk.
+/// - `https://example.com/foo-1.0-1-x86_64.pkg.tar.zst`, `file:///tmp/foo.pkg.tar.zst`: a
+///   package file to fetch.
+///
+/// Packages and groups can not be told apart without looking at the databases. Like pacman,
+/// [`TargetSpec::resolve`] prefers a satisfying package and falls back to a group.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetSpec {
+    /// A dependency, optionally restricted to a repository.
+    Dep {
+        /// The repository the package should come from. None for any repository.
+        repo: Option<String>,
+        /// The package name and version constraint.
+        dep: Depend,
+    },
+    /// A package file on disk.
+    File(PathBuf),
+    /// A package file to download.
+    Url(String),
+}
+
+/// A [`TargetSpec`] resolved to packages.
+#[derive(Debug)]
+pub enum ResolvedTarget<'a> {
+    /// A package from a sync database.
+    Package(&'a Package),
+    /// The packages of a group.
+    Group(Vec<&'a Package>),
+    /// A package loaded from a file or url.
+    Loaded(LoadedPackage<'a>),
+}
+
+impl FromStr for TargetSpec {
+    type Err = TargetSpecError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        TargetSpec::parse(s)
+    }
+}
+
+impl fmt::Display for TargetSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetSpec::Dep {
+                repo: Some(repo),
+                dep,
+            } => write!(f, "{}/{}", repo, dep),
+            TargetSpec::Dep { repo: None, dep } => dep.fmt(f),
+            TargetSpec::File(path) => path.display().fmt(f),
+            TargetSpec::Url(url) => f.write_str(url),
+        }
+    }
+}
+
+impl TargetSpec {
+    /// Parses a target.
+    pub fn parse(s: &str) -> std::result::Result<TargetSpec, TargetSpecError> {
+        if s.is_empty() {
+            return Err(TargetSpecError::Empty);
+        }
+
+        if let Some((scheme, rest)) = s.split_once("://") {
+            if !valid_scheme(scheme) {
+                return Err(TargetSpecError::InvalidUrl(s.to_string()));
+            }
+            if rest.is_empty() || rest.ends_with('/') {
+                return Err(TargetSpecError::InvalidUrl(s.to_string()));
+            }
+            return Ok(TargetSpec::Url(s.to_string()));
+        }
+
+        if is_pkg_file(s) {
+            return Ok(TargetSpec::File(PathBuf::from(s)));
+        }
+
+        let mut split = s.split('/');
+        let first = split.next().unwrap();
+        let (repo, pkg) = match (split.next(), split.next()) {
+            (None, _) => (None, first),
+            (Some(pkg), None) => (Some(first), pkg),
+            (Some(_), Some(_)) => return Err(TargetSpecError::TooManySlashes),
+        };
+
+        if let Some(repo) = repo {
+            if repo.is_empty() {
+                return Err(TargetSpecError::EmptyRepo);
+            }
+            if !repo
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            {
+                return Err(TargetSpecError::InvalidRepo(repo.to_string()));
+            }
+        }
+
+        let name = pkg.split(['<', '>', '=']).next().unwrap();
+        if name.is_empty() {
+            return Err(TargetSpecError::EmptyName);
+        }
+        if !valid_name(name) {
+            return Err(TargetSpecError::InvalidName(name.to_string()));
+        }
+
+        let dep = Depend::new(pkg);
+        if dep.depmod() != DepMod::Any && dep.version().is_none_or(|v| v.is_empty()) {
+            return Err(TargetSpecError::EmptyVersion);
+        }
+
+        Ok(TargetSpec::Dep {
+            repo: repo.map(|r| r.to_string()),
+            dep,
+        })
+    }
+
+    /// The repository the target is restricted to, if any.
+    pub fn repo(&self) -> Option<&str> {
+        match self {
+            TargetSpec::Dep { repo, .. } => repo.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The dependency of the target, if the target is not a file or url.
+    pub fn dep(&self) -> Option<&Depend> {
+        match self {
+            TargetSpec::Dep { dep, .. } => Some(dep),
+            _ => None,
+        }
+    }
+
+    /// The group name this target may refer to.
+    ///
+    /// Groups can not have version constraints so this is only Some for plain names.
+    pub fn group(&self) -> Option<&str> {
+        match self {
+            TargetSpec::Dep { dep, .. } if dep.depmod() == DepMod::Any => Some(dep.name()),
+            _ => None,
+        }
+    }
+
+    /// Resolves the target into packages.
+    ///
+    /// Dependencies are looked up in the sync databases using [`DbListExt`], falling back to
+    /// groups. Files are loaded with [`Alpm::pkg_load`] using the local file siglevel and urls
+    /// are downloaded with [`Alpm::fetch_pkgurl`] and loaded using the remote file siglevel.
+    pub fn resolve<'a>(&self, alpm: &'a Alpm) -> Result<ResolvedTarget<'a>> {
+        match self {
+            TargetSpec::Dep { repo, dep } => {
+                let dbs = alpm.syncdbs();
+                if let Some(repo) = repo
+                    && !dbs.iter().any(|db| db.name() == repo)
+                {
+                    return Err(alpm::Error::DbNotFound);
+                }
+
+                let dep_str = dep.to_string();
+                let targ = Targ::new(repo.as_deref(), &dep_str);
+                if let Some(pkg) = dbs.find_target_satisfier(targ) {
+                    return Ok(ResolvedTarget::Package(pkg));
+                }
+
+                let group = self.group().ok_or(alpm::Error::PkgNotFound)?;
+                let pkgs: Vec<_> = match repo {
+                    Some(repo) => dbs
+                        .iter()
+                        .find(|db| db.name() == repo)
+                        .and_then(|db| db.group(group).ok())
+                        .map(|g| g.packages().iter().collect())
+                        .unwrap_or_default(),
+                    None => alpm.find_group_pkgs(dbs, group).into_iter().collect(),
+                };
+
+                if pkgs.is_empty() {
+                    Err(alpm::Error::PkgNotFound)
+                } else {
+                    Ok(ResolvedTarget::Group(pkgs))
+                }
+            }
+            TargetSpec::File(path) => {
+                let path = path.to_str().ok_or(alpm::Error::WrongArgs)?;
+                let pkg = alpm.pkg_load(path, true, alpm.local_file_siglevel())?;
+                Ok(ResolvedTarget::Loaded(pkg))
+            }
+            TargetSpec::Url(url) => {
+                let fetched = alpm.fetch_pkgurl([url.as_str()].iter())?;
+                let path = fetched.first().ok_or(alpm::Error::Retrieve)?;
+                let pkg = alpm.pkg_load(path, true, alpm.remote_file_siglevel())?;
+                Ok(ResolvedTarget::Loaded(pkg))
+            }
+        }
+    }
+}
+
+fn valid_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.starts_with(['-', '.'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c))
+}
+
+fn is_pkg_file(s: &str) -> bool {
+    let path = Path::new(s);
+    path.is_absolute()
+        || s.starts_with("./")
+        || s.starts_with("../")
+        || PKG_EXTS.iter().any(|ext| s.ends_with(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alpm::SigLevel;
+
+    #[test]
+    fn test_parse() {
+        let spec = TargetSpec::parse("core/pacman>=1.2").unwrap();
+        assert_eq!(spec.repo(), Some("core"));
+        let dep = spec.dep().unwrap();
+        assert_eq!(dep.name(), "pacman");
+        assert_eq!(dep.depmod(), DepMod::Ge);
+        assert_eq!(dep.version().unwrap().as_str(), "1.2");
+        assert_eq!(spec.group(), None);
+        assert_eq!(spec.to_string(), "core/pacman>=1.2");
+
+        let spec = TargetSpec::parse("pacman=1.0-2").unwrap();
+        assert_eq!(spec.repo(), None);
+        assert_eq!(spec.dep().unwrap().depmod(), DepMod::Eq);
+
+        let spec = TargetSpec::parse("base-devel").unwrap();
+        assert_eq!(spec.group(), Some("base-devel"));
+
+        let spec = TargetSpec::parse("foo-1.0-1-x86_64.pkg.tar.zst").unwrap();
+        assert!(matches!(spec, TargetSpec::File(_)));
+        let spec = TargetSpec::parse("/tmp/foo").unwrap();
+        assert!(matches!(spec, TargetSpec::File(_)));
+
+        let spec = TargetSpec::parse("https://example.com/foo.pkg.tar.zst").unwrap();
+        assert!(matches!(spec, TargetSpec::Url(_)));
+        let spec = TargetSpec::parse("file:///tmp/foo.pkg.tar.zst").unwrap();
+        assert!(matches!(spec, TargetSpec::Url(_)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(TargetSpec::parse(""), Err(TargetSpecError::Empty));
+        assert_eq!(
+            TargetSpec::parse("a/b/c"),
+            Err(TargetSpecError::TooManySlashes)
+        );
+        assert_eq!(TargetSpec::parse("core/"), Err(TargetSpecError::EmptyName));
+        assert_eq!(TargetSpec::parse(">=1"), Err(TargetSpecError::EmptyName));
+        assert_eq!(
+            TargetSpec::parse("foo>="),
+            Err(TargetSpecError::EmptyVersion)
+        );
+        assert_eq!(
+            TargetSpec::parse("foo bar"),
+            Err(TargetSpecError::InvalidName("foo bar".into()))
+        );
+        assert_eq!(
+            TargetSpec::parse("://foo"),
+            Err(TargetSpecError::InvalidUrl("://foo".into()))
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+        handle.register_syncdb("core", SigLevel::NONE).unwrap();
+        handle.register_syncdb("extra", SigLevel::NONE).unwrap();
+
+        let spec = TargetSpec::parse("core/linux>0").unwrap();
+        let ResolvedTarget::Package(pkg) = spec.resolve(&handle).unwrap() else {
+            panic!("target is not a package");
+        };
+        assert_eq!(pkg.name(), "linux");
+
+        let spec = TargetSpec::parse("base").unwrap();
+        let ResolvedTarget::Group(pkgs) = spec.resolve(&handle).unwrap() else {
+            panic!("target is not a group");
+        };
+        assert!(pkgs.iter().any(|p| p.name() == "linux"));
+
+        let spec = TargetSpec::parse("nope/linux").unwrap();
+        assert_eq!(spec.resolve(&handle).unwrap_err(), alpm::Error::DbNotFound);
+
+        let spec = TargetSpec::parse("../alpm/tests/pacman-5.1.3-1-x86_64.pkg.tar.xz").unwrap();
+        let ResolvedTarget::Loaded(pkg) = spec.resolve(&handle).unwrap() else {
+            panic!("target is not a file");
+        };
+        assert_eq!(pkg.name(), "pacman");
+    }
+}
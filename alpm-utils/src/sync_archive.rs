@@ -0,0 +1,347 @@
+use crate::RepoPackage;
+use crate::archive::ArchiveReader;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+impl RepoPackage {
+    /// Fills in the fields found in a `desc`, `depends` or `files` entry.
+    ///
+    /// Fields with a single value use their first line, unknown fields are ignored.
+    pub fn parse_entry(&mut self, entry: &str) -> Result<(), SyncArchiveError> {
+        let mut sections = Vec::<(&str, Vec<String>)>::new();
+        let mut in_section = false;
+
+        for line in entry.lines() {
+            if line.is_empty() {
+                in_section = false;
+            } else if in_section {
+                sections.last_mut().unwrap().1.push(line.to_string());
+            } else if line.len() > 2 && line.starts_with('%') && line.ends_with('%') {
+                sections.push((&line[1..line.len() - 1], Vec::new()));
+                in_section = true;
+            } else {
+                return invalid(format!("expected %KEY%, got '{}'", line));
+            }
+        }
+
+        for (key, values) in sections {
+            let Some(first) = values.first().cloned() else {
+                continue;
+            };
+            let number = || match first.parse::<i64>() {
+                Ok(n) => Ok(n),
+                Err(_) => invalid(format!("{} is not a number", key)),
+            };
+
+            match key {
+                "FILENAME" => self.filename = first,
+                "NAME" => self.name = first,
+                "BASE" => self.base = Some(first),
+                "VERSION" => self.version = first,
+                "DESC" => self.desc = Some(first),
+                "GROUPS" => self.groups = values,
+                "CSIZE" => self.csize = number()?,
+                "ISIZE" => self.isize = number()?,
+                "MD5SUM" => self.md5sum = Some(first),
+                "SHA256SUM" => self.sha256sum = Some(first),
+                "PGPSIG" => self.pgpsig = Some(first),
+                "URL" => self.url = Some(first),
+                "LICENSE" => self.licenses = values,
+                "ARCH" => self.arch = Some(first),
+                "BUILDDATE" => self.builddate = number()?,
+                "PACKAGER" => self.packager = Some(first),
+                "REPLACES" => self.replaces = values,
+                "CONFLICTS" => self.conflicts = values,
+                "PROVIDES" => self.provides = values,
+                "DEPENDS" => self.depends = values,
+                "OPTDEPENDS" => self.optdepends = values,
+                "MAKEDEPENDS" => self.makedepends = values,
+                "CHECKDEPENDS" => self.checkdepends = values,
+                "FILES" => self.files = values,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An error reading a sync database archive.
+#[derive(Debug)]
+pub enum SyncArchiveError {
+    /// Reading the file or archive failed.
+    Io(io::Error),
+    /// An entry of the archive is corrupt.
+    Invalid(String),
+}
+
+impl fmt::Display for SyncArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncArchiveError::Io(e) => e.fmt(f),
+            SyncArchiveError::Invalid(msg) => write!(f, "invalid database archive: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SyncArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SyncArchiveError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SyncArchiveError {
+    fn from(e: io::Error) -> SyncArchiveError {
+        SyncArchiveError::Io(e)
+    }
+}
+
+fn invalid<T, S: Into<String>>(msg: S) -> Result<T, SyncArchiveError> {
+    Err(SyncArchiveError::Invalid(msg.into()))
+}
+
+/// The packages of a sync database read without a libalpm handle.
+///
+/// The archive is read through libarchive so any compression it supports can be used. Both
+/// db and files databases are supported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncArchive {
+    /// The packages in the order they appear in the archive.
+    pub packages: Vec<RepoPackage>,
+}
+
+/// The differences between two [`SyncArchive`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncArchiveDiff<'a> {
+    /// Packages only in the new archive.
+    pub added: Vec<&'a RepoPackage>,
+    /// Packages only in the old archive.
+    pub removed: Vec<&'a RepoPackage>,
+    /// Packages whose entries differ as (old, new).
+    pub changed: Vec<(&'a RepoPackage, &'a RepoPackage)>,
+}
+
+impl SyncArchive {
+    /// Reads a database file such as `/var/lib/pacman/sync/core.db`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<SyncArchive, SyncArchiveError> {
+        SyncArchive::from_archive(ArchiveReader::open(path.as_ref())?)
+    }
+
+    /// Reads a database from memory.
+    pub fn from_bytes(data: Vec<u8>) -> Result<SyncArchive, SyncArchiveError> {
+        SyncArchive::from_archive(ArchiveReader::from_bytes(data)?)
+    }
+
+    fn from_archive(mut archive: ArchiveReader) -> Result<SyncArchive, SyncArchiveError> {
+        let mut packages = Vec::<RepoPackage>::new();
+        let mut index = HashMap::new();
+
+        while let Some(path) = archive.next_file()? {
+            let path = path.trim_start_matches("./");
+            let Some((dir, _)) = path.split_once('/') else {
+                continue;
+            };
+
+            let mut data = String::new();
+            archive
+                .read_to_string(&mut data)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::InvalidData => {
+                        SyncArchiveError::Invalid(format!("{} is not utf-8", path))
+                    }
+                    _ => SyncArchiveError::Io(e),
+                })?;
+            if data.is_empty() {
+                continue;
+            }
+
+            let i = *index.entry(dir.to_string()).or_insert_with(|| {
+                packages.push(RepoPackage::default());
+                packages.len() - 1
+            });
+            packages[i].parse_entry(&data)?;
+        }
+
+        Ok(SyncArchive { packages })
+    }
+
+    /// Finds a package by name.
+    pub fn package(&self, name: &str) -> Option<&RepoPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Compares this archive, the old one, to a newer one.
+    pub fn diff<'a>(&'a self, new: &'a SyncArchive) -> SyncArchiveDiff<'a> {
+        let old_pkgs = self
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p))
+            .collect::<HashMap<_, _>>();
+        let new_pkgs = new
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p))
+            .collect::<HashMap<_, _>>();
+
+        let mut diff = SyncArchiveDiff::default();
+        for pkg in &new.packages {
+            match old_pkgs.get(pkg.name.as_str()) {
+                None => diff.added.push(pkg),
+                Some(old) if *old != pkg => diff.changed.push((old, pkg)),
+                Some(_) => (),
+            }
+        }
+        for pkg in &self.packages {
+            if !new_pkgs.contains_key(pkg.name.as_str()) {
+                diff.removed.push(pkg);
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use crate::{ArchiveWriter, Compression};
+    use std::fs;
+
+    const DBS: &[&str] = &[
+        "core",
+        "extra",
+        "community-testing",
+        "multilib",
+        "multilib-testing",
+        "testing",
+    ];
+
+    #[test]
+    fn test_read() {
+        let core = SyncArchive::read("../alpm/tests/db/sync/core.db").unwrap();
+        assert_eq!(core.packages.len(), 231);
+
+        let linux = core.package("linux").unwrap();
+        assert_eq!(linux.version, "5.1.8.arch1-1");
+        assert_eq!(linux.filename, "linux-5.1.8.arch1-1-x86_64.pkg.tar.xz");
+        assert_eq!(
+            linux.depends,
+            ["coreutils", "linux-firmware", "kmod", "mkinitcpio"]
+        );
+        assert!(linux.sha256sum.is_some());
+        assert!(linux.pgpsig.is_some());
+        assert!(linux.files.is_empty());
+
+        for db in DBS {
+            SyncArchive::read(format!("../alpm/tests/db/sync/{}.db", db)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let core = SyncArchive::read("../alpm/tests/db/sync/core.db").unwrap();
+        let mut linux = core.package("linux").unwrap().clone();
+        linux.files = vec!["boot/".into(), "boot/vmlinuz-linux".into()];
+
+        let mut parsed = RepoPackage::default();
+        parsed.parse_entry(&linux.desc_entry()).unwrap();
+        parsed.parse_entry(&linux.files_entry()).unwrap();
+        assert_eq!(parsed, linux);
+
+        assert!(parsed.parse_entry("NAME\nfoo\n").is_err());
+        assert!(parsed.parse_entry("%CSIZE%\nbig\n").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = SyncArchive::read("../alpm/tests/db/sync/core.db").unwrap();
+        let mut new = old.clone();
+        new.packages.retain(|p| p.name != "linux");
+        new.packages[0].version = "1000-1".into();
+        new.packages.push(RepoPackage {
+            name: "foo".into(),
+            ..Default::default()
+        });
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "foo");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "linux");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.version, "1000-1");
+        assert!(old.diff(&old).changed.is_empty());
+    }
+
+    #[test]
+    fn test_read_corrupt() {
+        let data = fs::read("../alpm/tests/db/sync/core.db").unwrap();
+        assert!(SyncArchive::from_bytes(data.clone()).is_ok());
+
+        assert!(
+            SyncArchive::from_bytes(Vec::new())
+                .unwrap()
+                .packages
+                .is_empty()
+        );
+        for len in [10, 100, data.len() / 2] {
+            assert!(SyncArchive::from_bytes(data[..len].to_vec()).is_err());
+        }
+        assert!(SyncArchive::from_bytes(b"not an archive".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_compression() {
+        let tmp = TempDir::new("alpm-utils-sync-archive").unwrap();
+        let core = SyncArchive::read("../alpm/tests/db/sync/core.db").unwrap();
+
+        for compression in [Compression::None, Compression::Xz, Compression::Zstd] {
+            let path = tmp
+                .path()
+                .join(format!("core.db.tar{}", compression.extension()));
+            let mut writer = ArchiveWriter::create(&path, compression).unwrap();
+            for pkg in &core.packages {
+                let dir = format!("{}-{}/", pkg.name, pkg.version);
+                writer.add_dir(&dir, 0o755, 0).unwrap();
+                let desc = pkg.desc_entry();
+                let path = format!("{}desc", dir);
+                writer.add_file(&path, desc.as_bytes(), 0o644, 0).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let read = SyncArchive::read(path).unwrap();
+            assert_eq!(read.packages.len(), core.packages.len());
+            assert_eq!(read.package("linux"), core.package("linux"));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alpm")]
+    fn test_matches_libalpm() {
+        use alpm::{Alpm, SigLevel};
+
+        let handle = Alpm::new("/", "../alpm/tests/db").unwrap();
+
+        for name in DBS {
+            let db = handle.register_syncdb(*name, SigLevel::NONE).unwrap();
+            let archive = SyncArchive::read(format!("../alpm/tests/db/sync/{}.db", name)).unwrap();
+            assert_eq!(archive.packages.len(), db.pkgs().len());
+
+            for pkg in db.pkgs() {
+                let mut expected = RepoPackage::from_pkg(pkg);
+                let mut actual = archive.package(pkg.name()).unwrap().clone();
+                if cfg!(feature = "git") {
+                    expected.md5sum = None;
+                    actual.md5sum = None;
+                }
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+}
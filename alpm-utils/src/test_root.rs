@@ -0,0 +1,418 @@
+use crate::repo::section;
+use crate::tempdir::TempDir;
+use crate::{Repo, RepoPackage};
+use alpm::{Alpm, PackageReason, SigLevel};
+
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+// md5 and sha256 of an empty file, every file in a test root is empty
+const EMPTY_MD5: &str = "d41d8cd98f00b204e9800998ecf8427e";
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// A package used to populate a [`TestRoot`].
+///
+/// ```
+/// use alpm::PackageReason;
+/// use alpm_utils::TestPackage;
+///
+/// let pkg = TestPackage::new("foo", "1.0-1")
+///     .depends(["bar>=2", "baz"])
+///     .files(["usr/bin/foo"])
+///     .reason(PackageReason::Depend);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestPackage {
+    /// The package's database entry.
+    pub pkg: RepoPackage,
+    /// The install reason used when the package is installed.
+    pub reason: PackageReason,
+}
+
+fn strings<S: Into<String>, I: IntoIterator<Item = S>>(iter: I) -> Vec<String> {
+    iter.into_iter().map(Into::into).collect()
+}
+
+impl TestPackage {
+    /// Creates an explicitly installed x86_64 package.
+    pub fn new<S: Into<String>, V: Into<String>>(name: S, version: V) -> TestPackage {
+        let name = name.into();
+        let version = version.into();
+
+        TestPackage {
+            pkg: RepoPackage {
+                filename: format!("{}-{}-x86_64.pkg.tar.zst", name, version),
+                base: Some(name.clone()),
+                desc: Some(format!("{} test package", name)),
+                arch: Some("x86_64".into()),
+                builddate: 1,
+                name,
+                version,
+                ..Default::default()
+            },
+            reason: PackageReason::Explicit,
+        }
+    }
+
+    /// Sets the description.
+    pub fn desc<S: Into<String>>(mut self, desc: S) -> Self {
+        self.pkg.desc = Some(desc.into());
+        self
+    }
+
+    /// Sets the architecture.
+    pub fn arch<S: Into<String>>(mut self, arch: S) -> Self {
+        self.pkg.arch = Some(arch.into());
+        self
+    }
+
+    /// Sets the installed size.
+    pub fn size(mut self, size: i64) -> Self {
+        self.pkg.isize = size;
+        self
+    }
+
+    /// Sets the install reason.
+    pub fn reason(mut self, reason: PackageReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// Sets the groups.
+    pub fn groups<S: Into<String>, I: IntoIterator<Item = S>>(mut self, groups: I) -> Self {
+        self.pkg.groups = strings(groups);
+        self
+    }
+
+    /// Sets the dependencies.
+    pub fn depends<S: Into<String>, I: IntoIterator<Item = S>>(mut self, deps: I) -> Self {
+        self.pkg.depends = strings(deps);
+        self
+    }
+
+    /// Sets the optional dependencies.
+    pub fn optdepends<S: Into<String>, I: IntoIterator<Item = S>>(mut self, deps: I) -> Self {
+        self.pkg.optdepends = strings(deps);
+        self
+    }
+
+    /// Sets what the package provides.
+    pub fn provides<S: Into<String>, I: IntoIterator<Item = S>>(mut self, deps: I) -> Self {
+        self.pkg.provides = strings(deps);
+        self
+    }
+
+    /// Sets what the package conflicts with.
+    pub fn conflicts<S: Into<String>, I: IntoIterator<Item = S>>(mut self, deps: I) -> Self {
+        self.pkg.conflicts = strings(deps);
+        self
+    }
+
+    /// Sets what the package replaces.
+    pub fn replaces<S: Into<String>, I: IntoIterator<Item = S>>(mut self, deps: I) -> Self {
+        self.pkg.replaces = strings(deps);
+        self
+    }
+
+    /// Sets the files in the package.
+    ///
+    /// Paths ending in `/` are directories. Parent directories are added automatically.
+    /// Paths must be relative and may not contain `..`, [`TestRootBuilder::build`] fails
+    /// otherwise.
+    pub fn files<S: Into<String>, I: IntoIterator<Item = S>>(mut self, files: I) -> Self {
+        let mut all = Vec::new();
+        for file in files {
+            let file = file.into();
+            for (i, _) in file.match_indices('/') {
+                all.push(file[..=i].to_string());
+            }
+            all.push(file);
+        }
+        all.sort();
+        all.dedup();
+        self.pkg.files = all;
+        self
+    }
+
+    fn local_desc(&self) -> String {
+        let pkg = &self.pkg;
+        let mut s = String::new();
+        section(&mut s, "NAME", [&pkg.name]);
+        section(&mut s, "VERSION", [&pkg.version]);
+        section(&mut s, "BASE", &pkg.base);
+        section(&mut s, "DESC", &pkg.desc);
+        section(&mut s, "URL", &pkg.url);
+        section(&mut s, "ARCH", &pkg.arch);
+        section(&mut s, "BUILDDATE", [pkg.builddate]);
+        section(&mut s, "INSTALLDATE", [pkg.builddate]);
+        section(&mut s, "PACKAGER", &pkg.packager);
+        section(&mut s, "SIZE", [pkg.isize]);
+        if self.reason == PackageReason::Depend {
+            section(&mut s, "REASON", [1]);
+        }
+        section(&mut s, "GROUPS", &pkg.groups);
+        section(&mut s, "LICENSE", &pkg.licenses);
+        section(&mut s, "VALIDATION", ["none"]);
+        section(&mut s, "REPLACES", &pkg.replaces);
+        section(&mut s, "DEPENDS", &pkg.depends);
+        section(&mut s, "OPTDEPENDS", &pkg.optdepends);
+        section(&mut s, "CONFLICTS", &pkg.conflicts);
+        section(&mut s, "PROVIDES", &pkg.provides);
+        s
+    }
+
+    fn mtree(&self) -> String {
+        let mut s = String::from("#mtree\n/set type=file uid=0 gid=0 mode=644\n");
+        for file in &self.pkg.files {
+            match file.strip_suffix('/') {
+                Some(dir) => s.push_str(&format!("./{} time=1.0 mode=755 type=dir\n", dir)),
+                None => s.push_str(&format!(
+                    "./{} time=1.0 size=0 md5digest={} sha256digest={}\n",
+                    file, EMPTY_MD5, EMPTY_SHA256
+                )),
+            }
+        }
+        s
+    }
+}
+
+/// Builds a [`TestRoot`].
+#[derive(Debug, Clone, Default)]
+pub struct TestRootBuilder {
+    local: Vec<TestPackage>,
+    sync: Vec<(String, Vec<TestPackage>)>,
+}
+
+impl TestRootBuilder {
+    /// Installs a package into the local db and writes its files to the root.
+    pub fn local(mut self, pkg: TestPackage) -> Self {
+        self.local.push(pkg);
+        self
+    }
+
+    /// Adds a package to a sync db. The db is created if it does not exist yet.
+    ///
+    /// Sync dbs are registered in the order they are first used.
+    pub fn sync<S: Into<String>>(mut self, db: S, pkg: TestPackage) -> Self {
+        let db = db.into();
+        match self.sync.iter_mut().find(|(name, _)| *name == db) {
+            Some((_, pkgs)) => pkgs.push(pkg),
+            None => self.sync.push((db, vec![pkg])),
+        }
+        self
+    }
+
+    /// Adds an empty sync db.
+    pub fn sync_db<S: Into<String>>(mut self, db: S) -> Self {
+        let db = db.into();
+        if !self.sync.iter().any(|(name, _)| *name == db) {
+            self.sync.push((db, Vec::new()));
+        }
+        self
+    }
+
+    /// Creates the root and dbpath in a temporary directory.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if a package has a file outside the root.
+    pub fn build(self) -> io::Result<TestRoot> {
+        let pkgs = self.sync.iter().flat_map(|(_, pkgs)| pkgs);
+        for pkg in self.local.iter().chain(pkgs) {
+            if let Some(file) = pkg.pkg.files.iter().find(|f| !is_relative(f)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: file outside the root: {}", pkg.pkg.name, file),
+                ));
+            }
+        }
+
+        let dir = TempDir::new("alpm-utils-root")?;
+        let root = TestRoot {
+            sync: self.sync.iter().map(|(name, _)| name.clone()).collect(),
+            dir,
+        };
+
+        let local = root.dbpath().join("local");
+        fs::create_dir_all(&local)?;
+        fs::create_dir_all(root.root())?;
+        fs::write(local.join("ALPM_DB_VERSION"), "9\n")?;
+
+        for pkg in &self.local {
+            let entry = local.join(format!("{}-{}", pkg.pkg.name, pkg.pkg.version));
+            fs::create_dir_all(&entry)?;
+            fs::write(entry.join("desc"), pkg.local_desc())?;
+            fs::write(entry.join("files"), pkg.pkg.files_entry())?;
+            // libalpm reads the mtree through libarchive, it does not have to be compressed
+            fs::write(entry.join("mtree"), pkg.mtree())?;
+
+            for file in &pkg.pkg.files {
+                let path = root.root().join(file);
+                if file.ends_with('/') {
+                    fs::create_dir_all(path)?;
+                } else {
+                    fs::File::create(path)?;
+                }
+            }
+        }
+
+        for (name, pkgs) in self.sync {
+            let mut repo = Repo::new(root.dbpath().join("sync"), name);
+            for pkg in pkgs {
+                repo.add_package(pkg.pkg);
+            }
+            repo.write()?;
+        }
+
+        Ok(root)
+    }
+}
+
+fn is_relative(file: &str) -> bool {
+    Path::new(file)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// A temporary root and dbpath for tests.
+///
+/// The directory is removed when the `TestRoot` is dropped.
+///
+/// ```
+/// use alpm_utils::{TestPackage, TestRoot};
+///
+/// let root = TestRoot::builder()
+///     .local(TestPackage::new("foo", "1.0-1").files(["usr/bin/foo"]))
+///     .sync("core", TestPackage::new("foo", "1.1-1"))
+///     .build()
+///     .unwrap();
+///
+/// let alpm = root.alpm().unwrap();
+/// assert!(alpm.localdb().pkg("foo").is_ok());
+/// assert!(root.root().join("usr/bin/foo").exists());
+/// ```
+#[derive(Debug)]
+pub struct TestRoot {
+    dir: TempDir,
+    sync: Vec<String>,
+}
+
+impl TestRoot {
+    /// Creates a builder for a test root.
+    pub fn builder() -> TestRootBuilder {
+        TestRootBuilder::default()
+    }
+
+    /// The root directory.
+    pub fn root(&self) -> PathBuf {
+        self.dir.path().join("root")
+    }
+
+    /// The database directory.
+    pub fn dbpath(&self) -> PathBuf {
+        self.dir.path().join("db")
+    }
+
+    /// The temporary directory containing the root and dbpath.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Creates a handle using the root and dbpath with every sync db registered.
+    ///
+    /// Signature checking is disabled.
+    pub fn alpm(&self) -> alpm::Result<Alpm> {
+        let root = self.root();
+        let dbpath = self.dbpath();
+        let alpm = Alpm::new(root.as_os_str().as_bytes(), dbpath.as_os_str().as_bytes())?;
+        alpm.set_default_siglevel(SigLevel::NONE)?;
+        alpm.set_local_file_siglevel(SigLevel::NONE)?;
+        alpm.set_remote_file_siglevel(SigLevel::NONE)?;
+        for db in &self.sync {
+            alpm.register_syncdb(db.as_str(), SigLevel::NONE)?;
+        }
+        Ok(alpm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SyncArchive;
+
+    #[test]
+    fn test_root() {
+        let root = TestRoot::builder()
+            .local(
+                TestPackage::new("foo", "1.0-1")
+                    .depends(["bar"])
+                    .files(["usr/bin/foo", "usr/share/foo/"]),
+            )
+            .local(TestPackage::new("bar", "1.0-1").reason(PackageReason::Depend))
+            .local(TestPackage::new("orphan", "1.0-1").reason(PackageReason::Depend))
+            .sync("core", TestPackage::new("foo", "1.1-1"))
+            .sync(
+                "extra",
+                TestPackage::new("baz", "2.0-1").provides(["bar=1.0"]),
+            )
+            .sync_db("empty")
+            .build()
+            .unwrap();
+
+        assert!(root.root().join("usr/bin/foo").is_file());
+        assert!(root.root().join("usr/share/foo").is_dir());
+
+        let alpm = root.alpm().unwrap();
+        let foo = alpm.localdb().pkg("foo").unwrap();
+        assert_eq!(foo.version().as_str(), "1.0-1");
+        assert_eq!(foo.files().files().len(), 5);
+        assert_eq!(
+            alpm.localdb().pkg("orphan").unwrap().reason(),
+            PackageReason::Depend
+        );
+
+        let dbs = alpm.syncdbs();
+        assert_eq!(dbs.len(), 3);
+        assert_eq!(
+            dbs.iter().map(|db| db.name()).collect::<Vec<_>>(),
+            ["core", "extra", "empty"]
+        );
+        let new = alpm.syncdbs().find_satisfier("bar").unwrap();
+        assert_eq!(new.name(), "baz");
+        assert_eq!(
+            foo.sync_new_version(alpm.syncdbs())
+                .unwrap()
+                .version()
+                .as_str(),
+            "1.1-1"
+        );
+
+        let core = SyncArchive::read(root.dbpath().join("sync/core.db")).unwrap();
+        assert_eq!(core.packages[0].name, "foo");
+
+        let path = root.path().to_path_buf();
+        drop(alpm);
+        drop(root);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_root_outside_files() {
+        for file in ["../escape", "usr/../../escape", "/etc/passwd"] {
+            let err = TestRoot::builder()
+                .local(TestPackage::new("foo", "1.0-1").files([file]))
+                .build()
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let err = TestRoot::builder()
+            .sync(
+                "core",
+                TestPackage::new("foo", "1.0-1").files(["../escape"]),
+            )
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
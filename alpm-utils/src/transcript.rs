@@ -0,0 +1,332 @@
+use alpm::{AnyEvent, Event, HookWhen, PackageOperation};
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The kind of operation done on a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationKind {
+    /// The package is installed.
+    Install,
+    /// The package is upgraded.
+    Upgrade,
+    /// The package is reinstalled.
+    Reinstall,
+    /// The package is downgraded.
+    Downgrade,
+    /// The package is removed.
+    Remove,
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OperationKind::Install => "install",
+            OperationKind::Upgrade => "upgrade",
+            OperationKind::Reinstall => "reinstall",
+            OperationKind::Downgrade => "downgrade",
+            OperationKind::Remove => "remove",
+        })
+    }
+}
+
+/// The output recorded while a package was processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageTranscript {
+    /// The name of the package.
+    pub name: String,
+    /// What was done to the package.
+    pub operation: OperationKind,
+    /// The version before the operation, if the package was installed.
+    pub old_version: Option<String>,
+    /// The version after the operation, if the package is installed.
+    pub new_version: Option<String>,
+    /// Lines printed by the package's scriptlets.
+    pub lines: Vec<String>,
+    /// How long the operation took, None if it did not finish.
+    pub duration: Option<Duration>,
+}
+
+/// The output recorded while a hook ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookTranscript {
+    /// The name of the hook.
+    pub name: String,
+    /// The description of the hook.
+    pub desc: Option<String>,
+    /// Whether the hook ran before or after the transaction.
+    pub when: Option<HookWhen>,
+    /// The position of the hook, starting at 1.
+    pub position: usize,
+    /// The number of hooks run at the same time as this one.
+    pub total: usize,
+    /// Lines printed by the hook.
+    pub lines: Vec<String>,
+    /// How long the hook took, None if it did not finish.
+    pub duration: Option<Duration>,
+}
+
+/// Everything recorded by a [`TranscriptRecorder`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranscriptReport {
+    /// Packages in the order they were processed.
+    pub packages: Vec<PackageTranscript>,
+    /// Hooks in the order they ran.
+    pub hooks: Vec<HookTranscript>,
+    /// Scriptlet lines printed outside of any package operation or hook.
+    pub unassigned: Vec<String>,
+}
+
+impl TranscriptReport {
+    /// Finds the transcript of a package.
+    pub fn package(&self, name: &str) -> Option<&PackageTranscript> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Finds the transcript of a hook.
+    pub fn hook(&self, name: &str) -> Option<&HookTranscript> {
+        self.hooks.iter().find(|h| h.name == name)
+    }
+}
+
+impl fmt::Display for TranscriptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pkg in &self.packages {
+            write!(f, "{} {}", pkg.operation, pkg.name)?;
+            match (&pkg.old_version, &pkg.new_version) {
+                (Some(old), Some(new)) => write!(f, " ({} -> {})", old, new)?,
+                (Some(ver), None) | (None, Some(ver)) => write!(f, " ({})", ver)?,
+                (None, None) => (),
+            }
+            write_duration(f, pkg.duration)?;
+            for line in &pkg.lines {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        for hook in &self.hooks {
+            write!(f, "hook ({}/{}) {}", hook.position, hook.total, hook.name)?;
+            if let Some(desc) = &hook.desc {
+                write!(f, ": {}", desc)?;
+            }
+            write_duration(f, hook.duration)?;
+            for line in &hook.lines {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        for line in &self.unassigned {
+            writeln!(f, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_duration(f: &mut fmt::Formatter<'_>, duration: Option<Duration>) -> fmt::Result {
+    match duration {
+        Some(d) => writeln!(f, " [{:.3}s]", d.as_secs_f64()),
+        None => writeln!(f, " [unfinished]"),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Current {
+    Package(usize, Instant),
+    Hook(usize, Instant),
+}
+
+/// Records the output of scriptlets and hooks during a transaction.
+///
+/// Scriptlet lines are attributed to the package operation or hook run that was in
+/// progress when they were printed.
+///
+/// ```no_run
+/// use alpm::Alpm;
+/// use alpm_utils::TranscriptRecorder;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// let alpm = Alpm::new("/", "/var/lib/pacman").unwrap();
+/// let recorder = Rc::new(RefCell::new(TranscriptRecorder::new()));
+/// alpm.set_event_cb(recorder.clone(), |event, recorder| {
+///     recorder.borrow_mut().on_event(&event)
+/// });
+///
+/// // run the transaction
+///
+/// print!("{}", recorder.borrow().report());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptRecorder {
+    report: TranscriptReport,
+    current: Option<Current>,
+    when: Option<HookWhen>,
+}
+
+impl TranscriptRecorder {
+    /// Creates an empty recorder.
+    pub fn new() -> TranscriptRecorder {
+        TranscriptRecorder::default()
+    }
+
+    /// Handles an event from the event callback.
+    pub fn on_event(&mut self, event: &AnyEvent) {
+        match event.event() {
+            Event::PackageOperationStart(op) => {
+                let (kind, name, old, new) = match op.operation() {
+                    PackageOperation::Install(new) => {
+                        (OperationKind::Install, new.name(), None, Some(new))
+                    }
+                    PackageOperation::Upgrade(new, old) => {
+                        (OperationKind::Upgrade, new.name(), Some(old), Some(new))
+                    }
+                    PackageOperation::Reinstall(new, old) => {
+                        (OperationKind::Reinstall, new.name(), Some(old), Some(new))
+                    }
+                    PackageOperation::Downgrade(new, old) => {
+                        (OperationKind::Downgrade, new.name(), Some(old), Some(new))
+                    }
+                    PackageOperation::Remove(old) => {
+                        (OperationKind::Remove, old.name(), Some(old), None)
+                    }
+                };
+                self.package_start(
+                    kind,
+                    name,
+                    old.map(|p| p.version().as_str()),
+                    new.map(|p| p.version().as_str()),
+                );
+            }
+            Event::PackageOperationDone(_) => self.package_done(),
+            Event::ScriptletInfo(info) => self.scriptlet_line(info.line()),
+            Event::HookStart(hook) => self.when = Some(hook.when()),
+            Event::HookDone(_) => self.when = None,
+            Event::HookRunStart(run) => {
+                self.hook_run_start(run.name(), run.desc(), run.position(), run.total())
+            }
+            Event::HookRunDone(_) => self.hook_run_done(),
+            _ => (),
+        }
+    }
+
+    /// Records the start of a package operation.
+    pub fn package_start(
+        &mut self,
+        operation: OperationKind,
+        name: &str,
+        old_version: Option<&str>,
+        new_version: Option<&str>,
+    ) {
+        self.report.packages.push(PackageTranscript {
+            name: name.to_string(),
+            operation,
+            old_version: old_version.map(|s| s.to_string()),
+            new_version: new_version.map(|s| s.to_string()),
+            lines: Vec::new(),
+            duration: None,
+        });
+        let idx = self.report.packages.len() - 1;
+        self.current = Some(Current::Package(idx, Instant::now()));
+    }
+
+    /// Records the end of the current package operation.
+    pub fn package_done(&mut self) {
+        if let Some(Current::Package(idx, start)) = self.current {
+            self.report.packages[idx].duration = Some(start.elapsed());
+            self.current = None;
+        }
+    }
+
+    /// Records the start of a hook.
+    pub fn hook_run_start(
+        &mut self,
+        name: &str,
+        desc: Option<&str>,
+        position: usize,
+        total: usize,
+    ) {
+        self.report.hooks.push(HookTranscript {
+            name: name.to_string(),
+            desc: desc.map(|s| s.to_string()),
+            when: self.when,
+            position,
+            total,
+            lines: Vec::new(),
+            duration: None,
+        });
+        let idx = self.report.hooks.len() - 1;
+        self.current = Some(Current::Hook(idx, Instant::now()));
+    }
+
+    /// Records the end of the current hook.
+    pub fn hook_run_done(&mut self) {
+        if let Some(Current::Hook(idx, start)) = self.current {
+            self.report.hooks[idx].duration = Some(start.elapsed());
+            self.current = None;
+        }
+    }
+
+    /// Records a line of scriptlet or hook output.
+    pub fn scriptlet_line(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\n', '\r']).to_string();
+        match self.current {
+            Some(Current::Package(idx, _)) => self.report.packages[idx].lines.push(line),
+            Some(Current::Hook(idx, _)) => self.report.hooks[idx].lines.push(line),
+            None => self.report.unassigned.push(line),
+        }
+    }
+
+    /// The report recorded so far.
+    pub fn report(&self) -> &TranscriptReport {
+        &self.report
+    }
+
+    /// Consumes the recorder and returns the report.
+    pub fn finish(self) -> TranscriptReport {
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_recorder() {
+        let mut recorder = TranscriptRecorder::new();
+
+        recorder.scriptlet_line("before\n");
+        recorder.package_start(OperationKind::Upgrade, "foo", Some("1-1"), Some("1-2"));
+        recorder.scriptlet_line("==> foo post_upgrade\n");
+        recorder.package_done();
+        recorder.package_start(OperationKind::Remove, "bar", Some("2-1"), None);
+        recorder.package_done();
+        recorder.when = Some(HookWhen::PostTransaction);
+        recorder.hook_run_start("systemd-update", Some("Reloading units"), 1, 2);
+        recorder.scriptlet_line("reloaded");
+        recorder.hook_run_done();
+        recorder.hook_run_start("ldconfig", None, 2, 2);
+
+        let report = recorder.finish();
+        assert_eq!(report.unassigned, ["before"]);
+        let foo = report.package("foo").unwrap();
+        assert_eq!(foo.lines, ["==> foo post_upgrade"]);
+        assert!(foo.duration.is_some());
+        assert!(report.package("bar").unwrap().lines.is_empty());
+
+        let hook = report.hook("systemd-update").unwrap();
+        assert_eq!(hook.when, Some(HookWhen::PostTransaction));
+        assert_eq!((hook.position, hook.total), (1, 2));
+        assert_eq!(hook.lines, ["reloaded"]);
+        assert!(report.hook("ldconfig").unwrap().duration.is_none());
+
+        let text = report.to_string();
+        assert!(text.contains("upgrade foo (1-1 -> 1-2) ["));
+        assert!(text.contains("\n  ==> foo post_upgrade\n"));
+        assert!(text.contains("remove bar (2-1) ["));
+        assert!(text.contains("hook (1/2) systemd-update: Reloading units ["));
+        assert!(text.contains("hook (2/2) ldconfig [unfinished]\n"));
+        assert!(text.ends_with("before\n"));
+    }
+}
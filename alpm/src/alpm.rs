@@ -16,7 +16,8 @@ unsafe extern "C" {
 #[allow(dead_code)]
 pub struct Alpm {
     handle: NonNull<alpm_handle_t>,
-    pub(crate) cbs: Callbacks,
+    // boxed so the callbacks can point to it while the handle moves
+    pub(crate) cbs: Box<Callbacks>,
 }
 
 impl std::fmt::Debug for Alpm {
@@ -56,7 +57,7 @@ impl Alpm {
             None => unsafe { Err(Error::new(err)) },
             Some(handle) => Ok(Alpm {
                 handle,
-                cbs: Callbacks::default(),
+                cbs: Box::default(),
             }),
         }
     }
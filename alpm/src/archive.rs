@@ -0,0 +1,406 @@
+use crate::LoadedPackage;
+
+use libarchive3_sys::ffi::*;
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt, symlink};
+use std::path::{Component, Path, PathBuf};
+use std::ptr::{self, NonNull};
+
+const AE_IFMT: u32 = 0o170000;
+const AE_IFREG: u32 = 0o100000;
+const AE_IFLNK: u32 = 0o120000;
+const AE_IFDIR: u32 = 0o040000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Hardlink,
+    Other,
+}
+
+/// A package file opened for reading.
+///
+/// Entries are read in the order they are stored in the archive.
+#[derive(Debug)]
+pub struct PackageArchive {
+    archive: NonNull<Struct_archive>,
+}
+
+impl Drop for PackageArchive {
+    fn drop(&mut self) {
+        unsafe { archive_read_free(self.as_ptr()) };
+    }
+}
+
+impl PackageArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PackageArchive> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+        let archive = NonNull::new(unsafe { archive_read_new() })
+            .ok_or_else(|| io::Error::other("failed to create archive reader"))?;
+        let archive = PackageArchive { archive };
+
+        unsafe {
+            archive_read_support_filter_all(archive.as_ptr());
+            archive_read_support_format_all(archive.as_ptr());
+            if archive_read_open_filename(archive.as_ptr(), path.as_ptr(), 16384) != ARCHIVE_OK {
+                return Err(archive.error());
+            }
+        }
+
+        Ok(archive)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut Struct_archive {
+        self.archive.as_ptr()
+    }
+
+    fn error(&self) -> io::Error {
+        let msg = unsafe { archive_error_string(self.as_ptr()) };
+        if msg.is_null() {
+            io::Error::other("failed to read archive")
+        } else {
+            let msg = unsafe { CStr::from_ptr(msg) };
+            io::Error::other(msg.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Reads the header of the next entry. Returns None at the end of the archive.
+    ///
+    /// The contents of the previous entry can no longer be read once this is called.
+    pub fn next_entry(&mut self) -> io::Result<Option<ArchiveEntry<'_>>> {
+        let mut entry = ptr::null_mut();
+        match unsafe { archive_read_next_header(self.as_ptr(), &mut entry) } {
+            ARCHIVE_OK | ARCHIVE_WARN => (),
+            ARCHIVE_EOF => return Ok(None),
+            _ => return Err(self.error()),
+        }
+
+        let path = unsafe { cstr_to_string(archive_entry_pathname(entry)) }.unwrap_or_default();
+        let hardlink = unsafe { cstr_to_string(archive_entry_hardlink(entry)) };
+        let symlink = unsafe { cstr_to_string(archive_entry_symlink(entry)) };
+        let filetype = unsafe { archive_entry_filetype(entry) } as u32 & AE_IFMT;
+
+        let (kind, link) = match (hardlink, symlink) {
+            (Some(target), _) => (EntryKind::Hardlink, Some(target)),
+            (None, Some(target)) if filetype == AE_IFLNK => (EntryKind::Symlink, Some(target)),
+            _ if filetype == AE_IFREG => (EntryKind::File, None),
+            _ if filetype == AE_IFDIR => (EntryKind::Dir, None),
+            _ => (EntryKind::Other, None),
+        };
+
+        Ok(Some(ArchiveEntry {
+            path,
+            kind,
+            link,
+            size: unsafe { archive_entry_size(entry) } as u64,
+            mode: unsafe { archive_entry_perm(entry) } as u32,
+            mtime: unsafe { archive_entry_mtime(entry) } as i64,
+            archive: self,
+        }))
+    }
+
+    /// Reads the contents of the file at `path` in the archive.
+    ///
+    /// Paths are relative to the root of the package such as `usr/bin/pacman`.
+    pub fn read_file(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+
+        while let Some(mut entry) = self.next_entry()? {
+            if entry.path().trim_start_matches("./") == path {
+                // the size comes from the archive, don't trust it for more than a guess
+                let mut data = Vec::with_capacity(entry.size().min(1 << 20) as usize);
+                entry.read_to_end(&mut data)?;
+                return Ok(data);
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found in archive", path),
+        ))
+    }
+
+    /// Extracts the entries for which `select` returns true into `dest`.
+    ///
+    /// Entries with absolute paths or paths containing `..` are rejected. Symlinks are never
+    /// followed while extracting, so an entry below a symlink, including one extracted
+    /// earlier, is rejected too. Existing files are replaced. Returns the paths that were
+    /// extracted.
+    pub fn extract<P: AsRef<Path>, F: FnMut(&ArchiveEntry) -> bool>(
+        &mut self,
+        dest: P,
+        mut select: F,
+    ) -> io::Result<Vec<PathBuf>> {
+        let dest = dest.as_ref();
+        let mut extracted = Vec::new();
+        fs::create_dir_all(dest)?;
+
+        while let Some(mut entry) = self.next_entry()? {
+            if !select(&entry) {
+                continue;
+            }
+
+            let path = safe_join(entry.path())?;
+            let out = dest.join(&path);
+            if entry.kind() == EntryKind::Dir {
+                create_dirs(dest, &path)?;
+                extracted.push(out);
+                continue;
+            }
+            create_dirs(dest, path.parent().unwrap_or(Path::new("")))?;
+
+            match fs::symlink_metadata(&out) {
+                Ok(meta) if !meta.is_dir() => fs::remove_file(&out)?,
+                Ok(_) => return Err(refuse(&out)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+
+            match entry.kind() {
+                EntryKind::File => {
+                    // create_new does not follow a symlink at the last component
+                    let mut file = fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .mode(0o600)
+                        .open(&out)?;
+                    io::copy(&mut entry, &mut file)?;
+                    file.set_permissions(fs::Permissions::from_mode(entry.mode()))?;
+                }
+                EntryKind::Symlink => symlink(entry.link().unwrap_or_default(), &out)?,
+                EntryKind::Hardlink => {
+                    let target = safe_join(entry.link().unwrap_or_default())?;
+                    create_dirs(dest, target.parent().unwrap_or(Path::new("")))?;
+                    fs::hard_link(dest.join(target), &out)?;
+                }
+                EntryKind::Dir | EntryKind::Other => continue,
+            }
+
+            extracted.push(out);
+        }
+
+        Ok(extracted)
+    }
+}
+
+/// An entry in a [`PackageArchive`].
+///
+/// The contents of the entry can be read through [`Read`].
+#[derive(Debug)]
+pub struct ArchiveEntry<'a> {
+    archive: &'a mut PackageArchive,
+    path: String,
+    kind: EntryKind,
+    link: Option<String>,
+    size: u64,
+    mode: u32,
+    mtime: i64,
+}
+
+impl ArchiveEntry<'_> {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    /// The target of a symlink or hardlink.
+    pub fn link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The permission bits of the entry.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn mtime(&self) -> i64 {
+        self.mtime
+    }
+}
+
+impl Read for ArchiveEntry<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe {
+            archive_read_data(self.archive.as_ptr(), buf.as_mut_ptr() as _, buf.len() as _)
+        };
+        if n < 0 {
+            Err(self.archive.error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
+impl LoadedPackage<'_> {
+    /// Opens the package file this package was loaded from.
+    pub fn archive(&self) -> io::Result<PackageArchive> {
+        let path = self
+            .filename()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "package has no filename"))?;
+        PackageArchive::open(path)
+    }
+}
+
+unsafe fn cstr_to_string(s: *const std::os::raw::c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned())
+    }
+}
+
+fn refuse(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("refusing to extract {}", path.display()),
+    )
+}
+
+// the path of an entry relative to the destination
+fn safe_join(path: &str) -> io::Result<PathBuf> {
+    let path = Path::new(path);
+    let mut out = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => out.push(c),
+            Component::CurDir => (),
+            _ => return Err(refuse(path)),
+        }
+    }
+
+    Ok(out)
+}
+
+// Creates the directories of `path` below `dest` one at a time. Anything in the way that is
+// not a directory, such as a symlink, is refused instead of followed.
+fn create_dirs(dest: &Path, path: &Path) -> io::Result<()> {
+    let mut dir = dest.to_path_buf();
+
+    for component in path.components() {
+        dir.push(component);
+        match fs::symlink_metadata(&dir) {
+            Ok(meta) if meta.is_dir() => (),
+            Ok(_) => return Err(refuse(&dir)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => fs::create_dir(&dir)?,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Alpm, BuildInfo, PkgInfo, SigLevel, TempDir};
+
+    const PKG: &str = "tests/pacman-5.1.3-1-x86_64.pkg.tar.xz";
+
+    #[test]
+    fn test_entries() {
+        let mut archive = PackageArchive::open(PKG).unwrap();
+
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.path(), ".PKGINFO");
+        assert_eq!(entry.kind(), EntryKind::File);
+        assert_eq!(entry.size(), 731);
+        assert_eq!(entry.mode(), 0o644);
+        let mut pkginfo = String::new();
+        entry.read_to_string(&mut pkginfo).unwrap();
+        assert!(pkginfo.contains("pkgname = pacman\n"));
+
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.path(), ".BUILDINFO");
+        assert!(archive.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_file() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let pkg = handle.pkg_load(PKG, false, SigLevel::NONE).unwrap();
+
+        let data = pkg.archive().unwrap().read_file("./.BUILDINFO").unwrap();
+        assert!(data.starts_with(b"format = 1\n"));
+
+        let err = pkg
+            .archive()
+            .unwrap()
+            .read_file("usr/bin/pacman")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        assert_eq!(PkgInfo::from_package(PKG).unwrap().pkgname, "pacman");
+        assert_eq!(
+            BuildInfo::from_package(PKG).unwrap().builddir.as_deref(),
+            Some("/build")
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        let tmp = TempDir::new("alpm-extract").unwrap();
+        let dir = tmp.path().join("dest");
+        let mut archive = PackageArchive::open(PKG).unwrap();
+
+        let files = archive.extract(&dir, |e| e.path() == ".BUILDINFO").unwrap();
+        assert_eq!(files, [dir.join(".BUILDINFO")]);
+        assert_eq!(fs::metadata(&files[0]).unwrap().len(), 5161);
+        assert!(!dir.join(".PKGINFO").exists());
+
+        // an existing symlink is replaced rather than written through
+        let outside = tmp.path().join("outside");
+        fs::write(&outside, "outside").unwrap();
+        symlink(&outside, dir.join(".PKGINFO")).unwrap();
+        let mut archive = PackageArchive::open(PKG).unwrap();
+        archive.extract(&dir, |e| e.path() == ".PKGINFO").unwrap();
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "outside");
+        assert!(
+            fs::symlink_metadata(dir.join(".PKGINFO"))
+                .unwrap()
+                .is_file()
+        );
+    }
+
+    #[test]
+    fn test_create_dirs() {
+        let tmp = TempDir::new("alpm-extract").unwrap();
+        let dest = tmp.path().join("dest");
+        let outside = tmp.path().join("etc");
+        fs::create_dir_all(dest.join("usr")).unwrap();
+        fs::create_dir(&outside).unwrap();
+
+        create_dirs(&dest, Path::new("usr/share/doc")).unwrap();
+        assert!(dest.join("usr/share/doc").is_dir());
+
+        symlink(&outside, dest.join("usr/lib")).unwrap();
+        let err = create_dirs(&dest, Path::new("usr/lib")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(create_dirs(&dest, Path::new("usr/lib/foo")).is_err());
+        assert!(!outside.join("foo").exists());
+    }
+
+    #[test]
+    fn test_safe_join() {
+        assert_eq!(
+            safe_join("./usr/bin/foo").unwrap(),
+            Path::new("usr/bin/foo")
+        );
+        assert!(safe_join("../etc/passwd").is_err());
+        assert!(safe_join("/etc/passwd").is_err());
+        assert!(safe_join("usr/../../etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,847 @@
+use crate::{
+    Alpm, AnyEvent, AnyQuestion, DownloadEvent, Error, Event, EventType, HookWhen, LogLevel,
+    PackageOperation, PackageReason, Pkg, Progress, Question, QuestionType, Result, SendAlpm,
+    TransFlag,
+};
+
+use std::collections::VecDeque;
+use std::future::{Future, poll_fn};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+type Job = Box<dyn FnOnce(&mut SendAlpm) + Send>;
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// An event emitted by the handle of an [`AsyncAlpm`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlpmEvent {
+    Log {
+        level: LogLevel,
+        message: String,
+    },
+    Event(Box<EventData>),
+    Progress {
+        progress: Progress,
+        pkgname: String,
+        percent: i32,
+        howmany: usize,
+        current: usize,
+    },
+    Download {
+        filename: String,
+        event: DownloadEvent,
+    },
+}
+
+/// An owned copy of an [`Event`].
+///
+/// Fields that do not apply to the event are None.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventData {
+    pub event_type: EventType,
+    /// The package being installed or the new package of an upgrade or pacnew event.
+    pub newpkg: Option<PackageData>,
+    /// The package being removed, the old package of an upgrade, pacnew or pacsave event, or
+    /// the package an optional dependency is removed from.
+    pub oldpkg: Option<PackageData>,
+    /// The line of a scriptlet, the name of a missing db, the file of a pacnew or pacsave
+    /// event, the optional dependency being removed or the name of a hook being run.
+    pub message: Option<String>,
+    /// The description of a hook being run.
+    pub desc: Option<String>,
+    /// When the hooks are run.
+    pub when: Option<HookWhen>,
+    /// The position of a hook being run or the number of packages being retrieved.
+    pub position: Option<usize>,
+    /// The number of hooks being run or the total size of the packages being retrieved.
+    pub total: Option<i64>,
+}
+
+impl From<&AnyEvent<'_>> for EventData {
+    fn from(event: &AnyEvent) -> EventData {
+        let mut data = EventData {
+            event_type: event.event_type(),
+            newpkg: None,
+            oldpkg: None,
+            message: None,
+            desc: None,
+            when: None,
+            position: None,
+            total: None,
+        };
+
+        match event.event() {
+            Event::PackageOperationStart(op) | Event::PackageOperationDone(op) => {
+                let (new, old) = match op.operation() {
+                    PackageOperation::Install(new) => (Some(new), None),
+                    PackageOperation::Upgrade(new, old)
+                    | PackageOperation::Reinstall(new, old)
+                    | PackageOperation::Downgrade(new, old) => (Some(new), Some(old)),
+                    PackageOperation::Remove(old) => (None, Some(old)),
+                };
+                data.newpkg = new.map(|p| PackageData::from(&**p));
+                data.oldpkg = old.map(|p| PackageData::from(&**p));
+            }
+            Event::ScriptletInfo(info) => data.message = Some(info.line().to_string()),
+            Event::PkgRetrieveStart(e)
+            | Event::PkgRetrieveDone(e)
+            | Event::PkgRetrieveFailed(e) => {
+                data.position = Some(e.num());
+                data.total = Some(e.total_size());
+            }
+            Event::OptDepRemoval(e) => {
+                data.oldpkg = Some(PackageData::from(&**e.pkg()));
+                data.message = Some(e.optdep().to_string());
+            }
+            Event::DatabaseMissing(e) => data.message = Some(e.dbname().to_string()),
+            Event::PacnewCreated(e) => {
+                data.newpkg = e.newpkg().map(|p| PackageData::from(&**p));
+                data.oldpkg = e.oldpkg().map(|p| PackageData::from(&**p));
+                data.message = Some(e.file().to_string());
+            }
+            Event::PacsaveCreated(e) => {
+                data.oldpkg = e.oldpkg().map(|p| PackageData::from(&**p));
+                data.message = Some(e.file().to_string());
+            }
+            Event::HookStart(e) | Event::HookDone(e) => data.when = Some(e.when()),
+            Event::HookRunStart(e) | Event::HookRunDone(e) => {
+                data.message = Some(e.name().to_string());
+                data.desc = e.desc().map(|s| s.to_string());
+                data.position = Some(e.position());
+                data.total = Some(e.total() as i64);
+            }
+            _ => (),
+        }
+
+        data
+    }
+}
+
+/// An owned copy of a [`Question`].
+///
+/// Fields that do not apply to the question are empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuestionData {
+    pub question_type: QuestionType,
+    /// The ignored package to install, the new and old package of a replacement, the two
+    /// conflicting packages, the packages that can not be upgraded or the providers to
+    /// choose from.
+    pub packages: Vec<PackageData>,
+    /// The reason of a conflict, the dependency to choose a provider for, the corrupted file
+    /// or the user ID of the key to import.
+    pub message: Option<String>,
+    /// The fingerprint of the key to import.
+    pub fingerprint: Option<String>,
+    /// Why a package is corrupted.
+    pub reason: Option<Error>,
+}
+
+impl From<&AnyQuestion<'_>> for QuestionData {
+    fn from(question: &AnyQuestion) -> QuestionData {
+        let mut data = QuestionData {
+            question_type: question.question_type(),
+            packages: Vec::new(),
+            message: None,
+            fingerprint: None,
+            reason: None,
+        };
+
+        match question.question() {
+            Question::InstallIgnorepkg(q) => data.packages.push(PackageData::from(&**q.pkg())),
+            Question::Replace(q) => {
+                data.packages.push(PackageData::from(&**q.newpkg()));
+                data.packages.push(PackageData::from(&**q.oldpkg()));
+            }
+            Question::Conflict(q) => {
+                let conflict = q.conflict();
+                data.packages
+                    .push(PackageData::from(&**conflict.package1()));
+                data.packages
+                    .push(PackageData::from(&**conflict.package2()));
+                data.message = Some(conflict.reason().to_string());
+            }
+            Question::Corrupted(q) => {
+                data.message = Some(q.filepath().to_string());
+                data.reason = Some(q.reason());
+            }
+            Question::RemovePkgs(q) => {
+                data.packages = q
+                    .packages()
+                    .iter()
+                    .map(|p| PackageData::from(&**p))
+                    .collect();
+            }
+            Question::SelectProvider(q) => {
+                data.packages = q
+                    .providers()
+                    .iter()
+                    .map(|p| PackageData::from(&**p))
+                    .collect();
+                data.message = Some(q.depend().to_string());
+            }
+            Question::ImportKey(q) => {
+                data.message = Some(q.uid().to_string());
+                data.fingerprint = Some(q.fingerprint().to_string());
+            }
+        }
+
+        data
+    }
+}
+
+/// A question asked by the handle of an [`AsyncAlpm`], received through
+/// [`AsyncAlpm::questions`].
+///
+/// The handle waits until the question is answered. Dropping the question keeps the default
+/// answer.
+#[derive(Debug)]
+pub struct PendingQuestion {
+    pub question: QuestionData,
+    reply: Sender<i32>,
+}
+
+impl PendingQuestion {
+    /// Answers yes or no.
+    pub fn answer(self, answer: bool) {
+        let _ = self.reply.send(answer as i32);
+    }
+
+    /// Chooses the provider at `index` in [`QuestionData::packages`] for a
+    /// [`QuestionType::SelectProvider`] question.
+    pub fn select(self, index: usize) {
+        let _ = self.reply.send(index as i32);
+    }
+}
+
+/// An owned copy of the information of a [`Pkg`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageData {
+    pub name: String,
+    pub version: String,
+    pub base: Option<String>,
+    pub desc: Option<String>,
+    pub url: Option<String>,
+    pub arch: Option<String>,
+    pub db: Option<String>,
+    pub filename: Option<String>,
+    pub packager: Option<String>,
+    pub build_date: i64,
+    pub install_date: Option<i64>,
+    pub size: i64,
+    pub isize: i64,
+    pub reason: PackageReason,
+    pub licenses: Vec<String>,
+    pub groups: Vec<String>,
+    pub depends: Vec<String>,
+    pub optdepends: Vec<String>,
+    pub makedepends: Vec<String>,
+    pub checkdepends: Vec<String>,
+    pub provides: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub replaces: Vec<String>,
+}
+
+impl From<&Pkg> for PackageData {
+    fn from(pkg: &Pkg) -> PackageData {
+        PackageData {
+            name: pkg.name().to_string(),
+            version: pkg.version().to_string(),
+            base: pkg.base().map(|s| s.to_string()),
+            desc: pkg.desc().map(|s| s.to_string()),
+            url: pkg.url().map(|s| s.to_string()),
+            arch: pkg.arch().map(|s| s.to_string()),
+            db: pkg.db().map(|db| db.name().to_string()),
+            filename: pkg.filename().map(|s| s.to_string()),
+            packager: pkg.packager().map(|s| s.to_string()),
+            build_date: pkg.build_date(),
+            install_date: pkg.install_date(),
+            size: pkg.size(),
+            isize: pkg.isize(),
+            reason: pkg.reason(),
+            licenses: pkg.licenses().iter().map(|s| s.to_string()).collect(),
+            groups: pkg.groups().iter().map(|s| s.to_string()).collect(),
+            depends: pkg.depends().iter().map(|d| d.to_string()).collect(),
+            optdepends: pkg.optdepends().iter().map(|d| d.to_string()).collect(),
+            makedepends: pkg.makedepends().iter().map(|d| d.to_string()).collect(),
+            checkdepends: pkg.checkdepends().iter().map(|d| d.to_string()).collect(),
+            provides: pkg.provides().iter().map(|d| d.to_string()).collect(),
+            conflicts: pkg.conflicts().iter().map(|d| d.to_string()).collect(),
+            replaces: pkg.replaces().iter().map(|d| d.to_string()).collect(),
+        }
+    }
+}
+
+/// The targets of a transaction run with [`AsyncAlpm::transaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransRequest {
+    pub flags: TransFlag,
+    pub sysupgrade: bool,
+    pub enable_downgrade: bool,
+    /// Targets to install from the sync dbs.
+    pub install: Vec<String>,
+    /// Names of installed packages to remove.
+    pub remove: Vec<String>,
+}
+
+impl Default for TransRequest {
+    fn default() -> TransRequest {
+        TransRequest {
+            flags: TransFlag::NONE,
+            sysupgrade: false,
+            enable_downgrade: false,
+            install: Vec::new(),
+            remove: Vec::new(),
+        }
+    }
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue {
+            items: VecDeque::new(),
+            waker: None,
+            closed: false,
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    fn push(&mut self, item: T) {
+        self.items.push_back(item);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(item) = self.items.pop_front() {
+            Poll::Ready(Some(item))
+        } else if self.closed {
+            Poll::Ready(None)
+        } else {
+            self.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Default)]
+struct Broadcast {
+    queues: Mutex<Vec<Weak<Mutex<Queue<AlpmEvent>>>>>,
+}
+
+impl Broadcast {
+    fn send(&self, event: AlpmEvent) {
+        let mut queues = lock(&self.queues);
+        queues.retain(|q| q.strong_count() > 0);
+
+        for queue in queues.iter().filter_map(|q| q.upgrade()) {
+            lock(&queue).push(event.clone());
+        }
+    }
+}
+
+impl Drop for Broadcast {
+    fn drop(&mut self) {
+        for queue in lock(&self.queues).iter().filter_map(|q| q.upgrade()) {
+            lock(&queue).close();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Asker {
+    queue: Mutex<Weak<Mutex<Queue<PendingQuestion>>>>,
+}
+
+impl Asker {
+    /// Sends the question to the current [`Questions`] and waits for the answer.
+    fn ask(&self, mut question: AnyQuestion) {
+        let Some(queue) = lock(&self.queue).upgrade() else {
+            return;
+        };
+
+        let (reply, answer) = mpsc::channel();
+        lock(&queue).push(PendingQuestion {
+            question: QuestionData::from(&question),
+            reply,
+        });
+        drop(queue);
+
+        match (answer.recv(), question.question()) {
+            (Ok(index), Question::SelectProvider(mut q)) => q.set_index(index),
+            (Ok(answer), _) => question.set_answer(answer != 0),
+            (Err(_), _) => (),
+        }
+    }
+}
+
+impl Drop for Asker {
+    fn drop(&mut self) {
+        if let Some(queue) = lock(&self.queue).upgrade() {
+            lock(&queue).close();
+        }
+    }
+}
+
+/// A subscription to the events of an [`AsyncAlpm`].
+///
+/// Events are buffered until they are received.
+pub struct Events {
+    queue: Arc<Mutex<Queue<AlpmEvent>>>,
+}
+
+impl std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Events").finish()
+    }
+}
+
+impl Events {
+    /// Waits for the next event.
+    ///
+    /// Returns None once the handle is gone and every event has been received.
+    pub async fn recv(&mut self) -> Option<AlpmEvent> {
+        poll_fn(|cx| lock(&self.queue).poll_recv(cx)).await
+    }
+
+    /// Returns the next event if one is buffered.
+    pub fn try_recv(&mut self) -> Option<AlpmEvent> {
+        lock(&self.queue).items.pop_front()
+    }
+}
+
+/// The questions asked by the handle of an [`AsyncAlpm`].
+///
+/// The handle waits for each question to be answered, so questions must be received while
+/// the call that asks them is awaited, for example by joining both futures.
+pub struct Questions {
+    queue: Arc<Mutex<Queue<PendingQuestion>>>,
+}
+
+impl std::fmt::Debug for Questions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Questions").finish()
+    }
+}
+
+impl Questions {
+    /// Waits for the next question.
+    ///
+    /// Returns None once the handle is gone.
+    pub async fn recv(&mut self) -> Option<PendingQuestion> {
+        poll_fn(|cx| lock(&self.queue).poll_recv(cx)).await
+    }
+}
+
+struct Slot<T> {
+    value: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+struct ReplySender<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> ReplySender<T> {
+    fn send(self, value: thread::Result<T>) {
+        lock(&self.0).value = Some(value);
+    }
+}
+
+impl<T> Drop for ReplySender<T> {
+    fn drop(&mut self) {
+        let mut slot = lock(&self.0);
+        slot.closed = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Reply<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Future for Reply<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = lock(&self.0);
+        match slot.value.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(e)) => {
+                drop(slot);
+                panic::resume_unwind(e)
+            }
+            None if slot.closed => panic!("alpm worker thread has exited"),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// An [`Alpm`] handle that lives on its own thread and is used through async methods.
+///
+/// Calls are queued and run one at a time on the handle's thread. The futures do not
+/// depend on any particular async runtime.
+///
+/// The log, download, event and progress callbacks of the handle are replaced to forward
+/// events to [`AsyncAlpm::subscribe`]. The question callback is replaced to forward
+/// questions to [`AsyncAlpm::questions`].
+///
+/// Dropping the `AsyncAlpm` does not block. Queued calls still run and the handle is
+/// released on its thread afterwards. Use [`AsyncAlpm::close`] to wait for that.
+pub struct AsyncAlpm {
+    tx: Option<Sender<Job>>,
+    closed: Arc<Mutex<Slot<()>>>,
+    events: Weak<Broadcast>,
+    asker: Weak<Asker>,
+}
+
+impl std::fmt::Debug for AsyncAlpm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncAlpm").finish()
+    }
+}
+
+impl AsyncAlpm {
+    pub fn new(mut alpm: SendAlpm) -> AsyncAlpm {
+        let events = Arc::new(Broadcast::default());
+        let weak = Arc::downgrade(&events);
+        let asker = Arc::new(Asker::default());
+        let weak_asker = Arc::downgrade(&asker);
+
+        alpm.set_log_cb(events.clone(), |level, msg, events| {
+            events.send(AlpmEvent::Log {
+                level,
+                message: msg.to_string(),
+            })
+        });
+        alpm.set_dl_cb(events.clone(), |filename, event, events| {
+            events.send(AlpmEvent::Download {
+                filename: filename.to_string(),
+                event: event.event(),
+            })
+        });
+        alpm.set_event_cb(events.clone(), |event, events| {
+            events.send(AlpmEvent::Event(Box::new(EventData::from(&event))))
+        });
+        alpm.set_question_cb(asker, |question, asker| asker.ask(question));
+        alpm.set_progress_cb(
+            events,
+            |progress, pkgname, percent, howmany, current, events| {
+                events.send(AlpmEvent::Progress {
+                    progress,
+                    pkgname: pkgname.to_string(),
+                    percent,
+                    howmany,
+                    current,
+                })
+            },
+        );
+
+        let closed = Arc::new(Mutex::new(Slot {
+            value: None,
+            waker: None,
+            closed: false,
+        }));
+        let sender = ReplySender(closed.clone());
+
+        let (tx, rx) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("alpm".into())
+            .spawn(move || {
+                for job in rx {
+                    job(&mut alpm);
+                }
+                drop(alpm);
+                sender.send(Ok(()));
+            })
+            .expect("failed to spawn alpm thread");
+
+        AsyncAlpm {
+            tx: Some(tx),
+            closed,
+            events: weak,
+            asker: weak_asker,
+        }
+    }
+
+    /// Waits for queued calls to finish and releases the handle.
+    pub async fn close(mut self) {
+        self.tx.take();
+        Reply(self.closed.clone()).await
+    }
+
+    /// Subscribes to the events of the handle.
+    ///
+    /// Only events emitted after subscribing are received.
+    pub fn subscribe(&self) -> Events {
+        let queue = Arc::new(Mutex::new(Queue::default()));
+        match self.events.upgrade() {
+            Some(events) => lock(&events.queues).push(Arc::downgrade(&queue)),
+            None => lock(&queue).closed = true,
+        }
+        Events { queue }
+    }
+
+    /// Receives the questions asked by the handle.
+    ///
+    /// Only the most recent `Questions` receives questions. While there is none the default
+    /// answers are used.
+    pub fn questions(&self) -> Questions {
+        let queue = Arc::new(Mutex::new(Queue::default()));
+        match self.asker.upgrade() {
+            Some(asker) => *lock(&asker.queue) = Arc::downgrade(&queue),
+            None => lock(&queue).closed = true,
+        }
+        Questions { queue }
+    }
+
+    /// Runs `f` with the handle on the handle's thread.
+    ///
+    /// A panic in `f` is resumed in the caller.
+    pub async fn run<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Alpm) -> R + Send + 'static,
+    {
+        self.run_send(move |alpm| alpm.with(f)).await
+    }
+
+    /// Like [`AsyncAlpm::run`] but gives access to the [`SendAlpm`], for example to set
+    /// callbacks.
+    pub async fn run_send<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut SendAlpm) -> R + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            value: None,
+            waker: None,
+            closed: false,
+        }));
+        let sender = ReplySender(slot.clone());
+        let job: Job = Box::new(move |alpm| {
+            let ret = panic::catch_unwind(AssertUnwindSafe(|| f(alpm)));
+            sender.send(ret);
+        });
+
+        if let Some(tx) = &self.tx {
+            // if the thread is gone the job is dropped which closes the reply
+            let _ = tx.send(job);
+        }
+
+        Reply(slot).await
+    }
+
+    /// Looks up a package by name in the given sync db, or the local db if `db` is None.
+    pub async fn pkg(&self, db: Option<&str>, name: &str) -> Result<PackageData> {
+        let db = db.map(|s| s.to_string());
+        let name = name.to_string();
+
+        self.run(move |alpm| {
+            let pkg = match db {
+                Some(db) => alpm
+                    .syncdbs()
+                    .iter()
+                    .find(|d| d.name() == db)
+                    .ok_or(Error::DbNotFound)?
+                    .pkg(name)?,
+                None => alpm.localdb().pkg(name)?,
+            };
+            Ok(PackageData::from(&**pkg))
+        })
+        .await
+    }
+
+    /// Searches the given sync db, or the local db if `db` is None.
+    pub async fn search(&self, db: Option<&str>, terms: Vec<String>) -> Result<Vec<PackageData>> {
+        let db = db.map(|s| s.to_string());
+
+        self.run(move |alpm| {
+            let db = match db {
+                Some(db) => alpm
+                    .syncdbs()
+                    .iter()
+                    .find(|d| d.name() == db)
+                    .ok_or(Error::DbNotFound)?,
+                None => alpm.localdb(),
+            };
+            let pkgs = db.search(terms.iter())?;
+            Ok(pkgs.iter().map(PackageData::from).collect())
+        })
+        .await
+    }
+
+    /// Updates every sync db. Returns true if any db was updated.
+    pub async fn update_syncdbs(&self, force: bool) -> Result<bool> {
+        self.run(move |alpm| alpm.syncdbs_mut().update(force)).await
+    }
+
+    /// Downloads packages from URLs to the cache. Returns the paths of the downloaded files.
+    pub async fn fetch_pkgurl(&self, urls: Vec<String>) -> Result<Vec<String>> {
+        self.run(move |alpm| {
+            let paths = alpm.fetch_pkgurl(urls.iter())?;
+            Ok(paths.iter().map(|s| s.to_string()).collect())
+        })
+        .await
+    }
+
+    /// Initialises, prepares and commits a transaction.
+    ///
+    /// Questions asked during the transaction are sent to [`AsyncAlpm::questions`]. The
+    /// transaction is always released afterwards.
+    pub async fn transaction(&self, req: TransRequest) -> Result<()> {
+        self.run(move |alpm| {
+            alpm.trans_init(req.flags)?;
+            let ret = run_transaction(alpm, &req);
+            let release = alpm.trans_release();
+            ret.and(release)
+        })
+        .await
+    }
+}
+
+fn run_transaction(alpm: &mut Alpm, req: &TransRequest) -> Result<()> {
+    if req.sysupgrade {
+        alpm.sync_sysupgrade(req.enable_downgrade)?;
+    }
+
+    for target in &req.install {
+        let pkg = alpm
+            .syncdbs()
+            .find_satisfier(&**target)
+            .ok_or(Error::PkgNotFound)?;
+        alpm.trans_add_pkg(pkg)?;
+    }
+
+    for name in &req.remove {
+        let pkg = alpm.localdb().pkg(&**name)?;
+        alpm.trans_remove_pkg(pkg)?;
+    }
+
+    alpm.trans_prepare()?;
+    alpm.trans_commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread, AtomicBool);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.1.store(true, Ordering::SeqCst);
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = std::pin::pin!(f);
+        let waker = Arc::new(ThreadWaker(thread::current(), AtomicBool::new(false)));
+        let w = Waker::from(waker.clone());
+        let mut cx = Context::from_waker(&w);
+
+        loop {
+            if let Poll::Ready(ret) = f.as_mut().poll(&mut cx) {
+                return ret;
+            }
+            while !waker.1.swap(false, Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+    }
+
+    fn handle() -> AsyncAlpm {
+        let alpm = Alpm::new("/", "tests/db").unwrap();
+        alpm.register_syncdb("core", crate::SigLevel::NONE).unwrap();
+        AsyncAlpm::new(SendAlpm::new(alpm).unwrap())
+    }
+
+    #[test]
+    fn test_async_pkg() {
+        let handle = handle();
+
+        let pkg = block_on(handle.pkg(None, "pacman")).unwrap();
+        assert_eq!(pkg.name, "pacman");
+        assert_eq!(pkg.db.as_deref(), Some("local"));
+        assert!(pkg.depends.iter().any(|d| d == "curl"));
+
+        let pkg = block_on(handle.pkg(Some("core"), "linux")).unwrap();
+        assert_eq!(pkg.version, "5.1.8.arch1-1");
+
+        let err = block_on(handle.pkg(Some("nope"), "linux")).unwrap_err();
+        assert_eq!(err, Error::DbNotFound);
+
+        let pkgs = block_on(handle.search(Some("core"), vec!["linux".into()])).unwrap();
+        assert!(pkgs.iter().any(|p| p.name == "linux"));
+    }
+
+    #[test]
+    fn test_async_events() {
+        let handle = handle();
+        let mut events = handle.subscribe();
+
+        let n = block_on(handle.run(|alpm| alpm.localdb().pkgs().len()));
+        assert!(n > 0);
+        block_on(handle.close());
+
+        let mut got_log = false;
+        while let Some(event) = block_on(events.recv()) {
+            got_log |= matches!(event, AlpmEvent::Log { .. });
+        }
+        assert!(got_log);
+    }
+
+    #[test]
+    fn test_async_drop() {
+        let handle = handle();
+        let mut events = handle.subscribe();
+        let mut questions = handle.questions();
+
+        let n = block_on(handle.run(|alpm| alpm.localdb().pkgs().len()));
+        drop(handle);
+
+        assert!(n > 0);
+        while block_on(events.recv()).is_some() {}
+        assert!(block_on(questions.recv()).is_none());
+    }
+
+    #[test]
+    fn test_async_panic() {
+        let handle = handle();
+
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            block_on(handle.run(|_| panic!("oops")))
+        }));
+        assert!(ret.is_err());
+
+        let pkg = block_on(handle.pkg(None, "pacman")).unwrap();
+        assert_eq!(pkg.name, "pacman");
+    }
+}
@@ -5,6 +5,9 @@ use crate::{AlpmList, DbMut};
 
 impl<'a> AlpmList<'a, DbMut<'a>> {
     pub fn update(&self, force: bool) -> Result<bool> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(target: crate::LOG_TARGET, "update", force).entered();
+
         let first = self.first().ok_or(Error::WrongArgs)?;
         let force = if force { 1 } else { 0 };
         let ret = unsafe { alpm_db_update(first.handle_ptr(), self.as_ptr(), force) };
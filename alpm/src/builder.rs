@@ -0,0 +1,497 @@
+use crate::{Alpm, Error, SigLevel, Usage};
+
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncDbOptions {
+    pub name: String,
+    pub servers: Vec<String>,
+    pub cache_servers: Vec<String>,
+    pub sig_level: SigLevel,
+    pub usage: Usage,
+}
+
+impl SyncDbOptions {
+    pub fn new<S: Into<String>>(name: S) -> SyncDbOptions {
+        SyncDbOptions {
+            name: name.into(),
+            servers: Vec::new(),
+            cache_servers: Vec::new(),
+            sig_level: SigLevel::USE_DEFAULT,
+            usage: Usage::ALL,
+        }
+    }
+
+    pub fn server<S: Into<String>>(mut self, server: S) -> Self {
+        self.servers.push(server.into());
+        self
+    }
+
+    pub fn cache_server<S: Into<String>>(mut self, server: S) -> Self {
+        self.cache_servers.push(server.into());
+        self
+    }
+
+    pub fn sig_level(mut self, sig_level: SigLevel) -> Self {
+        self.sig_level = sig_level;
+        self
+    }
+
+    pub fn usage(mut self, usage: Usage) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlpmBuildProblem {
+    EmptyValue {
+        option: &'static str,
+    },
+    NotADir {
+        option: &'static str,
+        path: String,
+    },
+    IsADir {
+        option: &'static str,
+        path: String,
+    },
+    InvalidSigLevel {
+        option: &'static str,
+        sig_level: SigLevel,
+    },
+    InvalidParallelDownloads,
+    InvalidDbName(String),
+    DuplicateDb(String),
+    InvalidServer {
+        db: String,
+        server: String,
+    },
+    Alpm {
+        option: &'static str,
+        error: Error,
+    },
+}
+
+impl fmt::Display for AlpmBuildProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlpmBuildProblem::EmptyValue { option } => write!(f, "{} is empty", option),
+            AlpmBuildProblem::NotADir { option, path } => {
+                write!(f, "{}: {} is not a directory", option, path)
+            }
+            AlpmBuildProblem::IsADir { option, path } => {
+                write!(f, "{}: {} is a directory", option, path)
+            }
+            AlpmBuildProblem::InvalidSigLevel { option, sig_level } => {
+                write!(f, "{}: invalid siglevel {:?}", option, sig_level)
+            }
+            AlpmBuildProblem::InvalidParallelDownloads => {
+                f.write_str("parallel downloads must be at least 1")
+            }
+            AlpmBuildProblem::InvalidDbName(name) => write!(f, "invalid db name: {}", name),
+            AlpmBuildProblem::DuplicateDb(name) => write!(f, "duplicate db: {}", name),
+            AlpmBuildProblem::InvalidServer { db, server } => {
+                write!(f, "{}: invalid server: {}", db, server)
+            }
+            AlpmBuildProblem::Alpm { option, error } => write!(f, "{}: {}", option, error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlpmBuildError {
+    pub problems: Vec<AlpmBuildProblem>,
+}
+
+impl fmt::Display for AlpmBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed to build alpm handle")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{}", sep, problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AlpmBuildError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct AlpmBuilder {
+    root: String,
+    db_path: String,
+    cachedirs: Vec<String>,
+    hookdirs: Vec<String>,
+    gpgdir: Option<String>,
+    logfile: Option<String>,
+    architectures: Vec<String>,
+    default_siglevel: Option<SigLevel>,
+    local_file_siglevel: Option<SigLevel>,
+    remote_file_siglevel: Option<SigLevel>,
+    parallel_downloads: Option<u32>,
+    sandbox_user: Option<String>,
+    disable_sandbox_filesystem: bool,
+    disable_sandbox_syscalls: bool,
+    syncdbs: Vec<SyncDbOptions>,
+}
+
+impl AlpmBuilder {
+    pub fn new<S: Into<String>>(root: S, db_path: S) -> AlpmBuilder {
+        AlpmBuilder {
+            root: root.into(),
+            db_path: db_path.into(),
+            cachedirs: Vec::new(),
+            hookdirs: Vec::new(),
+            gpgdir: None,
+            logfile: None,
+            architectures: Vec::new(),
+            default_siglevel: None,
+            local_file_siglevel: None,
+            remote_file_siglevel: None,
+            parallel_downloads: None,
+            sandbox_user: None,
+            disable_sandbox_filesystem: false,
+            disable_sandbox_syscalls: false,
+            syncdbs: Vec::new(),
+        }
+    }
+
+    pub fn cachedir<S: Into<String>>(mut self, dir: S) -> Self {
+        self.cachedirs.push(dir.into());
+        self
+    }
+
+    pub fn hookdir<S: Into<String>>(mut self, dir: S) -> Self {
+        self.hookdirs.push(dir.into());
+        self
+    }
+
+    pub fn gpgdir<S: Into<String>>(mut self, dir: S) -> Self {
+        self.gpgdir = Some(dir.into());
+        self
+    }
+
+    pub fn logfile<S: Into<String>>(mut self, file: S) -> Self {
+        self.logfile = Some(file.into());
+        self
+    }
+
+    pub fn architecture<S: Into<String>>(mut self, arch: S) -> Self {
+        self.architectures.push(arch.into());
+        self
+    }
+
+    pub fn default_siglevel(mut self, sig_level: SigLevel) -> Self {
+        self.default_siglevel = Some(sig_level);
+        self
+    }
+
+    pub fn local_file_siglevel(mut self, sig_level: SigLevel) -> Self {
+        self.local_file_siglevel = Some(sig_level);
+        self
+    }
+
+    pub fn remote_file_siglevel(mut self, sig_level: SigLevel) -> Self {
+        self.remote_file_siglevel = Some(sig_level);
+        self
+    }
+
+    pub fn parallel_downloads(mut self, n: u32) -> Self {
+        self.parallel_downloads = Some(n);
+        self
+    }
+
+    pub fn sandbox_user<S: Into<String>>(mut self, user: S) -> Self {
+        self.sandbox_user = Some(user.into());
+        self
+    }
+
+    pub fn disable_sandbox_filesystem(mut self, b: bool) -> Self {
+        self.disable_sandbox_filesystem = b;
+        self
+    }
+
+    pub fn disable_sandbox_syscalls(mut self, b: bool) -> Self {
+        self.disable_sandbox_syscalls = b;
+        self
+    }
+
+    pub fn syncdb(mut self, db: SyncDbOptions) -> Self {
+        self.syncdbs.push(db);
+        self
+    }
+
+    pub fn validate(&self) -> Vec<AlpmBuildProblem> {
+        let mut problems = Vec::new();
+
+        check_dir(&mut problems, "root", &self.root, true);
+        check_dir(&mut problems, "dbpath", &self.db_path, false);
+        for dir in &self.cachedirs {
+            check_dir(&mut problems, "cachedir", dir, false);
+        }
+        for dir in &self.hookdirs {
+            check_dir(&mut problems, "hookdir", dir, false);
+        }
+        if let Some(dir) = &self.gpgdir {
+            check_dir(&mut problems, "gpgdir", dir, false);
+        }
+        if let Some(file) = &self.logfile {
+            if file.is_empty() {
+                problems.push(AlpmBuildProblem::EmptyValue { option: "logfile" });
+            } else if Path::new(file).is_dir() {
+                problems.push(AlpmBuildProblem::IsADir {
+                    option: "logfile",
+                    path: file.clone(),
+                });
+            }
+        }
+        if self.architectures.iter().any(|a| a.is_empty()) {
+            problems.push(AlpmBuildProblem::EmptyValue {
+                option: "architecture",
+            });
+        }
+        if self.sandbox_user.as_deref() == Some("") {
+            problems.push(AlpmBuildProblem::EmptyValue {
+                option: "sandbox_user",
+            });
+        }
+        if self.parallel_downloads == Some(0) {
+            problems.push(AlpmBuildProblem::InvalidParallelDownloads);
+        }
+
+        let siglevels = [
+            ("default_siglevel", self.default_siglevel, false),
+            ("local_file_siglevel", self.local_file_siglevel, true),
+            ("remote_file_siglevel", self.remote_file_siglevel, true),
+        ];
+        for (option, sig_level, allow_default) in siglevels {
+            if let Some(sig_level) = sig_level {
+                check_siglevel(&mut problems, option, sig_level, allow_default);
+            }
+        }
+
+        for (i, db) in self.syncdbs.iter().enumerate() {
+            if db.name.is_empty() || db.name == "local" || db.name.contains('/') {
+                problems.push(AlpmBuildProblem::InvalidDbName(db.name.clone()));
+            }
+            if self.syncdbs[..i].iter().any(|d| d.name == db.name) {
+                problems.push(AlpmBuildProblem::DuplicateDb(db.name.clone()));
+            }
+            for server in db.servers.iter().chain(&db.cache_servers) {
+                if !server.contains("://") || server.contains(char::is_whitespace) {
+                    problems.push(AlpmBuildProblem::InvalidServer {
+                        db: db.name.clone(),
+                        server: server.clone(),
+                    });
+                }
+            }
+            check_siglevel(&mut problems, "syncdb siglevel", db.sig_level, true);
+        }
+
+        problems
+    }
+
+    pub fn build(self) -> std::result::Result<Alpm, AlpmBuildError> {
+        let mut problems = self.validate();
+        if !problems.is_empty() {
+            return Err(AlpmBuildError { problems });
+        }
+
+        let mut handle =
+            Alpm::new(&*self.root, &*self.db_path).map_err(|error| AlpmBuildError {
+                problems: vec![AlpmBuildProblem::Alpm {
+                    option: "root",
+                    error,
+                }],
+            })?;
+
+        let mut check = |option, res: crate::Result<()>| {
+            if let Err(error) = res {
+                problems.push(AlpmBuildProblem::Alpm { option, error });
+            }
+        };
+
+        check("cachedir", handle.set_cachedirs(self.cachedirs.iter()));
+        check("hookdir", handle.set_hookdirs(self.hookdirs.iter()));
+        if let Some(dir) = &self.gpgdir {
+            check("gpgdir", handle.set_gpgdir(&**dir));
+        }
+        if let Some(file) = &self.logfile {
+            check("logfile", handle.set_logfile(&**file));
+        }
+        check(
+            "architecture",
+            handle.set_architectures(self.architectures.iter()),
+        );
+        if let Some(sig_level) = self.default_siglevel {
+            check("default_siglevel", handle.set_default_siglevel(sig_level));
+        }
+        if let Some(sig_level) = self.local_file_siglevel {
+            check(
+                "local_file_siglevel",
+                handle.set_local_file_siglevel(sig_level),
+            );
+        }
+        if let Some(sig_level) = self.remote_file_siglevel {
+            check(
+                "remote_file_siglevel",
+                handle.set_remote_file_siglevel(sig_level),
+            );
+        }
+        if let Some(n) = self.parallel_downloads {
+            handle.set_parallel_downloads(n);
+        }
+        check(
+            "sandbox_user",
+            handle.set_sandbox_user(self.sandbox_user.as_deref()),
+        );
+        handle.set_disable_sandbox_filesystem(self.disable_sandbox_filesystem);
+        handle.set_disable_sandbox_syscalls(self.disable_sandbox_syscalls);
+
+        for db in &self.syncdbs {
+            let res = handle
+                .register_syncdb_mut(&*db.name, db.sig_level)
+                .and_then(|syncdb| {
+                    syncdb.set_servers(db.servers.iter())?;
+                    syncdb.set_cache_servers(db.cache_servers.iter())?;
+                    syncdb.set_usage(db.usage)
+                });
+            check("syncdb", res);
+        }
+
+        if problems.is_empty() {
+            Ok(handle)
+        } else {
+            Err(AlpmBuildError { problems })
+        }
+    }
+}
+
+fn check_dir(
+    problems: &mut Vec<AlpmBuildProblem>,
+    option: &'static str,
+    dir: &str,
+    must_exist: bool,
+) {
+    let path = Path::new(dir);
+
+    if dir.is_empty() {
+        problems.push(AlpmBuildProblem::EmptyValue { option });
+    } else if (must_exist || path.exists()) && !path.is_dir() {
+        problems.push(AlpmBuildProblem::NotADir {
+            option,
+            path: dir.to_string(),
+        });
+    }
+}
+
+fn check_siglevel(
+    problems: &mut Vec<AlpmBuildProblem>,
+    option: &'static str,
+    sig_level: SigLevel,
+    allow_default: bool,
+) {
+    let pkg_optional_only =
+        sig_level.contains(SigLevel::PACKAGE_OPTIONAL) && !sig_level.contains(SigLevel::PACKAGE);
+    let db_optional_only =
+        sig_level.contains(SigLevel::DATABASE_OPTIONAL) && !sig_level.contains(SigLevel::DATABASE);
+    let default = sig_level.contains(SigLevel::USE_DEFAULT);
+
+    if pkg_optional_only
+        || db_optional_only
+        || (default && !allow_default)
+        || (default && sig_level != SigLevel::USE_DEFAULT)
+    {
+        problems.push(AlpmBuildProblem::InvalidSigLevel { option, sig_level });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let handle = AlpmBuilder::new("/", "tests/db")
+            .cachedir("/var/cache/pacman/pkg/")
+            .architecture("x86_64")
+            .default_siglevel(SigLevel::PACKAGE | SigLevel::DATABASE)
+            .parallel_downloads(3)
+            .disable_sandbox_syscalls(true)
+            .syncdb(
+                SyncDbOptions::new("core")
+                    .server("https://example.com/core/os/x86_64")
+                    .usage(Usage::SYNC | Usage::SEARCH),
+            )
+            .syncdb(SyncDbOptions::new("extra").sig_level(SigLevel::NONE))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            handle.cachedirs().iter().collect::<Vec<_>>(),
+            ["/var/cache/pacman/pkg/"]
+        );
+        assert_eq!(
+            handle.architectures().iter().collect::<Vec<_>>(),
+            ["x86_64"]
+        );
+        assert!(handle.sandbox_syscalls_disabled());
+        assert!(!handle.sandbox_filesystem_disabled());
+
+        let dbs = handle.syncdbs();
+        assert_eq!(
+            dbs.iter().map(|db| db.name()).collect::<Vec<_>>(),
+            ["core", "extra"]
+        );
+        let core = dbs.iter().next().unwrap();
+        assert_eq!(core.usage().unwrap(), Usage::SYNC | Usage::SEARCH);
+        assert_eq!(
+            core.servers().iter().collect::<Vec<_>>(),
+            ["https://example.com/core/os/x86_64"]
+        );
+        assert!(core.pkg("linux").is_ok());
+    }
+
+    #[test]
+    fn test_build_problems() {
+        let err = AlpmBuilder::new("/does/not/exist", "tests/db")
+            .cachedir("")
+            .logfile("/")
+            .parallel_downloads(0)
+            .default_siglevel(SigLevel::USE_DEFAULT)
+            .syncdb(SyncDbOptions::new("core").server("example.com/core"))
+            .syncdb(SyncDbOptions::new("core"))
+            .syncdb(SyncDbOptions::new("local"))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            err.problems,
+            [
+                AlpmBuildProblem::NotADir {
+                    option: "root",
+                    path: "/does/not/exist".into()
+                },
+                AlpmBuildProblem::EmptyValue { option: "cachedir" },
+                AlpmBuildProblem::IsADir {
+                    option: "logfile",
+                    path: "/".into()
+                },
+                AlpmBuildProblem::InvalidParallelDownloads,
+                AlpmBuildProblem::InvalidSigLevel {
+                    option: "default_siglevel",
+                    sig_level: SigLevel::USE_DEFAULT
+                },
+                AlpmBuildProblem::InvalidServer {
+                    db: "core".into(),
+                    server: "example.com/core".into()
+                },
+                AlpmBuildProblem::DuplicateDb("core".into()),
+                AlpmBuildProblem::InvalidDbName("local".into()),
+            ]
+        );
+    }
+}
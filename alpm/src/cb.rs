@@ -1,6 +1,7 @@
+use crate::listeners::Listeners;
 use crate::{Alpm, AnyDownloadEvent, AnyEvent, AnyQuestion, FetchResult, LogLevel, Progress, free};
 use alpm_sys::*;
-use std::cell::{RefCell, UnsafeCell};
+use std::cell::{Cell, RefCell, UnsafeCell};
 use std::ffi::{CStr, c_void};
 use std::mem::transmute;
 use std::os::raw::{c_char, c_int};
@@ -20,6 +21,33 @@ pub(crate) struct Callbacks {
     pub(crate) progress: Cb<dyn ProgressCbTrait>,
     pub(crate) question: Cb<dyn QuestionCbTrait>,
     pub(crate) fetch: Cb<dyn FetchCbTrait>,
+    // callbacks that may not be Send, see SendAlpm
+    pub(crate) non_send: Cell<u8>,
+    // owned by the handle and reached through the ctx of the log, download, event and progress
+    // callbacks, so the boxed callbacks never share state with the handle
+    pub(crate) listeners: Listeners,
+}
+
+pub(crate) const LOG_CB: u8 = 1 << 0;
+pub(crate) const DL_CB: u8 = 1 << 1;
+pub(crate) const EVENT_CB: u8 = 1 << 2;
+pub(crate) const PROGRESS_CB: u8 = 1 << 3;
+pub(crate) const QUESTION_CB: u8 = 1 << 4;
+pub(crate) const FETCH_CB: u8 = 1 << 5;
+
+impl Callbacks {
+    pub(crate) fn set_send(&self, cb: u8, send: bool) {
+        let non_send = self.non_send.get();
+        if send {
+            self.non_send.set(non_send & !cb);
+        } else {
+            self.non_send.set(non_send | cb);
+        }
+    }
+
+    pub(crate) fn is_send(&self, cb: u8) -> bool {
+        self.non_send.get() & cb == 0
+    }
 }
 
 pub(crate) trait LogCbTrait {
@@ -145,6 +173,7 @@ pub struct RawLogCb {
     pub(crate) raw: alpm_cb_log,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn LogCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawLogCb {
@@ -157,6 +186,7 @@ pub struct RawDlCb {
     pub(crate) raw: alpm_cb_download,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn DlCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawDlCb {
@@ -169,6 +199,7 @@ pub struct RawEventCb {
     pub(crate) raw: alpm_cb_event,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn EventCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawEventCb {
@@ -181,6 +212,7 @@ pub struct RawProgressCb {
     pub(crate) raw: alpm_cb_progress,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn ProgressCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawProgressCb {
@@ -193,6 +225,7 @@ pub struct RawQuestionCb {
     pub(crate) raw: alpm_cb_question,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn QuestionCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawQuestionCb {
@@ -205,6 +238,7 @@ pub struct RawFetchCb {
     pub(crate) raw: alpm_cb_fetch,
     pub(crate) ctx: *mut c_void,
     pub(crate) cb: Option<Box<dyn FetchCbTrait>>,
+    pub(crate) send: bool,
 }
 
 impl fmt::Debug for RawFetchCb {
@@ -223,12 +257,10 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        let ctx = LogCbImpl(RefCell::new((f, data)));
-        let ctx = Box::new(ctx);
-        let cb = logcb::<LogCbImpl<T, F>>;
-        let cb: unsafe extern "C" fn(_, _, _, _) = unsafe { transmute(cb as *mut c_void) };
-        unsafe { alpm_option_set_logcb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
-        c.replace(ctx);
+        c.replace(Box::new(LogCbImpl(RefCell::new((f, data)))));
+        let cb: unsafe extern "C" fn(_, _, _, _) = unsafe { transmute(logcb as *mut c_void) };
+        unsafe { alpm_option_set_logcb(self.as_ptr(), Some(cb), self.cbs_ctx()) };
+        self.cbs.set_send(LOG_CB, false);
     }
 
     pub fn set_dl_cb<T: 'static, F: FnMut(&str, AnyDownloadEvent, &mut T) + 'static>(
@@ -244,11 +276,9 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        let ctx = DlCbImpl(RefCell::new((f, data)));
-        let ctx = Box::new(ctx);
-        let cb = dlcb::<DlCbImpl<T, F>>;
-        unsafe { alpm_option_set_dlcb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
-        c.replace(ctx);
+        c.replace(Box::new(DlCbImpl(RefCell::new((f, data)))));
+        unsafe { alpm_option_set_dlcb(self.as_ptr(), Some(dlcb), self.cbs_ctx()) };
+        self.cbs.set_send(DL_CB, false);
     }
 
     pub fn set_event_cb<T: 'static, F: FnMut(AnyEvent, &mut T) + 'static>(&self, data: T, f: F) {
@@ -256,11 +286,9 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        let ctx = EventCbImpl(RefCell::new((f, data)));
-        let ctx = Box::new(ctx);
-        let cb = eventcb::<EventCbImpl<T, F>>;
-        unsafe { alpm_option_set_eventcb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
-        c.replace(ctx);
+        c.replace(Box::new(EventCbImpl(RefCell::new((f, data)))));
+        unsafe { alpm_option_set_eventcb(self.as_ptr(), Some(eventcb), self.cbs_ctx()) };
+        self.cbs.set_send(EVENT_CB, false);
     }
 
     pub fn set_progress_cb<
@@ -275,11 +303,9 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        let ctx = ProgressCbImpl(RefCell::new((f, data)));
-        let ctx = Box::new(ctx);
-        let cb = progresscb::<ProgressCbImpl<T, F>>;
-        unsafe { alpm_option_set_progresscb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
-        c.replace(ctx);
+        c.replace(Box::new(ProgressCbImpl(RefCell::new((f, data)))));
+        unsafe { alpm_option_set_progresscb(self.as_ptr(), Some(progresscb), self.cbs_ctx()) };
+        self.cbs.set_send(PROGRESS_CB, false);
     }
 
     pub fn set_question_cb<T: 'static, F: FnMut(AnyQuestion, &mut T) + 'static>(
@@ -296,6 +322,7 @@ impl Alpm {
         let cb = questioncb::<QuestionCbImpl<T, F>>;
         unsafe { alpm_option_set_questioncb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
         c.replace(ctx);
+        self.cbs.set_send(QUESTION_CB, false);
     }
 
     pub fn set_fetch_cb<T: 'static, F: FnMut(&str, &str, bool, &mut T) -> FetchResult + 'static>(
@@ -312,6 +339,13 @@ impl Alpm {
         let cb = fetchcb::<FetchCbImpl<T, F>>;
         unsafe { alpm_option_set_fetchcb(self.as_ptr(), Some(cb), &*ctx as *const _ as *mut _) };
         c.replace(ctx);
+        self.cbs.set_send(FETCH_CB, false);
+    }
+
+    // the ctx of the log, download, event and progress callbacks. the callbacks are looked up
+    // through it when called so a raw callback moved to another handle uses that handle's.
+    fn cbs_ctx(&self) -> *mut c_void {
+        &*self.cbs as *const Callbacks as *mut c_void
     }
 
     pub fn take_raw_log_cb(&self) -> RawLogCb {
@@ -323,9 +357,11 @@ impl Alpm {
         let cb = RawLogCb {
             ctx: unsafe { alpm_option_get_logcb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_logcb(self.as_ptr()) },
+            send: self.cbs.is_send(LOG_CB),
             cb: c.take(),
         };
         unsafe { alpm_option_set_logcb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(LOG_CB, true);
         cb
     }
 
@@ -334,7 +370,13 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        unsafe { alpm_option_set_logcb(self.as_ptr(), cb.raw, cb.ctx) };
+        let ctx = if cb.cb.is_some() {
+            self.cbs_ctx()
+        } else {
+            cb.ctx
+        };
+        unsafe { alpm_option_set_logcb(self.as_ptr(), cb.raw, ctx) };
+        self.cbs.set_send(LOG_CB, cb.send);
         *c = cb.cb
     }
 
@@ -346,9 +388,11 @@ impl Alpm {
         let cb = RawDlCb {
             ctx: unsafe { alpm_option_get_dlcb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_dlcb(self.as_ptr()) },
+            send: self.cbs.is_send(DL_CB),
             cb: c.take(),
         };
         unsafe { alpm_option_set_dlcb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(DL_CB, true);
         cb
     }
 
@@ -357,7 +401,13 @@ impl Alpm {
         if let Some(cb) = c.as_ref() {
             cb.assert_unlocked()
         }
-        unsafe { alpm_option_set_dlcb(self.as_ptr(), cb.raw, cb.ctx) };
+        let ctx = if cb.cb.is_some() {
+            self.cbs_ctx()
+        } else {
+            cb.ctx
+        };
+        unsafe { alpm_option_set_dlcb(self.as_ptr(), cb.raw, ctx) };
+        self.cbs.set_send(DL_CB, cb.send);
         *c = cb.cb
     }
 
@@ -369,9 +419,11 @@ impl Alpm {
         let cb = RawEventCb {
             ctx: unsafe { alpm_option_get_eventcb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_eventcb(self.as_ptr()) },
+            send: self.cbs.is_send(EVENT_CB),
             cb: c.take(),
         };
         unsafe { alpm_option_set_eventcb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(EVENT_CB, true);
         cb
     }
 
@@ -381,7 +433,13 @@ impl Alpm {
             cb.assert_unlocked()
         }
 
-        unsafe { alpm_option_set_eventcb(self.as_ptr(), cb.raw, cb.ctx) };
+        let ctx = if cb.cb.is_some() {
+            self.cbs_ctx()
+        } else {
+            cb.ctx
+        };
+        unsafe { alpm_option_set_eventcb(self.as_ptr(), cb.raw, ctx) };
+        self.cbs.set_send(EVENT_CB, cb.send);
         *c = cb.cb
     }
 
@@ -394,9 +452,11 @@ impl Alpm {
         let cb = RawProgressCb {
             ctx: unsafe { alpm_option_get_progresscb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_progresscb(self.as_ptr()) },
+            send: self.cbs.is_send(PROGRESS_CB),
             cb: c.take(),
         };
         unsafe { alpm_option_set_progresscb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(PROGRESS_CB, true);
         cb
     }
 
@@ -406,7 +466,13 @@ impl Alpm {
             cb.assert_unlocked()
         }
 
-        unsafe { alpm_option_set_progresscb(self.as_ptr(), cb.raw, cb.ctx) };
+        let ctx = if cb.cb.is_some() {
+            self.cbs_ctx()
+        } else {
+            cb.ctx
+        };
+        unsafe { alpm_option_set_progresscb(self.as_ptr(), cb.raw, ctx) };
+        self.cbs.set_send(PROGRESS_CB, cb.send);
         *c = cb.cb;
     }
 
@@ -419,9 +485,11 @@ impl Alpm {
         let cb = RawQuestionCb {
             ctx: unsafe { alpm_option_get_questioncb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_questioncb(self.as_ptr()) },
+            send: self.cbs.is_send(QUESTION_CB),
             cb: c.take(),
         };
         unsafe { alpm_option_set_questioncb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(QUESTION_CB, true);
         cb
     }
 
@@ -432,6 +500,7 @@ impl Alpm {
         }
 
         unsafe { alpm_option_set_questioncb(self.as_ptr(), cb.raw, cb.ctx) };
+        self.cbs.set_send(QUESTION_CB, cb.send);
         *c = cb.cb;
     }
 
@@ -444,10 +513,12 @@ impl Alpm {
         let cb = RawFetchCb {
             ctx: unsafe { alpm_option_get_fetchcb_ctx(self.as_ptr()) },
             raw: unsafe { alpm_option_get_fetchcb(self.as_ptr()) },
+            send: self.cbs.is_send(FETCH_CB),
             cb: c.take(),
         };
 
         unsafe { alpm_option_set_fetchcb(self.as_ptr(), None, ptr::null_mut()) };
+        self.cbs.set_send(FETCH_CB, true);
         cb
     }
 
@@ -458,11 +529,12 @@ impl Alpm {
         }
 
         unsafe { alpm_option_set_fetchcb(self.as_ptr(), cb.raw, cb.ctx) };
+        self.cbs.set_send(FETCH_CB, cb.send);
         *c = cb.cb;
     }
 }
 
-extern "C" fn logcb<C: LogCbTrait>(
+extern "C" fn logcb(
     ctx: *mut c_void,
     level: alpm_loglevel_t,
     fmt: *const c_char,
@@ -474,15 +546,19 @@ extern "C" fn logcb<C: LogCbTrait>(
         let _ = panic::catch_unwind(|| {
             let s = unsafe { CStr::from_ptr(buff) };
             let level = LogLevel::from_bits(level).unwrap();
-            let cb = unsafe { &*(ctx as *const C) };
-            cb.call(level, &s.to_string_lossy());
+            let s = s.to_string_lossy();
+            let cbs = unsafe { &*(ctx as *const Callbacks) };
+            if let Some(cb) = unsafe { &*cbs.log.get() } {
+                cb.call(level, &s);
+            }
+            cbs.listeners.log(level, &s);
         });
 
         unsafe { free(buff as *mut c_void) };
     }
 }
 
-extern "C" fn dlcb<C: DlCbTrait>(
+extern "C" fn dlcb(
     ctx: *mut c_void,
     filename: *const c_char,
     event: alpm_download_event_type_t,
@@ -492,8 +568,11 @@ extern "C" fn dlcb<C: DlCbTrait>(
         let filename = unsafe { CStr::from_ptr(filename) };
         let filename = filename.to_str().unwrap();
         let event = unsafe { AnyDownloadEvent::new(event, data) };
-        let cb = unsafe { &*(ctx as *const C) };
-        cb.call(filename, event);
+        let cbs = unsafe { &*(ctx as *const Callbacks) };
+        if let Some(cb) = unsafe { &*cbs.dl.get() } {
+            cb.call(filename, event.reborrow());
+        }
+        cbs.listeners.dl(filename, &event);
     });
 }
 
@@ -519,12 +598,15 @@ extern "C" fn fetchcb<C: FetchCbTrait>(
     ret.unwrap_or(-1)
 }
 
-extern "C" fn eventcb<C: EventCbTrait>(ctx: *mut c_void, event: *mut alpm_event_t) {
+extern "C" fn eventcb(ctx: *mut c_void, event: *mut alpm_event_t) {
     let _ = panic::catch_unwind(|| {
-        let cb = unsafe { &*(ctx as *const C) };
+        let cbs = unsafe { &*(ctx as *const Callbacks) };
 
         let event = unsafe { AnyEvent::new(event) };
-        cb.call(event);
+        if let Some(cb) = unsafe { &*cbs.event.get() } {
+            cb.call(event.reborrow());
+        }
+        cbs.listeners.event(&event);
     });
 }
 
@@ -536,7 +618,7 @@ extern "C" fn questioncb<C: QuestionCbTrait>(ctx: *mut c_void, question: *mut al
     });
 }
 
-extern "C" fn progresscb<C: ProgressCbTrait>(
+extern "C" fn progresscb(
     ctx: *mut c_void,
     progress: alpm_progress_t,
     pkgname: *const c_char,
@@ -548,9 +630,14 @@ extern "C" fn progresscb<C: ProgressCbTrait>(
         let pkgname = unsafe { CStr::from_ptr(pkgname) };
         let pkgname = pkgname.to_str().unwrap();
         let progress = unsafe { transmute::<alpm_progress_t, Progress>(progress) };
-        let cb = unsafe { &*(ctx as *const C) };
+        let cbs = unsafe { &*(ctx as *const Callbacks) };
         #[allow(clippy::unnecessary_cast)]
-        cb.call(progress, pkgname, percent as i32, howmany, current);
+        let percent = percent as i32;
+        if let Some(cb) = unsafe { &*cbs.progress.get() } {
+            cb.call(progress, pkgname, percent, howmany, current);
+        }
+        cbs.listeners
+            .progress(progress, pkgname, percent, howmany, current);
     });
 }
 
@@ -95,6 +95,25 @@ impl DbMut<'_> {
         let ret = unsafe { alpm_db_remove_server(self.as_ptr(), server.as_ptr()) };
         self.check_ret(ret)
     }
+
+    pub fn add_cache_server<S: Into<Vec<u8>>>(&self, server: S) -> Result<()> {
+        let server = CString::new(server).unwrap();
+        let ret = unsafe { alpm_db_add_cache_server(self.as_ptr(), server.as_ptr()) };
+        self.check_ret(ret)
+    }
+
+    pub fn set_cache_servers<'a, L: AsAlpmList<&'a str>>(&self, list: L) -> Result<()> {
+        list.with(|list| {
+            let ret = unsafe { alpm_db_set_cache_servers(self.as_ptr(), list.as_ptr()) };
+            self.check_ret(ret)
+        })
+    }
+
+    pub fn remove_cache_server<S: Into<Vec<u8>>>(&self, server: S) -> Result<()> {
+        let server = CString::new(server).unwrap();
+        let ret = unsafe { alpm_db_remove_cache_server(self.as_ptr(), server.as_ptr()) };
+        self.check_ret(ret)
+    }
 }
 
 impl Db {
@@ -140,6 +159,11 @@ impl Db {
         unsafe { AlpmList::from_ptr(list) }
     }
 
+    pub fn cache_servers(&self) -> AlpmList<&str> {
+        let list = unsafe { alpm_db_get_cache_servers(self.as_ptr()) };
+        unsafe { AlpmList::from_ptr(list) }
+    }
+
     pub fn pkg<S: Into<Vec<u8>>>(&self, name: S) -> Result<&Package> {
         let name = CString::new(name).unwrap();
         let pkg = unsafe { alpm_db_get_pkg(self.as_ptr(), name.as_ptr()) };
@@ -257,6 +281,22 @@ mod tests {
         assert_eq!(servers, db.servers().iter().collect::<Vec<_>>());
     }
 
+    #[test]
+    fn test_cache_servers() {
+        let mut handle = Alpm::new("/", "tests/db").unwrap();
+        let db = handle.register_syncdb_mut("foo", SigLevel::NONE).unwrap();
+
+        db.set_cache_servers(["a", "bb"].iter().cloned()).unwrap();
+        db.add_cache_server("ccc").unwrap();
+        db.remove_cache_server("a").unwrap();
+
+        assert_eq!(
+            db.cache_servers().iter().collect::<Vec<_>>(),
+            vec!["bb", "ccc"]
+        );
+        assert!(db.servers().is_empty());
+    }
+
     #[test]
     fn test_mut() {
         let mut handle = Alpm::new("/", "tests/db").unwrap();
@@ -0,0 +1,892 @@
+use crate::{
+    Alpm, DownloadEvent, DownloadEventCompleted, DownloadEventInit, DownloadEventProgress,
+    DownloadEventRetry, DownloadResult, FetchResult, SendAlpm,
+};
+
+use alpm_sys::*;
+
+use std::ffi::{CStr, CString, c_void};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Fetched {
+    Downloaded,
+    UpToDate,
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    UnsupportedUrl(String),
+    Io(io::Error),
+    Http(u16),
+    TooManyRedirects,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::UnsupportedUrl(url) => write!(f, "unsupported url: {}", url),
+            DownloadError::Io(e) => e.fmt(f),
+            DownloadError::Http(code) => write!(f, "server returned http status {}", code),
+            DownloadError::TooManyRedirects => f.write_str("too many redirects"),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> DownloadError {
+        DownloadError::Io(e)
+    }
+}
+
+impl DownloadError {
+    /// Whether trying the same url again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Io(_) => true,
+            DownloadError::Http(code) => *code == 408 || *code == 429 || *code >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// Reports the progress of a download as [`DownloadEvent`]s.
+pub struct DownloadProgress<'a> {
+    sink: &'a mut dyn FnMut(DownloadEvent),
+    total: i64,
+}
+
+impl fmt::Debug for DownloadProgress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadProgress")
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl<'a> DownloadProgress<'a> {
+    pub fn new(sink: &'a mut dyn FnMut(DownloadEvent)) -> DownloadProgress<'a> {
+        DownloadProgress { sink, total: 0 }
+    }
+
+    /// Reports that `downloaded` of `total` bytes have been downloaded.
+    ///
+    /// `total` is 0 if the size is not known.
+    pub fn update(&mut self, downloaded: i64, total: i64) {
+        self.total = total.max(downloaded);
+        (self.sink)(DownloadEvent::Progress(DownloadEventProgress {
+            downloaded,
+            total,
+        }))
+    }
+
+    /// Reports that the download is tried again.
+    pub fn retry(&mut self, resume: bool) {
+        self.total = 0;
+        (self.sink)(DownloadEvent::Retry(DownloadEventRetry { resume }))
+    }
+
+    /// The size of the file as last reported.
+    pub fn total(&self) -> i64 {
+        self.total
+    }
+}
+
+/// A transport used by libalpm to download files.
+///
+/// Install a downloader with [`Alpm::set_downloader`]. Events are then sent to the
+/// download callback like they are for libalpm's own downloader.
+///
+/// When a download fails libalpm tries the next server of the db, so mirror fallback
+/// does not have to be handled by the downloader.
+pub trait Downloader {
+    /// Downloads `url` to the file `dest`.
+    ///
+    /// Unless `force` is set, the download may be skipped if `dest` is up to date.
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError>;
+}
+
+impl<D: Downloader + ?Sized> Downloader for Box<D> {
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError> {
+        (**self).download(url, dest, force, progress)
+    }
+}
+
+/// Downloads `file://` urls by copying the file.
+#[derive(Debug, Default, Clone)]
+pub struct FileDownloader;
+
+impl Downloader for FileDownloader {
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError> {
+        let path = strip_scheme(url, "file")
+            .ok_or_else(|| DownloadError::UnsupportedUrl(url.to_string()))?;
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+        let mtime = meta.modified()?;
+
+        if !force
+            && let Ok(dest_meta) = fs::metadata(dest)
+            && dest_meta.len() == meta.len()
+            && dest_meta.modified()? >= mtime
+        {
+            return Ok(Fetched::UpToDate);
+        }
+
+        write_body(file, dest, meta.len() as i64, Some(mtime), progress)?;
+        Ok(Fetched::Downloaded)
+    }
+}
+
+/// Downloads `http://` urls using std networking.
+///
+/// Redirects are followed and `If-Modified-Since` is used to skip files that are up to
+/// date. Downloaded files take their modification time from `Last-Modified`. There is no TLS
+/// support.
+#[derive(Debug, Clone)]
+pub struct HttpDownloader {
+    pub timeout: Option<Duration>,
+    pub max_redirects: u32,
+}
+
+impl Default for HttpDownloader {
+    fn default() -> HttpDownloader {
+        HttpDownloader {
+            timeout: Some(Duration::from_secs(30)),
+            max_redirects: 5,
+        }
+    }
+}
+
+impl Downloader for HttpDownloader {
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError> {
+        let mut url = url.to_string();
+        let since = match force {
+            true => None,
+            false => fs::metadata(dest).and_then(|m| m.modified()).ok(),
+        };
+
+        for _ in 0..=self.max_redirects {
+            let (host, port, path) = split_http_url(&url)?;
+            let stream = connect(host.trim_matches(['[', ']']), port, self.timeout)?;
+            stream.set_read_timeout(self.timeout)?;
+            stream.set_write_timeout(self.timeout)?;
+
+            let mut req = format!("GET {} HTTP/1.0\r\nHost: {}", path, host);
+            if port != 80 {
+                req.push_str(&format!(":{}", port));
+            }
+            req.push_str("\r\nUser-Agent: alpm.rs\r\nAccept: */*\r\n");
+            if let Some(since) = since {
+                req.push_str(&format!("If-Modified-Since: {}\r\n", http_date(since)));
+            }
+            req.push_str("\r\n");
+            (&stream).write_all(req.as_bytes())?;
+
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let status = line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|s| s.parse::<u16>().ok())
+                .ok_or_else(|| invalid_data("invalid http status line"))?;
+
+            let mut len = None;
+            let mut location = None;
+            let mut modified = None;
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Err(invalid_data("unexpected end of http headers").into());
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((key, value)) = line.split_once(':') {
+                    let value = value.trim();
+                    if key.eq_ignore_ascii_case("content-length") {
+                        len = value.parse::<u64>().ok();
+                    } else if key.eq_ignore_ascii_case("location") {
+                        location = Some(value.to_string());
+                    } else if key.eq_ignore_ascii_case("last-modified") {
+                        modified = parse_http_date(value);
+                    }
+                }
+            }
+
+            match status {
+                200 => {
+                    let total = len.unwrap_or(0) as i64;
+                    let written = match len {
+                        Some(len) => write_body(reader.take(len), dest, total, modified, progress)?,
+                        None => write_body(reader, dest, total, modified, progress)?,
+                    };
+                    if len.is_some_and(|len| written != len) {
+                        let _ = fs::remove_file(dest);
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    return Ok(Fetched::Downloaded);
+                }
+                304 => return Ok(Fetched::UpToDate),
+                301 | 302 | 303 | 307 | 308 => {
+                    let location = location.ok_or(DownloadError::Http(status))?;
+                    url = if location.starts_with('/') {
+                        format!("http://{}:{}{}", host, port, location)
+                    } else {
+                        location
+                    };
+                }
+                _ => return Err(DownloadError::Http(status)),
+            }
+        }
+
+        Err(DownloadError::TooManyRedirects)
+    }
+}
+
+/// Downloads `file://` urls with a [`FileDownloader`] and `http://` urls with an
+/// [`HttpDownloader`].
+#[derive(Debug, Default, Clone)]
+pub struct BasicDownloader {
+    pub file: FileDownloader,
+    pub http: HttpDownloader,
+}
+
+impl Downloader for BasicDownloader {
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError> {
+        if strip_scheme(url, "file").is_some() {
+            self.file.download(url, dest, force, progress)
+        } else if strip_scheme(url, "http").is_some() {
+            self.http.download(url, dest, force, progress)
+        } else {
+            Err(DownloadError::UnsupportedUrl(url.to_string()))
+        }
+    }
+}
+
+/// Retries downloads that fail with a retryable error.
+#[derive(Debug, Clone)]
+pub struct RetryDownloader<D> {
+    pub inner: D,
+    /// How often to try a url in total.
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl<D> RetryDownloader<D> {
+    pub fn new(inner: D, attempts: u32) -> RetryDownloader<D> {
+        RetryDownloader {
+            inner,
+            attempts,
+            delay: Duration::from_secs(1),
+        }
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl<D: Downloader> Downloader for RetryDownloader<D> {
+    fn download(
+        &mut self,
+        url: &str,
+        dest: &Path,
+        force: bool,
+        progress: &mut DownloadProgress,
+    ) -> Result<Fetched, DownloadError> {
+        let mut attempt = 1;
+        loop {
+            match self.inner.download(url, dest, force, progress) {
+                Err(e) if e.is_retryable() && attempt < self.attempts => {
+                    attempt += 1;
+                    progress.retry(false);
+                    thread::sleep(self.delay);
+                }
+                ret => return ret,
+            }
+        }
+    }
+}
+
+impl Alpm {
+    /// Downloads files with `downloader` instead of libalpm's own downloader.
+    ///
+    /// This replaces the fetch callback.
+    pub fn set_downloader<D: Downloader + 'static>(&self, downloader: D) {
+        let sink = DlSink(self.as_ptr());
+        self.set_fetch_cb((downloader, sink), |url, localpath, force, (d, sink)| {
+            fetch(d, sink, url, localpath, force)
+        });
+    }
+}
+
+impl SendAlpm {
+    pub fn set_downloader<D: Downloader + Send + 'static>(&mut self, downloader: D) {
+        let sink = DlSink(self.as_ptr());
+        self.set_fetch_cb((downloader, sink), |url, localpath, force, (d, sink)| {
+            fetch(d, sink, url, localpath, force)
+        });
+    }
+}
+
+// sends events to whatever download callback is set on the handle
+struct DlSink(*mut alpm_handle_t);
+
+// the pointer is only used from within the fetch callback, which runs wherever the handle is
+unsafe impl Send for DlSink {}
+
+impl DlSink {
+    fn emit(&self, filename: &CStr, event: DownloadEvent) {
+        let Some(cb) = (unsafe { alpm_option_get_dlcb(self.0) }) else {
+            return;
+        };
+        let ctx = unsafe { alpm_option_get_dlcb_ctx(self.0) };
+        let name = filename.as_ptr();
+
+        unsafe {
+            match event {
+                DownloadEvent::Init(e) => {
+                    let mut data = alpm_download_event_init_t {
+                        optional: e.optional as c_int,
+                    };
+                    cb(
+                        ctx,
+                        name,
+                        alpm_download_event_type_t::ALPM_DOWNLOAD_INIT,
+                        &mut data as *mut _ as *mut c_void,
+                    )
+                }
+                DownloadEvent::Progress(e) => {
+                    let mut data = alpm_download_event_progress_t {
+                        downloaded: e.downloaded as off_t,
+                        total: e.total as off_t,
+                    };
+                    cb(
+                        ctx,
+                        name,
+                        alpm_download_event_type_t::ALPM_DOWNLOAD_PROGRESS,
+                        &mut data as *mut _ as *mut c_void,
+                    )
+                }
+                DownloadEvent::Retry(e) => {
+                    let mut data = alpm_download_event_retry_t {
+                        resume: e.resume as c_int,
+                    };
+                    cb(
+                        ctx,
+                        name,
+                        alpm_download_event_type_t::ALPM_DOWNLOAD_RETRY,
+                        &mut data as *mut _ as *mut c_void,
+                    )
+                }
+                DownloadEvent::Completed(e) => {
+                    let result = match e.result {
+                        DownloadResult::Success => 0,
+                        DownloadResult::UpToDate => 1,
+                        DownloadResult::Failed => -1,
+                    };
+                    let mut data = alpm_download_event_completed_t {
+                        total: e.total as off_t,
+                        result,
+                    };
+                    cb(
+                        ctx,
+                        name,
+                        alpm_download_event_type_t::ALPM_DOWNLOAD_COMPLETED,
+                        &mut data as *mut _ as *mut c_void,
+                    )
+                }
+            }
+        }
+    }
+}
+
+fn fetch(
+    downloader: &mut dyn Downloader,
+    sink: &DlSink,
+    url: &str,
+    localpath: &str,
+    force: bool,
+) -> FetchResult {
+    let Some(name) = file_name(url) else {
+        return FetchResult::Err;
+    };
+    let cname = CString::new(name).unwrap_or_default();
+    let dest = Path::new(localpath).join(name);
+
+    let optional = name.ends_with(".sig");
+    sink.emit(&cname, DownloadEvent::Init(DownloadEventInit { optional }));
+
+    let mut emit = |event| sink.emit(&cname, event);
+    let mut progress = DownloadProgress::new(&mut emit);
+    let ret = downloader.download(url, &dest, force, &mut progress);
+    let total = progress.total();
+
+    let (result, ret) = match ret {
+        Ok(Fetched::Downloaded) => (DownloadResult::Success, FetchResult::Ok),
+        Ok(Fetched::UpToDate) => (DownloadResult::UpToDate, FetchResult::FileExists),
+        Err(_) => (DownloadResult::Failed, FetchResult::Err),
+    };
+    let event = DownloadEventCompleted { total, result };
+    sink.emit(&cname, DownloadEvent::Completed(event));
+    ret
+}
+
+// the last path segment of url, which is used as the name of the file in the cache dir
+fn file_name(url: &str) -> Option<&str> {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let name = url.rsplit('/').next().unwrap_or(url);
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name),
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// writes to a temporary file next to dest which replaces dest once complete
+fn write_body<R: Read>(
+    mut body: R,
+    dest: &Path,
+    total: i64,
+    mtime: Option<SystemTime>,
+    progress: &mut DownloadProgress,
+) -> io::Result<u64> {
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let ret = (|| {
+        let mut file = File::create(&part)?;
+        let mut buf = [0; 64 * 1024];
+        let mut downloaded = 0;
+        progress.update(0, total);
+
+        loop {
+            let n = match body.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            file.write_all(&buf[..n])?;
+            downloaded += n as u64;
+            progress.update(downloaded as i64, total);
+        }
+
+        file.flush()?;
+        if let Some(mtime) = mtime {
+            file.set_modified(mtime)?;
+        }
+        Ok(downloaded)
+    })();
+
+    match ret {
+        Ok(n) => fs::rename(&part, dest).map(|_| n),
+        Err(e) => {
+            let _ = fs::remove_file(&part);
+            Err(e)
+        }
+    }
+}
+
+// the rest of url after `scheme://`, the scheme is matched case-insensitively
+fn strip_scheme<'a>(url: &'a str, scheme: &str) -> Option<&'a str> {
+    let (s, rest) = url.split_once("://")?;
+    s.eq_ignore_ascii_case(scheme).then_some(rest)
+}
+
+fn split_http_url(url: &str) -> Result<(&str, u16, &str), DownloadError> {
+    let invalid = || DownloadError::UnsupportedUrl(url.to_string());
+    let rest = strip_scheme(url, "http").ok_or_else(invalid)?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+
+    let port_sep = match authority.rfind(']') {
+        Some(i) => authority[i..].find(':').map(|j| i + j),
+        None => authority.rfind(':'),
+    };
+    let (host, port) = match port_sep {
+        Some(i) => (
+            &authority[..i],
+            authority[i + 1..].parse().map_err(|_| invalid())?,
+        ),
+        None => (authority, 80),
+    };
+
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host, port, path))
+}
+
+fn connect(host: &str, port: u16, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let Some(timeout) = timeout else {
+        return TcpStream::connect((host, port));
+    };
+
+    let mut err = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => err = Some(e),
+        }
+    }
+    Err(err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, host.to_string())))
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Formats a time as an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as i64;
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let mut parts = s.split_once(", ")?.1.split_whitespace();
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<i64>().ok());
+    let (h, m, sec) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + h * 3600 + m * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnyDownloadEvent, SigLevel};
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::rc::Rc;
+
+    fn tmpdir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("alpm-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // serves one canned response per connection and returns the requests
+    fn serve<R: AsRef<[u8]> + Send + 'static>(
+        responses: Vec<R>,
+    ) -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let thread = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut req = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    req.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                requests.push(req);
+                stream.write_all(response.as_ref()).unwrap();
+            }
+            requests
+        });
+
+        (port, thread)
+    }
+
+    #[test]
+    fn test_file_downloader() {
+        let dir = tmpdir("file-downloader");
+        let src = dir.join("src");
+        let dest = dir.join("dest");
+        fs::write(&src, "hello").unwrap();
+        let url = format!("file://{}", src.display());
+
+        let mut events = Vec::new();
+        let mut sink = |e| events.push(e);
+        let mut progress = DownloadProgress::new(&mut sink);
+        let mut d = FileDownloader;
+
+        let ret = d.download(&url, &dest, false, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::Downloaded);
+        assert_eq!(progress.total(), 5);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "hello");
+
+        let ret = d.download(&url, &dest, false, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::UpToDate);
+        let ret = d.download(&url, &dest, true, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::Downloaded);
+
+        let err = d.download("http://a/b", &dest, false, &mut progress);
+        assert!(matches!(err, Err(DownloadError::UnsupportedUrl(_))));
+
+        assert_eq!(
+            events.last(),
+            Some(&DownloadEvent::Progress(DownloadEventProgress {
+                downloaded: 5,
+                total: 5
+            }))
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_http_downloader() {
+        let dir = tmpdir("http-downloader");
+        let dest = dir.join("core.db");
+        let (port, server) = serve(vec![
+            "HTTP/1.1 301 Moved\r\nLocation: /real/core.db\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\
+             Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\nabcd",
+            "HTTP/1.1 304 Not Modified\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+        ]);
+
+        let mut sink = |_| ();
+        let mut progress = DownloadProgress::new(&mut sink);
+        let mut d = HttpDownloader::default();
+        let url = format!("http://127.0.0.1:{}/core.db", port);
+
+        let ret = d.download(&url, &dest, false, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::Downloaded);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "abcd");
+        let mtime = fs::metadata(&dest).unwrap().modified().unwrap();
+        assert_eq!(mtime, UNIX_EPOCH + Duration::from_secs(784111777));
+
+        let ret = d.download(&url, &dest, false, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::UpToDate);
+
+        let err = d.download(&url, &dest, true, &mut progress);
+        assert!(matches!(err, Err(DownloadError::Http(404))));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /core.db HTTP/1.0\r\n"));
+        assert!(requests[1].starts_with("GET /real/core.db HTTP/1.0\r\n"));
+        assert!(!requests[1].contains("If-Modified-Since"));
+        assert!(requests[2].contains("If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+        assert!(!requests[3].contains("If-Modified-Since"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_retry_downloader() {
+        let dir = tmpdir("retry-downloader");
+        let dest = dir.join("core.db");
+        let (port, server) = serve(vec![
+            "HTTP/1.1 503 Unavailable\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+        ]);
+
+        let mut events = Vec::new();
+        let mut sink = |e| events.push(e);
+        let mut progress = DownloadProgress::new(&mut sink);
+        let mut d = RetryDownloader::new(BasicDownloader::default(), 3).delay(Duration::ZERO);
+        let url = format!("http://127.0.0.1:{}/core.db", port);
+
+        let ret = d.download(&url, &dest, true, &mut progress).unwrap();
+        assert_eq!(ret, Fetched::Downloaded);
+        server.join().unwrap();
+
+        assert_eq!(
+            events[0],
+            DownloadEvent::Retry(DownloadEventRetry { resume: false })
+        );
+        assert!(!DownloadError::Http(404).is_retryable());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_downloader() {
+        let dir = tmpdir("set-downloader");
+        let db = fs::read("tests/db/sync/core.db").unwrap();
+        let mut response =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", db.len()).into_bytes();
+        response.extend(db);
+        let (port, server) = serve(vec![response]);
+
+        let mut handle = Alpm::new("/", dir.to_str().unwrap()).unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        handle.set_dl_cb(events.clone(), |name, e: AnyDownloadEvent, events| {
+            events.borrow_mut().push((name.to_string(), e.event()))
+        });
+        handle.set_downloader(HttpDownloader::default());
+
+        let core = handle.register_syncdb_mut("core", SigLevel::NONE).unwrap();
+        core.add_server(format!("http://127.0.0.1:{}", port))
+            .unwrap();
+        assert!(handle.syncdbs_mut().update(false).unwrap());
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /core.db HTTP/1.0\r\n"));
+        assert!(handle.syncdbs().first().unwrap().pkg("linux").is_ok());
+
+        let events = events.borrow();
+        assert!(events.iter().all(|e| e.0 == "core.db"));
+        assert_eq!(
+            events[0].1,
+            DownloadEvent::Init(DownloadEventInit { optional: false })
+        );
+        assert!(matches!(
+            events.last().unwrap().1,
+            DownloadEvent::Completed(DownloadEventCompleted {
+                result: DownloadResult::Success,
+                ..
+            })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_strip_scheme() {
+        assert_eq!(strip_scheme("file:///srv/repo", "file"), Some("/srv/repo"));
+        assert_eq!(strip_scheme("FILE:///srv/repo", "file"), Some("/srv/repo"));
+        assert_eq!(strip_scheme("Http://a/b", "http"), Some("a/b"));
+        assert_eq!(strip_scheme("https://a/b", "http"), None);
+        assert_eq!(strip_scheme("/srv/file://", "file"), None);
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("http://a/core/core.db"), Some("core.db"));
+        assert_eq!(file_name("http://a/core.db?x=1/y#z"), Some("core.db"));
+        assert_eq!(file_name("http://a/core/"), None);
+        assert_eq!(file_name("http://a/core/."), None);
+        assert_eq!(file_name("http://a/core/..?x=1"), None);
+        assert_eq!(file_name(""), None);
+    }
+
+    #[test]
+    fn test_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(parse_http_date(&http_date(time)), Some(time));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn test_split_http_url() {
+        assert_eq!(
+            split_http_url("http://example.com/a/b").unwrap(),
+            ("example.com", 80, "/a/b")
+        );
+        assert_eq!(
+            split_http_url("http://[::1]:8080").unwrap(),
+            ("[::1]", 8080, "/")
+        );
+        assert!(split_http_url("https://example.com/").is_err());
+        assert!(split_http_url("http://:80/").is_err());
+    }
+}
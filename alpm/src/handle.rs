@@ -174,6 +174,17 @@ impl Alpm {
         unsafe { alpm_option_set_disable_sandbox_filesystem(self.as_ptr(), b) };
     }
 
+    pub fn set_disable_sandbox(&self, b: bool) {
+        let b = if b { 1 } else { 0 };
+        unsafe { alpm_option_set_disable_sandbox(self.as_ptr(), b) };
+    }
+
+    /// Returns true if every component of the sandbox is disabled.
+    #[doc(alias = "disable_sandbox")]
+    pub fn sandbox_disabled(&self) -> bool {
+        unsafe { alpm_option_get_disable_sandbox(self.as_ptr()) == 2 }
+    }
+
     #[doc(alias = "disable_sandbox_filesystem")]
     pub fn sandbox_filesystem_disabled(&self) -> bool {
         unsafe { alpm_option_get_disable_sandbox_filesystem(self.as_ptr()) != 0 }
@@ -438,6 +449,10 @@ impl Alpm {
     pub fn set_parallel_downloads(&self, n: u32) {
         unsafe { alpm_option_set_parallel_downloads(self.as_ptr(), n) };
     }
+
+    pub fn parallel_downloads(&self) -> u32 {
+        unsafe { alpm_option_get_parallel_downloads(self.as_ptr()) as u32 }
+    }
 }
 
 #[cfg(test)]
@@ -488,6 +503,17 @@ mod tests {
             );
         }
 
+        handle.set_parallel_downloads(4);
+        assert_eq!(handle.parallel_downloads(), 4);
+
+        assert!(!handle.sandbox_disabled());
+        handle.set_disable_sandbox(true);
+        assert!(handle.sandbox_disabled());
+        assert!(handle.sandbox_filesystem_disabled());
+        assert!(handle.sandbox_syscalls_disabled());
+        handle.set_disable_sandbox_syscalls(false);
+        assert!(!handle.sandbox_disabled());
+
         handle.set_ignorepkgs(["a", "b", "c"].iter()).unwrap();
         let pkgs = handle.ignorepkgs().iter().collect::<Vec<_>>();
         assert_eq!(pkgs.as_slice(), ["a", "b", "c"]);
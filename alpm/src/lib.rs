@@ -7,28 +7,41 @@
 
 mod add;
 mod alpm;
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "async")]
+mod async_alpm;
 mod be_local;
 mod be_pkg;
 mod be_sync;
+mod builder;
 mod cb;
 mod conflict;
 mod db;
 mod deps;
 mod dload;
+mod downloader;
 mod error;
 mod filelist;
 mod handle;
 mod list;
 mod list_mut;
 mod list_with;
+mod listeners;
 mod log;
+#[cfg(any(feature = "log", feature = "tracing"))]
+mod logging;
 #[cfg(feature = "mtree")]
 mod mtree;
+mod options;
 mod package;
+mod pkginfo;
 mod remove;
 mod sandbox;
+mod shared;
 mod signing;
 mod sync;
+mod tempdir;
 mod trans;
 mod types;
 mod unions;
@@ -38,20 +51,33 @@ mod version;
 
 pub use crate::add::*;
 pub use crate::alpm::*;
+#[cfg(feature = "archive")]
+pub use crate::archive::*;
+#[cfg(feature = "async")]
+pub use crate::async_alpm::*;
 pub use crate::be_pkg::*;
+pub use crate::builder::*;
 pub use crate::cb::*;
 pub use crate::conflict::*;
 pub use crate::db::*;
 pub use crate::deps::*;
+pub use crate::downloader::*;
 pub use crate::error::*;
 pub use crate::filelist::*;
 pub use crate::list::*;
 pub use crate::list_mut::*;
 pub use crate::list_with::*;
+pub use crate::listeners::*;
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub use crate::logging::*;
 #[cfg(feature = "mtree")]
 pub use crate::mtree::*;
+pub use crate::options::*;
 pub use crate::package::*;
+pub use crate::pkginfo::*;
+pub use crate::shared::*;
 pub use crate::signing::*;
+pub(crate) use crate::tempdir::*;
 pub use crate::trans::*;
 pub use crate::types::*;
 pub use crate::unions::*;
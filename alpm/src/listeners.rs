@@ -0,0 +1,342 @@
+use crate::cb::{DL_CB, EVENT_CB, LOG_CB, PROGRESS_CB};
+use crate::{Alpm, AnyDownloadEvent, AnyEvent, AnyQuestion, FetchResult, LogLevel, Progress};
+
+use alpm_sys::*;
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+type Listener<F> = Rc<RefCell<F>>;
+// the bool is set if the listener is known to be Send, see SendAlpm
+type List<F> = RefCell<Vec<(ListenerId, bool, Listener<F>)>>;
+
+type LogListener = dyn FnMut(LogLevel, &str);
+type DlListener = dyn FnMut(&str, AnyDownloadEvent);
+type EventListener = dyn FnMut(AnyEvent);
+type ProgressListener = dyn FnMut(Progress, &str, i32, usize, usize);
+
+/// Identifies a listener added to an [`Alpm`] handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ListenerId(u64);
+
+// the listeners are called by the callbacks set with set_*_cb, after the callback itself.
+#[derive(Default)]
+pub(crate) struct Listeners {
+    next: Cell<u64>,
+    log: List<LogListener>,
+    dl: List<DlListener>,
+    event: List<EventListener>,
+    progress: List<ProgressListener>,
+}
+
+impl Listeners {
+    fn push<F: ?Sized>(&self, list: &List<F>, f: Listener<F>, send: bool) -> ListenerId {
+        let id = ListenerId(self.next.get());
+        self.next.set(id.0 + 1);
+        list.borrow_mut().push((id, send, f));
+        id
+    }
+
+    pub(crate) fn log(&self, level: LogLevel, msg: &str) {
+        dispatch(&self.log, |f| f(level, msg))
+    }
+
+    pub(crate) fn dl(&self, filename: &str, event: &AnyDownloadEvent) {
+        dispatch(&self.dl, |f| f(filename, event.reborrow()))
+    }
+
+    pub(crate) fn event(&self, event: &AnyEvent) {
+        dispatch(&self.event, |f| f(event.reborrow()))
+    }
+
+    pub(crate) fn progress(
+        &self,
+        progress: Progress,
+        pkgname: &str,
+        percent: i32,
+        howmany: usize,
+        current: usize,
+    ) {
+        dispatch(&self.progress, |f| {
+            f(progress, pkgname, percent, howmany, current)
+        })
+    }
+
+    /// Returns true if every listener is known to be Send.
+    pub(crate) fn is_send(&self) -> bool {
+        fn is_send<F: ?Sized>(list: &List<F>) -> bool {
+            list.borrow().iter().all(|(_, send, _)| *send)
+        }
+
+        is_send(&self.log) && is_send(&self.dl) && is_send(&self.event) && is_send(&self.progress)
+    }
+
+    /// Removes the listeners that are not known to be Send. Returns true if any were removed.
+    pub(crate) fn remove_non_send(&self) -> bool {
+        fn remove<F: ?Sized>(list: &List<F>) -> bool {
+            let mut list = list.borrow_mut();
+            let len = list.len();
+            list.retain(|(_, send, _)| *send);
+            list.len() != len
+        }
+
+        // not short circuiting so every list is cleaned
+        remove(&self.log) | remove(&self.dl) | remove(&self.event) | remove(&self.progress)
+    }
+}
+
+// listeners are collected first so they may add or remove listeners while being called.
+// a listener that is already running is skipped.
+fn dispatch<F: ?Sized>(list: &List<F>, mut call: impl FnMut(&mut F)) {
+    let listeners = list
+        .borrow()
+        .iter()
+        .map(|(_, _, l)| l.clone())
+        .collect::<Vec<_>>();
+
+    for listener in listeners {
+        if let Ok(mut listener) = listener.try_borrow_mut() {
+            call(&mut *listener);
+        }
+    }
+}
+
+/// The error returned when setting a callback that is already set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackInUse {
+    name: &'static str,
+}
+
+impl fmt::Display for CallbackInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} callback is already set", self.name)
+    }
+}
+
+impl std::error::Error for CallbackInUse {}
+
+impl CallbackInUse {
+    /// The name of the callback, such as "question".
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Alpm {
+    /// Adds a listener for log messages.
+    ///
+    /// Listeners are called in the order they were added, after the callback set with
+    /// [`Alpm::set_log_cb`]. Setting a callback keeps the listeners.
+    pub fn add_log_listener<F: FnMut(LogLevel, &str) + 'static>(&self, f: F) -> ListenerId {
+        self.add_log_listener_inner(Rc::new(RefCell::new(f)), false)
+    }
+
+    pub(crate) fn add_log_listener_inner(
+        &self,
+        f: Listener<LogListener>,
+        send: bool,
+    ) -> ListenerId {
+        if unsafe { alpm_option_get_logcb(self.as_ptr()).is_none() } {
+            self.set_log_cb((), |_, _, _| ());
+            self.cbs.set_send(LOG_CB, true);
+        }
+        let listeners = &self.cbs.listeners;
+        listeners.push(&listeners.log, f, send)
+    }
+
+    /// Adds a listener for download events.
+    ///
+    /// Listeners are called in the order they were added, after the callback set with
+    /// [`Alpm::set_dl_cb`]. Setting a callback keeps the listeners.
+    pub fn add_dl_listener<F: FnMut(&str, AnyDownloadEvent) + 'static>(&self, f: F) -> ListenerId {
+        self.add_dl_listener_inner(Rc::new(RefCell::new(f)), false)
+    }
+
+    pub(crate) fn add_dl_listener_inner(&self, f: Listener<DlListener>, send: bool) -> ListenerId {
+        if unsafe { alpm_option_get_dlcb(self.as_ptr()).is_none() } {
+            self.set_dl_cb((), |_, _, _| ());
+            self.cbs.set_send(DL_CB, true);
+        }
+        let listeners = &self.cbs.listeners;
+        listeners.push(&listeners.dl, f, send)
+    }
+
+    /// Adds a listener for events.
+    ///
+    /// Listeners are called in the order they were added, after the callback set with
+    /// [`Alpm::set_event_cb`]. Setting a callback keeps the listeners.
+    pub fn add_event_listener<F: FnMut(AnyEvent) + 'static>(&self, f: F) -> ListenerId {
+        self.add_event_listener_inner(Rc::new(RefCell::new(f)), false)
+    }
+
+    pub(crate) fn add_event_listener_inner(
+        &self,
+        f: Listener<EventListener>,
+        send: bool,
+    ) -> ListenerId {
+        if unsafe { alpm_option_get_eventcb(self.as_ptr()).is_none() } {
+            self.set_event_cb((), |_, _| ());
+            self.cbs.set_send(EVENT_CB, true);
+        }
+        let listeners = &self.cbs.listeners;
+        listeners.push(&listeners.event, f, send)
+    }
+
+    /// Adds a listener for progress updates.
+    ///
+    /// Listeners are called in the order they were added, after the callback set with
+    /// [`Alpm::set_progress_cb`]. Setting a callback keeps the listeners.
+    pub fn add_progress_listener<F: FnMut(Progress, &str, i32, usize, usize) + 'static>(
+        &self,
+        f: F,
+    ) -> ListenerId {
+        self.add_progress_listener_inner(Rc::new(RefCell::new(f)), false)
+    }
+
+    pub(crate) fn add_progress_listener_inner(
+        &self,
+        f: Listener<ProgressListener>,
+        send: bool,
+    ) -> ListenerId {
+        if unsafe { alpm_option_get_progresscb(self.as_ptr()).is_none() } {
+            self.set_progress_cb((), |_, _, _, _, _, _| ());
+            self.cbs.set_send(PROGRESS_CB, true);
+        }
+        let listeners = &self.cbs.listeners;
+        listeners.push(&listeners.progress, f, send)
+    }
+
+    /// Removes a listener. Returns false if there is no listener with this id.
+    pub fn remove_listener(&self, id: ListenerId) -> bool {
+        fn remove<F: ?Sized>(list: &List<F>, id: ListenerId) -> bool {
+            let mut list = list.borrow_mut();
+            let len = list.len();
+            list.retain(|(i, _, _)| *i != id);
+            list.len() != len
+        }
+
+        let listeners = &self.cbs.listeners;
+        remove(&listeners.log, id)
+            || remove(&listeners.dl, id)
+            || remove(&listeners.event, id)
+            || remove(&listeners.progress, id)
+    }
+
+    /// Sets the question callback unless one is already set.
+    ///
+    /// There can only be one question callback as it has to answer the questions.
+    pub fn try_set_question_cb<T: 'static, F: FnMut(AnyQuestion, &mut T) + 'static>(
+        &self,
+        data: T,
+        f: F,
+    ) -> Result<(), CallbackInUse> {
+        if unsafe { alpm_option_get_questioncb(self.as_ptr()).is_some() } {
+            return Err(CallbackInUse { name: "question" });
+        }
+        self.set_question_cb(data, f);
+        Ok(())
+    }
+
+    /// Sets the fetch callback unless one is already set.
+    ///
+    /// There can only be one fetch callback as it has to download the files.
+    pub fn try_set_fetch_cb<
+        T: 'static,
+        F: FnMut(&str, &str, bool, &mut T) -> FetchResult + 'static,
+    >(
+        &self,
+        data: T,
+        f: F,
+    ) -> Result<(), CallbackInUse> {
+        if unsafe { alpm_option_get_fetchcb(self.as_ptr()).is_some() } {
+            return Err(CallbackInUse { name: "fetch" });
+        }
+        self.set_fetch_cb(data, f);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SigLevel;
+
+    #[test]
+    fn test_listeners() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+
+        let c = calls.clone();
+        let first = handle.add_log_listener(move |_, _| c.borrow_mut().push(1));
+        let c = calls.clone();
+        let second = handle.add_log_listener(move |_, _| c.borrow_mut().push(2));
+
+        handle.register_syncdb("core", SigLevel::NONE).unwrap();
+        handle.syncdbs().first().unwrap().pkgs();
+        let n = calls.borrow().len();
+        assert!(n >= 2);
+        assert_eq!(calls.borrow()[..2], [1, 2]);
+
+        assert!(handle.remove_listener(first));
+        assert!(!handle.remove_listener(first));
+        calls.borrow_mut().clear();
+        handle.localdb().pkgs();
+        assert!(calls.borrow().iter().all(|&c| c == 2));
+
+        let cb_calls = Rc::new(Cell::new(0));
+        let c = cb_calls.clone();
+        handle.set_log_cb((), move |_, _, _| c.set(c.get() + 1));
+        calls.borrow_mut().clear();
+        handle.localdb().pkgs();
+        assert!(cb_calls.get() > 0);
+        assert_eq!(calls.borrow().len(), cb_calls.get());
+        assert!(handle.remove_listener(second));
+    }
+
+    #[test]
+    fn test_listeners_raw_cb() {
+        let first = Alpm::new("/", "tests/db").unwrap();
+        let second = Alpm::new("/", "tests/db").unwrap();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+
+        let c = calls.clone();
+        first.add_log_listener(move |_, _| c.borrow_mut().push(1));
+        let c = calls.clone();
+        second.add_log_listener(move |_, _| c.borrow_mut().push(2));
+        let cb_calls = Rc::new(Cell::new(0));
+        let c = cb_calls.clone();
+        first.set_log_cb((), move |_, _, _| c.set(c.get() + 1));
+
+        let raw = first.take_raw_log_cb();
+        drop(first);
+        second.set_raw_log_cb(raw);
+        second.localdb().pkgs();
+        assert!(cb_calls.get() > 0);
+        assert!(calls.borrow().iter().all(|&c| c == 2));
+        assert_eq!(calls.borrow().len(), cb_calls.get());
+    }
+
+    #[test]
+    fn test_try_set_cb() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+
+        handle.try_set_question_cb((), |_, _| ()).unwrap();
+        let err = handle.try_set_question_cb((), |_, _| ()).unwrap_err();
+        assert_eq!(err.name(), "question");
+        assert_eq!(err.to_string(), "question callback is already set");
+
+        handle
+            .try_set_fetch_cb((), |_, _, _, _| FetchResult::Ok)
+            .unwrap();
+        assert!(
+            handle
+                .try_set_fetch_cb((), |_, _, _, _| FetchResult::Ok)
+                .is_err()
+        );
+        drop(handle.take_raw_fetch_cb());
+        handle
+            .try_set_fetch_cb((), |_, _, _, _| FetchResult::Ok)
+            .unwrap();
+    }
+}
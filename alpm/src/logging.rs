@@ -0,0 +1,138 @@
+use crate::{Alpm, ListenerId, LogLevel, SendAlpm};
+
+/// The target that messages from libalpm are logged with.
+pub const LOG_TARGET: &str = "libalpm";
+
+#[cfg(feature = "log")]
+fn log_level(level: LogLevel) -> log::Level {
+    if level.contains(LogLevel::ERROR) {
+        log::Level::Error
+    } else if level.contains(LogLevel::WARNING) {
+        log::Level::Warn
+    } else if level.contains(LogLevel::DEBUG) {
+        log::Level::Debug
+    } else {
+        log::Level::Trace
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_listener(level: LogLevel, msg: &str) {
+    let msg = msg.trim_end();
+    if !msg.is_empty() {
+        log::log!(target: LOG_TARGET, log_level(level), "{}", msg);
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn tracing_listener(level: LogLevel, msg: &str) {
+    let msg = msg.trim_end();
+    if msg.is_empty() {
+        return;
+    }
+
+    if level.contains(LogLevel::ERROR) {
+        tracing::error!(target: LOG_TARGET, "{}", msg);
+    } else if level.contains(LogLevel::WARNING) {
+        tracing::warn!(target: LOG_TARGET, "{}", msg);
+    } else if level.contains(LogLevel::DEBUG) {
+        tracing::debug!(target: LOG_TARGET, "{}", msg);
+    } else {
+        tracing::trace!(target: LOG_TARGET, "{}", msg);
+    }
+}
+
+impl Alpm {
+    /// Adds a log listener that forwards messages to the `log` crate.
+    ///
+    /// Messages are logged with the target [`LOG_TARGET`] and without trailing whitespace.
+    /// ERROR, WARNING, DEBUG and FUNCTION map to error, warn, debug and trace.
+    #[cfg(feature = "log")]
+    pub fn forward_logs_to_log(&self) -> ListenerId {
+        self.add_log_listener(log_listener)
+    }
+
+    /// Adds a log listener that forwards messages to `tracing`.
+    ///
+    /// Messages are logged with the target [`LOG_TARGET`] and without trailing whitespace.
+    /// ERROR, WARNING, DEBUG and FUNCTION map to error, warn, debug and trace.
+    #[cfg(feature = "tracing")]
+    pub fn forward_logs_to_tracing(&self) -> ListenerId {
+        self.add_log_listener(tracing_listener)
+    }
+}
+
+impl SendAlpm {
+    #[cfg(feature = "log")]
+    pub fn forward_logs_to_log(&mut self) -> ListenerId {
+        self.add_log_listener(log_listener)
+    }
+
+    #[cfg(feature = "tracing")]
+    pub fn forward_logs_to_tracing(&mut self) -> ListenerId {
+        self.add_log_listener(tracing_listener)
+    }
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use super::*;
+    use crate::SigLevel;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    static RECORDS: Mutex<Vec<(String, log::Level, String)>> = Mutex::new(Vec::new());
+
+    struct TestLogger;
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let record = (
+                record.target().to_string(),
+                record.level(),
+                record.args().to_string(),
+            );
+            RECORDS.lock().unwrap().push(record);
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(LogLevel::ERROR), log::Level::Error);
+        assert_eq!(log_level(LogLevel::WARNING), log::Level::Warn);
+        assert_eq!(log_level(LogLevel::DEBUG), log::Level::Debug);
+        assert_eq!(log_level(LogLevel::FUNCTION), log::Level::Trace);
+    }
+
+    #[test]
+    fn test_forward_logs_to_log() {
+        log::set_logger(&TestLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let c = calls.clone();
+        handle.set_log_cb((), move |_, _, _| c.set(c.get() + 1));
+        let id = handle.forward_logs_to_log();
+
+        handle.register_syncdb("core", SigLevel::NONE).unwrap();
+        handle.syncdbs().first().unwrap().pkgs();
+
+        let records = RECORDS.lock().unwrap().clone();
+        assert!(!records.is_empty());
+        assert!(records.len() <= calls.get());
+        assert!(records.iter().all(|(target, _, _)| target == LOG_TARGET));
+        assert!(records.iter().all(|(_, _, msg)| msg == msg.trim_end()));
+
+        assert!(handle.remove_listener(id));
+        handle.localdb().pkgs();
+        assert_eq!(RECORDS.lock().unwrap().len(), records.len());
+    }
+}
@@ -0,0 +1,625 @@
+use crate::{Alpm, Depend, Result, SigLevel, SyncDbOptions, Usage};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandleOptions {
+    pub root: String,
+    pub dbpath: String,
+    pub cachedirs: Vec<String>,
+    pub hookdirs: Vec<String>,
+    pub gpgdir: Option<String>,
+    pub logfile: Option<String>,
+    pub use_syslog: bool,
+    pub noupgrades: Vec<String>,
+    pub noextracts: Vec<String>,
+    pub ignorepkgs: Vec<String>,
+    pub ignoregroups: Vec<String>,
+    pub overwrite_files: Vec<String>,
+    pub assume_installed: Vec<String>,
+    pub architectures: Vec<String>,
+    pub check_space: bool,
+    pub dbext: String,
+    pub default_siglevel: SigLevel,
+    pub local_file_siglevel: SigLevel,
+    pub remote_file_siglevel: SigLevel,
+    pub disable_dl_timeout: bool,
+    pub parallel_downloads: u32,
+    pub sandbox_user: Option<String>,
+    pub disable_sandbox_filesystem: bool,
+    pub disable_sandbox_syscalls: bool,
+    pub syncdbs: Vec<SyncDbOptions>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OptionDiff {
+    pub section: String,
+    pub option: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for OptionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}: {} -> {}",
+            self.section,
+            self.option,
+            self.old.as_deref().unwrap_or("(unset)"),
+            self.new.as_deref().unwrap_or("(unset)")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HandleOptionsParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for HandleOptionsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for HandleOptionsParseError {}
+
+impl HandleOptions {
+    pub fn snapshot(handle: &Alpm) -> HandleOptions {
+        let strings = |list: crate::AlpmList<&str>| list.iter().map(|s| s.to_string()).collect();
+
+        HandleOptions {
+            root: handle.root().to_string(),
+            dbpath: handle.dbpath().to_string(),
+            cachedirs: strings(handle.cachedirs()),
+            hookdirs: strings(handle.hookdirs()),
+            gpgdir: handle.gpgdir().map(|s| s.to_string()),
+            logfile: handle.logfile().map(|s| s.to_string()),
+            use_syslog: handle.use_syslog(),
+            noupgrades: strings(handle.noupgrades()),
+            noextracts: strings(handle.noextracts()),
+            ignorepkgs: strings(handle.ignorepkgs()),
+            ignoregroups: strings(handle.ignoregroups()),
+            overwrite_files: strings(handle.overwrite_files()),
+            assume_installed: handle
+                .assume_installed()
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            architectures: strings(handle.architectures()),
+            check_space: handle.check_space(),
+            dbext: handle.dbext().to_string(),
+            default_siglevel: handle.default_siglevel(),
+            local_file_siglevel: handle.local_file_siglevel(),
+            remote_file_siglevel: handle.remote_file_siglevel(),
+            disable_dl_timeout: handle.dl_timeout_disabled(),
+            parallel_downloads: handle.parallel_downloads(),
+            sandbox_user: handle.sandbox_user().map(|s| s.to_string()),
+            disable_sandbox_filesystem: handle.sandbox_filesystem_disabled(),
+            disable_sandbox_syscalls: handle.sandbox_syscalls_disabled(),
+            syncdbs: handle
+                .syncdbs()
+                .iter()
+                .map(|db| SyncDbOptions {
+                    name: db.name().to_string(),
+                    servers: strings(db.servers()),
+                    cache_servers: strings(db.cache_servers()),
+                    sig_level: db.siglevel(),
+                    usage: db.usage().unwrap_or(Usage::ALL),
+                })
+                .collect(),
+        }
+    }
+
+    /// Applies the options to a handle.
+    ///
+    /// The root and dbpath can not be changed after a handle is created and are left as is.
+    /// Every sync db of the handle is unregistered and replaced with the ones in the options.
+    pub fn apply(&self, handle: &mut Alpm) -> Result<()> {
+        handle.set_cachedirs(self.cachedirs.iter())?;
+        handle.set_hookdirs(self.hookdirs.iter())?;
+        if let Some(dir) = &self.gpgdir {
+            handle.set_gpgdir(&**dir)?;
+        }
+        if let Some(file) = &self.logfile {
+            handle.set_logfile(&**file)?;
+        }
+        handle.set_use_syslog(self.use_syslog);
+        handle.set_noupgrades(self.noupgrades.iter())?;
+        handle.set_noextracts(self.noextracts.iter())?;
+        handle.set_ignorepkgs(self.ignorepkgs.iter())?;
+        handle.set_ignoregroups(self.ignoregroups.iter())?;
+        handle.set_overwrite_files(self.overwrite_files.iter())?;
+
+        let deps = self
+            .assume_installed
+            .iter()
+            .map(|d| Depend::new(&**d))
+            .collect::<Vec<_>>();
+        handle.set_assume_installed(deps.iter().map(|d| &**d))?;
+
+        handle.set_architectures(self.architectures.iter())?;
+        handle.set_check_space(self.check_space);
+        handle.set_dbext(&*self.dbext);
+        handle.set_default_siglevel(self.default_siglevel)?;
+        handle.set_local_file_siglevel(self.local_file_siglevel)?;
+        handle.set_remote_file_siglevel(self.remote_file_siglevel)?;
+        handle.set_disable_dl_timeout(self.disable_dl_timeout);
+        handle.set_parallel_downloads(self.parallel_downloads);
+        handle.set_sandbox_user(self.sandbox_user.as_deref())?;
+        handle.set_disable_sandbox_filesystem(self.disable_sandbox_filesystem);
+        handle.set_disable_sandbox_syscalls(self.disable_sandbox_syscalls);
+
+        handle.unregister_all_syncdbs()?;
+        for db in &self.syncdbs {
+            let syncdb = handle.register_syncdb_mut(&*db.name, db.sig_level)?;
+            syncdb.set_servers(db.servers.iter())?;
+            syncdb.set_cache_servers(db.cache_servers.iter())?;
+            syncdb.set_usage(db.usage)?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists every option that differs between `self` and `other`.
+    ///
+    /// Sync dbs are matched by name.
+    pub fn diff(&self, other: &HandleOptions) -> Vec<OptionDiff> {
+        let old = self.entries();
+        let new = other.entries();
+        let mut diff = Vec::new();
+
+        let mut sections = Vec::new();
+        for (section, _, _) in old.iter().chain(&new) {
+            if !sections.contains(&section) {
+                sections.push(section);
+            }
+        }
+
+        for section in sections {
+            let old = section_entries(&old, section);
+            let new = section_entries(&new, section);
+            let mut options = old.iter().map(|(o, _)| *o).collect::<Vec<_>>();
+            for (option, _) in &new {
+                if !options.contains(option) {
+                    options.push(option);
+                }
+            }
+
+            for option in options {
+                let old_value = joined(&old, option);
+                let new_value = joined(&new, option);
+                if old_value != new_value {
+                    diff.push(OptionDiff {
+                        section: section.clone(),
+                        option,
+                        old: old_value,
+                        new: new_value,
+                    });
+                }
+            }
+        }
+
+        diff
+    }
+
+    fn entries(&self) -> Vec<(String, &'static str, String)> {
+        let mut entries = Vec::new();
+        let options = "options";
+        let mut push = |section: &str, option: &'static str, value: &str| {
+            entries.push((section.to_string(), option, value.to_string()))
+        };
+
+        push(options, "RootDir", &self.root);
+        push(options, "DBPath", &self.dbpath);
+        for dir in &self.cachedirs {
+            push(options, "CacheDir", dir);
+        }
+        for dir in &self.hookdirs {
+            push(options, "HookDir", dir);
+        }
+        if let Some(dir) = &self.gpgdir {
+            push(options, "GPGDir", dir);
+        }
+        if let Some(file) = &self.logfile {
+            push(options, "LogFile", file);
+        }
+        if self.use_syslog {
+            push(options, "UseSyslog", "");
+        }
+        for (option, list) in [
+            ("NoUpgrade", &self.noupgrades),
+            ("NoExtract", &self.noextracts),
+            ("IgnorePkg", &self.ignorepkgs),
+            ("IgnoreGroup", &self.ignoregroups),
+            ("OverwriteFile", &self.overwrite_files),
+            ("AssumeInstalled", &self.assume_installed),
+            ("Architecture", &self.architectures),
+        ] {
+            for value in list {
+                push(options, option, value);
+            }
+        }
+        if self.check_space {
+            push(options, "CheckSpace", "");
+        }
+        push(options, "DBExt", &self.dbext);
+        push(options, "SigLevel", &format_siglevel(self.default_siglevel));
+        push(
+            options,
+            "LocalFileSigLevel",
+            &format_siglevel(self.local_file_siglevel),
+        );
+        push(
+            options,
+            "RemoteFileSigLevel",
+            &format_siglevel(self.remote_file_siglevel),
+        );
+        if self.disable_dl_timeout {
+            push(options, "DisableDownloadTimeout", "");
+        }
+        push(
+            options,
+            "ParallelDownloads",
+            &self.parallel_downloads.to_string(),
+        );
+        if let Some(user) = &self.sandbox_user {
+            push(options, "DownloadUser", user);
+        }
+        if self.disable_sandbox_filesystem {
+            push(options, "DisableSandboxFilesystem", "");
+        }
+        if self.disable_sandbox_syscalls {
+            push(options, "DisableSandboxSyscalls", "");
+        }
+
+        for db in &self.syncdbs {
+            push(&db.name, "SigLevel", &format_siglevel(db.sig_level));
+            push(&db.name, "Usage", &format_usage(db.usage));
+            for server in &db.servers {
+                push(&db.name, "Server", server);
+            }
+            for server in &db.cache_servers {
+                push(&db.name, "CacheServer", server);
+            }
+        }
+
+        entries
+    }
+}
+
+/// Formats the options in pacman.conf syntax.
+///
+/// Every option is written, even if it has the default value, so that the exact
+/// configuration can be restored with [`HandleOptions::from_str`].
+impl fmt::Display for HandleOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut section = None;
+
+        for (s, option, value) in self.entries() {
+            if section.as_ref() != Some(&s) {
+                if section.is_some() {
+                    writeln!(f)?;
+                }
+                writeln!(f, "[{}]", s)?;
+                section = Some(s);
+            }
+
+            if value.is_empty() {
+                writeln!(f, "{}", option)?;
+            } else {
+                writeln!(f, "{} = {}", option, value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for HandleOptions {
+    type Err = HandleOptionsParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut opts = HandleOptions {
+            root: String::new(),
+            dbpath: String::new(),
+            cachedirs: Vec::new(),
+            hookdirs: Vec::new(),
+            gpgdir: None,
+            logfile: None,
+            use_syslog: false,
+            noupgrades: Vec::new(),
+            noextracts: Vec::new(),
+            ignorepkgs: Vec::new(),
+            ignoregroups: Vec::new(),
+            overwrite_files: Vec::new(),
+            assume_installed: Vec::new(),
+            architectures: Vec::new(),
+            check_space: false,
+            dbext: String::new(),
+            default_siglevel: SigLevel::NONE,
+            local_file_siglevel: SigLevel::NONE,
+            remote_file_siglevel: SigLevel::NONE,
+            disable_dl_timeout: false,
+            parallel_downloads: 1,
+            sandbox_user: None,
+            disable_sandbox_filesystem: false,
+            disable_sandbox_syscalls: false,
+            syncdbs: Vec::new(),
+        };
+        let mut section = None;
+
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            let err = |msg: String| HandleOptionsParseError { line: n + 1, msg };
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if name != "options" {
+                    opts.syncdbs.push(SyncDbOptions::new(name));
+                }
+                section = Some(name.to_string());
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (line, ""),
+            };
+            let value_string = || value.to_string();
+            let siglevel =
+                || parse_siglevel(value).ok_or_else(|| err(format!("invalid siglevel: {}", value)));
+
+            match section.as_deref() {
+                None => return Err(err("option outside of a section".to_string())),
+                Some("options") => match key {
+                    "RootDir" => opts.root = value_string(),
+                    "DBPath" => opts.dbpath = value_string(),
+                    "CacheDir" => opts.cachedirs.push(value_string()),
+                    "HookDir" => opts.hookdirs.push(value_string()),
+                    "GPGDir" => opts.gpgdir = Some(value_string()),
+                    "LogFile" => opts.logfile = Some(value_string()),
+                    "UseSyslog" => opts.use_syslog = true,
+                    "NoUpgrade" => opts.noupgrades.push(value_string()),
+                    "NoExtract" => opts.noextracts.push(value_string()),
+                    "IgnorePkg" => opts.ignorepkgs.push(value_string()),
+                    "IgnoreGroup" => opts.ignoregroups.push(value_string()),
+                    "OverwriteFile" => opts.overwrite_files.push(value_string()),
+                    "AssumeInstalled" => opts.assume_installed.push(value_string()),
+                    "Architecture" => opts.architectures.push(value_string()),
+                    "CheckSpace" => opts.check_space = true,
+                    "DBExt" => opts.dbext = value_string(),
+                    "SigLevel" => opts.default_siglevel = siglevel()?,
+                    "LocalFileSigLevel" => opts.local_file_siglevel = siglevel()?,
+                    "RemoteFileSigLevel" => opts.remote_file_siglevel = siglevel()?,
+                    "DisableDownloadTimeout" => opts.disable_dl_timeout = true,
+                    "ParallelDownloads" => {
+                        opts.parallel_downloads = value
+                            .parse()
+                            .map_err(|_| err(format!("invalid number: {}", value)))?
+                    }
+                    "DownloadUser" => opts.sandbox_user = Some(value_string()),
+                    "DisableSandboxFilesystem" => opts.disable_sandbox_filesystem = true,
+                    "DisableSandboxSyscalls" => opts.disable_sandbox_syscalls = true,
+                    _ => return Err(err(format!("unknown option: {}", key))),
+                },
+                Some(_) => {
+                    let db = opts.syncdbs.last_mut().unwrap();
+                    match key {
+                        "Server" => db.servers.push(value_string()),
+                        "CacheServer" => db.cache_servers.push(value_string()),
+                        "SigLevel" => db.sig_level = siglevel()?,
+                        "Usage" => {
+                            db.usage = parse_usage(value)
+                                .ok_or_else(|| err(format!("invalid usage: {}", value)))?
+                        }
+                        _ => return Err(err(format!("unknown option: {}", key))),
+                    }
+                }
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+fn section_entries<'a>(
+    entries: &'a [(String, &'static str, String)],
+    section: &str,
+) -> Vec<(&'static str, &'a str)> {
+    entries
+        .iter()
+        .filter(|(s, _, _)| s == section)
+        .map(|(_, o, v)| (*o, v.as_str()))
+        .collect()
+}
+
+fn joined(entries: &[(&'static str, &str)], option: &str) -> Option<String> {
+    let values = entries
+        .iter()
+        .filter(|(o, _)| *o == option)
+        .map(|(_, v)| *v)
+        .collect::<Vec<_>>();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(" "))
+    }
+}
+
+/// Formats a siglevel falling back to the raw bits if it can not be expressed in
+/// pacman.conf syntax.
+fn format_siglevel(sig: SigLevel) -> String {
+    let s = sig.to_string();
+    if s.parse::<SigLevel>() == Ok(sig) {
+        s
+    } else {
+        format!("{:#x}", sig.bits())
+    }
+}
+
+fn parse_siglevel(s: &str) -> Option<SigLevel> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(SigLevel::from_bits)
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn format_usage(usage: Usage) -> String {
+    if usage.contains(Usage::ALL) {
+        return "All".to_string();
+    }
+
+    let names = [
+        (Usage::SYNC, "Sync"),
+        (Usage::SEARCH, "Search"),
+        (Usage::INSTALL, "Install"),
+        (Usage::UPGRADE, "Upgrade"),
+    ];
+    names
+        .iter()
+        .filter(|(flag, _)| usage.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_usage(s: &str) -> Option<Usage> {
+    let mut usage = Usage::NONE;
+
+    for word in s.split_whitespace() {
+        usage |= match word {
+            "Sync" => Usage::SYNC,
+            "Search" => Usage::SEARCH,
+            "Install" => Usage::INSTALL,
+            "Upgrade" => Usage::UPGRADE,
+            "All" => Usage::ALL,
+            _ => return None,
+        };
+    }
+
+    Some(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle() -> Alpm {
+        let mut handle = Alpm::new("/", "tests/db").unwrap();
+        handle
+            .set_cachedirs(["/var/cache/pacman/pkg"].iter())
+            .unwrap();
+        handle.set_ignorepkgs(["linux", "glibc"].iter()).unwrap();
+        handle.set_architectures(["x86_64"].iter()).unwrap();
+        handle.set_parallel_downloads(5);
+        handle.set_disable_sandbox_syscalls(true);
+        handle.set_sandbox_user(Some("alpm")).unwrap();
+        let db = handle.register_syncdb_mut("core", SigLevel::NONE).unwrap();
+        db.set_servers(["https://example.com/core"].iter()).unwrap();
+        db.set_cache_servers(["http://cache.lan/core"].iter())
+            .unwrap();
+        db.set_usage(Usage::SYNC | Usage::SEARCH).unwrap();
+        handle.register_syncdb("extra", SigLevel::NONE).unwrap();
+        handle
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let handle = handle();
+        let opts = HandleOptions::snapshot(&handle);
+
+        assert_eq!(opts.cachedirs, ["/var/cache/pacman/pkg/"]);
+        assert_eq!(opts.ignorepkgs, ["linux", "glibc"]);
+        assert_eq!(opts.parallel_downloads, 5);
+        assert!(opts.disable_sandbox_syscalls);
+        assert!(!opts.disable_sandbox_filesystem);
+        assert_eq!(opts.sandbox_user.as_deref(), Some("alpm"));
+        assert_eq!(opts.syncdbs.len(), 2);
+        assert_eq!(opts.syncdbs[0].name, "core");
+        assert_eq!(opts.syncdbs[0].servers, ["https://example.com/core"]);
+        assert_eq!(opts.syncdbs[0].cache_servers, ["http://cache.lan/core"]);
+        assert_eq!(opts.syncdbs[0].usage, Usage::SYNC | Usage::SEARCH);
+        assert_eq!(opts.syncdbs[1].usage, Usage::ALL);
+    }
+
+    #[test]
+    fn test_apply() {
+        let opts = HandleOptions::snapshot(&handle());
+
+        let mut other = Alpm::new("/", "tests/db").unwrap();
+        other.register_syncdb("foo", SigLevel::NONE).unwrap();
+        assert!(!HandleOptions::snapshot(&other).diff(&opts).is_empty());
+
+        opts.apply(&mut other).unwrap();
+        assert_eq!(HandleOptions::snapshot(&other), opts);
+        assert!(HandleOptions::snapshot(&other).diff(&opts).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let opts = HandleOptions::snapshot(&handle());
+        let mut other = opts.clone();
+        other.parallel_downloads = 1;
+        other.ignorepkgs.pop();
+        other.syncdbs.pop();
+
+        let diff = opts.diff(&other);
+        assert_eq!(
+            diff,
+            [
+                OptionDiff {
+                    section: "options".into(),
+                    option: "IgnorePkg",
+                    old: Some("linux glibc".into()),
+                    new: Some("linux".into()),
+                },
+                OptionDiff {
+                    section: "options".into(),
+                    option: "ParallelDownloads",
+                    old: Some("5".into()),
+                    new: Some("1".into()),
+                },
+                OptionDiff {
+                    section: "extra".into(),
+                    option: "SigLevel",
+                    old: Some("Never".into()),
+                    new: None,
+                },
+                OptionDiff {
+                    section: "extra".into(),
+                    option: "Usage",
+                    old: Some("All".into()),
+                    new: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let opts = HandleOptions::snapshot(&handle());
+        let s = opts.to_string();
+
+        assert!(s.contains("[options]\nRootDir = /\n"));
+        assert!(s.contains("\nIgnorePkg = linux\nIgnorePkg = glibc\n"));
+        assert!(s.contains("\nDisableSandboxSyscalls\n"));
+        assert!(s.contains("\n[core]\nSigLevel = Never\nUsage = Sync Search\n"));
+        assert_eq!(s.parse::<HandleOptions>().unwrap(), opts);
+
+        let err = "[options]\nFoo = bar\n"
+            .parse::<HandleOptions>()
+            .unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
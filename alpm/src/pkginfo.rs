@@ -0,0 +1,424 @@
+use crate::Pkg;
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InfoParseError {
+    /// A line is not of the form `key = value`.
+    MissingEquals(usize),
+    /// A numeric field is not a number.
+    InvalidNumber { line: usize, key: String },
+    /// A required field is missing.
+    MissingField(&'static str),
+}
+
+impl fmt::Display for InfoParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InfoParseError::MissingEquals(line) => {
+                write!(f, "line {}: expected 'key = value'", line)
+            }
+            InfoParseError::InvalidNumber { line, key } => {
+                write!(f, "line {}: {} is not a number", line, key)
+            }
+            InfoParseError::MissingField(key) => write!(f, "missing field {}", key),
+        }
+    }
+}
+
+impl std::error::Error for InfoParseError {}
+
+/// The contents of a package's `.PKGINFO`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PkgInfo {
+    pub pkgname: String,
+    pub pkgbase: Option<String>,
+    pub xdata: Vec<String>,
+    pub pkgver: String,
+    pub pkgdesc: Option<String>,
+    pub url: Option<String>,
+    pub builddate: i64,
+    pub packager: Option<String>,
+    pub size: i64,
+    pub arch: Option<String>,
+    pub license: Vec<String>,
+    pub replaces: Vec<String>,
+    pub group: Vec<String>,
+    pub conflict: Vec<String>,
+    pub provides: Vec<String>,
+    pub backup: Vec<String>,
+    pub depend: Vec<String>,
+    pub optdepend: Vec<String>,
+    pub makedepend: Vec<String>,
+    pub checkdepend: Vec<String>,
+    /// Fields this crate does not know about, in the order they appeared.
+    pub extra: Vec<(String, String)>,
+}
+
+/// The contents of a package's `.BUILDINFO`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BuildInfo {
+    pub format: Option<u32>,
+    pub pkgname: String,
+    pub pkgbase: Option<String>,
+    pub pkgver: String,
+    pub pkgarch: Option<String>,
+    pub pkgbuild_sha256sum: Option<String>,
+    pub packager: Option<String>,
+    pub builddate: i64,
+    pub builddir: Option<String>,
+    pub startdir: Option<String>,
+    pub buildtool: Option<String>,
+    pub buildtoolver: Option<String>,
+    pub buildenv: Vec<String>,
+    pub options: Vec<String>,
+    /// The packages installed at build time as `name-pkgver-pkgrel-arch`.
+    pub installed: Vec<String>,
+    /// Fields this crate does not know about, in the order they appeared.
+    pub extra: Vec<(String, String)>,
+}
+
+fn fields(s: &str) -> impl Iterator<Item = Result<(usize, &str, &str), InfoParseError>> {
+    s.lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| match line.split_once('=') {
+            Some((key, value)) => Ok((n, key.trim(), value.trim())),
+            None => Err(InfoParseError::MissingEquals(n)),
+        })
+}
+
+fn number<T: FromStr>(line: usize, key: &str, value: &str) -> Result<T, InfoParseError> {
+    value.parse().map_err(|_| InfoParseError::InvalidNumber {
+        line,
+        key: key.to_string(),
+    })
+}
+
+fn write_field<T: fmt::Display>(f: &mut fmt::Formatter, key: &str, value: T) -> fmt::Result {
+    writeln!(f, "{} = {}", key, value)
+}
+
+fn write_opt(f: &mut fmt::Formatter, key: &str, value: &Option<String>) -> fmt::Result {
+    match value {
+        Some(value) => write_field(f, key, value),
+        None => Ok(()),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, key: &str, values: &[String]) -> fmt::Result {
+    values.iter().try_for_each(|v| write_field(f, key, v))
+}
+
+impl FromStr for PkgInfo {
+    type Err = InfoParseError;
+
+    fn from_str(s: &str) -> Result<PkgInfo, InfoParseError> {
+        let mut info = PkgInfo::default();
+
+        for field in fields(s) {
+            let (line, key, value) = field?;
+            let v = value.to_string();
+            match key {
+                "pkgname" => info.pkgname = v,
+                "pkgbase" => info.pkgbase = Some(v),
+                "xdata" => info.xdata.push(v),
+                "pkgver" => info.pkgver = v,
+                "pkgdesc" => info.pkgdesc = Some(v),
+                "url" => info.url = Some(v),
+                "builddate" => info.builddate = number(line, key, value)?,
+                "packager" => info.packager = Some(v),
+                "size" => info.size = number(line, key, value)?,
+                "arch" => info.arch = Some(v),
+                "license" => info.license.push(v),
+                "replaces" => info.replaces.push(v),
+                "group" => info.group.push(v),
+                "conflict" => info.conflict.push(v),
+                "provides" => info.provides.push(v),
+                "backup" => info.backup.push(v),
+                "depend" => info.depend.push(v),
+                "optdepend" => info.optdepend.push(v),
+                "makedepend" => info.makedepend.push(v),
+                "checkdepend" => info.checkdepend.push(v),
+                _ => info.extra.push((key.to_string(), v)),
+            }
+        }
+
+        if info.pkgname.is_empty() {
+            return Err(InfoParseError::MissingField("pkgname"));
+        }
+        if info.pkgver.is_empty() {
+            return Err(InfoParseError::MissingField("pkgver"));
+        }
+        Ok(info)
+    }
+}
+
+impl fmt::Display for PkgInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_field(f, "pkgname", &self.pkgname)?;
+        write_opt(f, "pkgbase", &self.pkgbase)?;
+        write_list(f, "xdata", &self.xdata)?;
+        write_field(f, "pkgver", &self.pkgver)?;
+        write_opt(f, "pkgdesc", &self.pkgdesc)?;
+        write_opt(f, "url", &self.url)?;
+        write_field(f, "builddate", self.builddate)?;
+        write_opt(f, "packager", &self.packager)?;
+        write_field(f, "size", self.size)?;
+        write_opt(f, "arch", &self.arch)?;
+        write_list(f, "license", &self.license)?;
+        write_list(f, "replaces", &self.replaces)?;
+        write_list(f, "group", &self.group)?;
+        write_list(f, "conflict", &self.conflict)?;
+        write_list(f, "provides", &self.provides)?;
+        write_list(f, "backup", &self.backup)?;
+        write_list(f, "depend", &self.depend)?;
+        write_list(f, "optdepend", &self.optdepend)?;
+        write_list(f, "makedepend", &self.makedepend)?;
+        write_list(f, "checkdepend", &self.checkdepend)?;
+        for (key, value) in &self.extra {
+            write_field(f, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl PkgInfo {
+    /// Builds the `.PKGINFO` of a package from its metadata.
+    ///
+    /// Sync and local dbs do not store makedepends and checkdepends of every package so
+    /// these may be empty.
+    pub fn from_pkg(pkg: &Pkg) -> PkgInfo {
+        PkgInfo {
+            pkgname: pkg.name().to_string(),
+            pkgbase: pkg.base().map(|s| s.to_string()),
+            xdata: Vec::new(),
+            pkgver: pkg.version().to_string(),
+            pkgdesc: pkg.desc().map(|s| s.to_string()),
+            url: pkg.url().map(|s| s.to_string()),
+            builddate: pkg.build_date(),
+            packager: pkg.packager().map(|s| s.to_string()),
+            size: pkg.isize(),
+            arch: pkg.arch().map(|s| s.to_string()),
+            license: pkg.licenses().iter().map(|s| s.to_string()).collect(),
+            replaces: pkg.replaces().iter().map(|d| d.to_string()).collect(),
+            group: pkg.groups().iter().map(|s| s.to_string()).collect(),
+            conflict: pkg.conflicts().iter().map(|d| d.to_string()).collect(),
+            provides: pkg.provides().iter().map(|d| d.to_string()).collect(),
+            backup: pkg.backup().iter().map(|b| b.name().to_string()).collect(),
+            depend: pkg.depends().iter().map(|d| d.to_string()).collect(),
+            optdepend: pkg.optdepends().iter().map(|d| d.to_string()).collect(),
+            makedepend: pkg.makedepends().iter().map(|d| d.to_string()).collect(),
+            checkdepend: pkg.checkdepends().iter().map(|d| d.to_string()).collect(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for BuildInfo {
+    type Err = InfoParseError;
+
+    fn from_str(s: &str) -> Result<BuildInfo, InfoParseError> {
+        let mut info = BuildInfo::default();
+
+        for field in fields(s) {
+            let (line, key, value) = field?;
+            let v = value.to_string();
+            match key {
+                "format" => info.format = Some(number(line, key, value)?),
+                "pkgname" => info.pkgname = v,
+                "pkgbase" => info.pkgbase = Some(v),
+                "pkgver" => info.pkgver = v,
+                "pkgarch" => info.pkgarch = Some(v),
+                "pkgbuild_sha256sum" => info.pkgbuild_sha256sum = Some(v),
+                "packager" => info.packager = Some(v),
+                "builddate" => info.builddate = number(line, key, value)?,
+                "builddir" => info.builddir = Some(v),
+                "startdir" => info.startdir = Some(v),
+                "buildtool" => info.buildtool = Some(v),
+                "buildtoolver" => info.buildtoolver = Some(v),
+                "buildenv" => info.buildenv.push(v),
+                "options" => info.options.push(v),
+                "installed" => info.installed.push(v),
+                _ => info.extra.push((key.to_string(), v)),
+            }
+        }
+
+        if info.pkgname.is_empty() {
+            return Err(InfoParseError::MissingField("pkgname"));
+        }
+        if info.pkgver.is_empty() {
+            return Err(InfoParseError::MissingField("pkgver"));
+        }
+        Ok(info)
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(format) = self.format {
+            write_field(f, "format", format)?;
+        }
+        write_field(f, "pkgname", &self.pkgname)?;
+        write_opt(f, "pkgbase", &self.pkgbase)?;
+        write_field(f, "pkgver", &self.pkgver)?;
+        write_opt(f, "pkgarch", &self.pkgarch)?;
+        write_opt(f, "pkgbuild_sha256sum", &self.pkgbuild_sha256sum)?;
+        write_opt(f, "packager", &self.packager)?;
+        write_field(f, "builddate", self.builddate)?;
+        write_opt(f, "builddir", &self.builddir)?;
+        write_opt(f, "startdir", &self.startdir)?;
+        write_opt(f, "buildtool", &self.buildtool)?;
+        write_opt(f, "buildtoolver", &self.buildtoolver)?;
+        write_list(f, "buildenv", &self.buildenv)?;
+        write_list(f, "options", &self.options)?;
+        write_list(f, "installed", &self.installed)?;
+        for (key, value) in &self.extra {
+            write_field(f, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "archive")]
+mod archive {
+    use super::{BuildInfo, PkgInfo};
+    use crate::PackageArchive;
+
+    use std::io;
+    use std::path::Path;
+
+    fn read_info<T>(path: &Path, name: &str) -> io::Result<T>
+    where
+        T: std::str::FromStr<Err = super::InfoParseError>,
+    {
+        let data = PackageArchive::open(path)?.read_file(name)?;
+        String::from_utf8(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    impl PkgInfo {
+        /// Reads the `.PKGINFO` of a package file.
+        pub fn from_package<P: AsRef<Path>>(path: P) -> io::Result<PkgInfo> {
+            read_info(path.as_ref(), ".PKGINFO")
+        }
+    }
+
+    impl BuildInfo {
+        /// Reads the `.BUILDINFO` of a package file.
+        pub fn from_package<P: AsRef<Path>>(path: P) -> io::Result<BuildInfo> {
+            read_info(path.as_ref(), ".BUILDINFO")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Alpm, SigLevel};
+
+    const PKGINFO: &str = "# Generated by makepkg 6.0.2
+# using fakeroot version 1.31
+pkgname = vifm
+pkgbase = vifm
+xdata = pkgtype=pkg
+pkgver = 0.10.1-1
+pkgdesc = A file manager with curses interface, which provides Vi[m]-like environment
+url = https://vifm.info/
+builddate = 1556989213
+packager = Jaroslav Lichtblau <svetlemodry@archlinux.org>
+size = 3440640
+arch = x86_64
+license = GPL
+backup = etc/vifm/vifmrc
+depend = ncurses
+depend = desktop-file-utils
+optdepend = vim: vifm vim plugin
+makedepend = gtk2
+";
+
+    const BUILDINFO: &str = "format = 2
+pkgname = vifm
+pkgbase = vifm
+pkgver = 0.10.1-1
+pkgarch = x86_64
+pkgbuild_sha256sum = 2d2f1e6f0c1f6f3b0f77c8a8a9b0b9c1f9d6b3a2c1d0e9f8a7b6c5d4e3f2a1b0
+packager = Jaroslav Lichtblau <svetlemodry@archlinux.org>
+builddate = 1556989213
+builddir = /build
+startdir = /startdir
+buildtool = devtools
+buildtoolver = 1:1.0.0-1-any
+buildenv = !distcc
+buildenv = color
+options = strip
+options = !debug
+installed = acl-2.2.53-1-x86_64
+installed = ncurses-6.1-6-x86_64
+";
+
+    #[test]
+    fn test_pkginfo() {
+        let info = PKGINFO.parse::<PkgInfo>().unwrap();
+        assert_eq!(info.pkgname, "vifm");
+        assert_eq!(info.xdata, ["pkgtype=pkg"]);
+        assert_eq!(info.builddate, 1556989213);
+        assert_eq!(info.depend, ["ncurses", "desktop-file-utils"]);
+        assert_eq!(info.optdepend, ["vim: vifm vim plugin"]);
+
+        let written = info.to_string();
+        assert_eq!(written, PKGINFO.split_once("1.31\n").unwrap().1);
+        assert_eq!(written.parse::<PkgInfo>().unwrap(), info);
+
+        assert_eq!(
+            "pkgname = a\nsize = big".parse::<PkgInfo>(),
+            Err(InfoParseError::InvalidNumber {
+                line: 2,
+                key: "size".into()
+            })
+        );
+        assert_eq!(
+            "pkgname = a\nfoo".parse::<PkgInfo>(),
+            Err(InfoParseError::MissingEquals(2))
+        );
+        assert_eq!(
+            "pkgname = a".parse::<PkgInfo>(),
+            Err(InfoParseError::MissingField("pkgver"))
+        );
+    }
+
+    #[test]
+    fn test_buildinfo() {
+        let info = BUILDINFO.parse::<BuildInfo>().unwrap();
+        assert_eq!(info.format, Some(2));
+        assert_eq!(info.builddir.as_deref(), Some("/build"));
+        assert_eq!(info.buildenv, ["!distcc", "color"]);
+        assert_eq!(info.options, ["strip", "!debug"]);
+        assert_eq!(info.installed.len(), 2);
+        assert_eq!(info.to_string(), BUILDINFO);
+
+        let mut other = info.clone();
+        other.installed[1] = "ncurses-6.1-7-x86_64".into();
+        assert_ne!(info, other);
+    }
+
+    #[test]
+    fn test_pkginfo_from_pkg() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let db = handle.register_syncdb("core", SigLevel::NONE).unwrap();
+        let pkg = db.pkg("linux").unwrap();
+
+        let info = PkgInfo::from_pkg(pkg);
+        assert_eq!(info.pkgname, "linux");
+        assert_eq!(info.pkgver, "5.1.8.arch1-1");
+        assert_eq!(info.size, pkg.isize());
+        assert!(info.depend.iter().any(|d| d.starts_with("coreutils")));
+
+        let parsed = info.to_string().parse::<PkgInfo>().unwrap();
+        assert_eq!(parsed, info);
+    }
+}
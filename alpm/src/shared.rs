@@ -0,0 +1,349 @@
+use crate::cb::{DL_CB, EVENT_CB, FETCH_CB, LOG_CB, PROGRESS_CB, QUESTION_CB};
+use crate::{
+    Alpm, AnyDownloadEvent, AnyEvent, AnyQuestion, FetchResult, ListenerId, LogLevel, Progress,
+    ReleaseError,
+};
+
+use alpm_sys::alpm_handle_t;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// An [`Alpm`] handle that can be sent to other threads.
+///
+/// libalpm itself has no thread affinity, the handle is only tied to a thread by the callbacks
+/// it stores. `SendAlpm` only allows callbacks that are `Send` so the whole handle can be
+/// moved between threads.
+///
+/// The handle is accessed through [`SendAlpm::with`]. Callbacks set with the normal
+/// [`Alpm`] setters inside of `with` can not be checked to be `Send`, so they are removed
+/// again and `with` panics. Use the setters on `SendAlpm` instead. The same applies to
+/// listeners added with [`Alpm::add_log_listener`] and friends.
+pub struct SendAlpm {
+    alpm: Alpm,
+}
+
+// every callback and listener is Send. the listener registry is owned by the handle and the
+// boxed callbacks do not point back into it, so a callback taken out of the handle with
+// take_raw_*_cb shares no state with the handle. the raw callbacks are not Send and can not be
+// returned from with either.
+unsafe impl Send for SendAlpm {}
+
+impl fmt::Debug for SendAlpm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendAlpm").finish()
+    }
+}
+
+impl SendAlpm {
+    /// Wraps a handle.
+    ///
+    /// Returns the handle back if it has callbacks or listeners that are not known to be
+    /// `Send`.
+    pub fn new(alpm: Alpm) -> Result<SendAlpm, Alpm> {
+        if alpm.cbs.non_send.get() == 0 && alpm.cbs.listeners.is_send() {
+            Ok(SendAlpm { alpm })
+        } else {
+            Err(alpm)
+        }
+    }
+
+    /// Unwraps the handle.
+    pub fn into_inner(self) -> Alpm {
+        self.alpm
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut alpm_handle_t {
+        self.alpm.as_ptr()
+    }
+
+    /// Calls `f` with the handle.
+    ///
+    /// The return value has to be `Send` as the handle may be moved to another thread
+    /// afterwards. This keeps callbacks taken out of the handle from escaping:
+    ///
+    /// ```compile_fail
+    /// # use alpm::{Alpm, SendAlpm};
+    /// let alpm = Alpm::new("/", "/var/lib/pacman").unwrap();
+    /// let mut alpm = SendAlpm::new(alpm).unwrap();
+    /// let cb = alpm.with(|alpm| alpm.take_raw_log_cb());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` sets a callback or adds a listener that is not known to be `Send`. The
+    /// callback or listener is removed before panicking.
+    pub fn with<R: Send, F: FnOnce(&mut Alpm) -> R>(&mut self, f: F) -> R {
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| f(&mut self.alpm)));
+        let non_send = self.remove_non_send();
+
+        match ret {
+            Ok(_) if non_send => panic!("callback set on SendAlpm is not known to be Send"),
+            Ok(ret) => ret,
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+
+    pub fn release(self) -> Result<(), ReleaseError> {
+        self.alpm.release()
+    }
+
+    pub fn set_log_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(LogLevel, &str, &mut T) + Send + 'static,
+    {
+        self.alpm.set_log_cb(data, f);
+        self.alpm.cbs.set_send(LOG_CB, true);
+    }
+
+    pub fn set_dl_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(&str, AnyDownloadEvent, &mut T) + Send + 'static,
+    {
+        self.alpm.set_dl_cb(data, f);
+        self.alpm.cbs.set_send(DL_CB, true);
+    }
+
+    pub fn set_event_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(AnyEvent, &mut T) + Send + 'static,
+    {
+        self.alpm.set_event_cb(data, f);
+        self.alpm.cbs.set_send(EVENT_CB, true);
+    }
+
+    pub fn set_progress_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(Progress, &str, i32, usize, usize, &mut T) + Send + 'static,
+    {
+        self.alpm.set_progress_cb(data, f);
+        self.alpm.cbs.set_send(PROGRESS_CB, true);
+    }
+
+    pub fn set_question_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(AnyQuestion, &mut T) + Send + 'static,
+    {
+        self.alpm.set_question_cb(data, f);
+        self.alpm.cbs.set_send(QUESTION_CB, true);
+    }
+
+    pub fn set_fetch_cb<T, F>(&mut self, data: T, f: F)
+    where
+        T: Send + 'static,
+        F: FnMut(&str, &str, bool, &mut T) -> FetchResult + Send + 'static,
+    {
+        self.alpm.set_fetch_cb(data, f);
+        self.alpm.cbs.set_send(FETCH_CB, true);
+    }
+
+    pub fn add_log_listener<F>(&mut self, f: F) -> ListenerId
+    where
+        F: FnMut(LogLevel, &str) + Send + 'static,
+    {
+        self.alpm
+            .add_log_listener_inner(Rc::new(RefCell::new(f)), true)
+    }
+
+    pub fn add_dl_listener<F>(&mut self, f: F) -> ListenerId
+    where
+        F: FnMut(&str, AnyDownloadEvent) + Send + 'static,
+    {
+        self.alpm
+            .add_dl_listener_inner(Rc::new(RefCell::new(f)), true)
+    }
+
+    pub fn add_event_listener<F>(&mut self, f: F) -> ListenerId
+    where
+        F: FnMut(AnyEvent) + Send + 'static,
+    {
+        self.alpm
+            .add_event_listener_inner(Rc::new(RefCell::new(f)), true)
+    }
+
+    pub fn add_progress_listener<F>(&mut self, f: F) -> ListenerId
+    where
+        F: FnMut(Progress, &str, i32, usize, usize) + Send + 'static,
+    {
+        self.alpm
+            .add_progress_listener_inner(Rc::new(RefCell::new(f)), true)
+    }
+
+    fn remove_non_send(&mut self) -> bool {
+        let alpm = &self.alpm;
+        let cbs = &alpm.cbs;
+        let non_send = cbs.listeners.remove_non_send() | (cbs.non_send.get() != 0);
+
+        if !cbs.is_send(LOG_CB) {
+            drop(alpm.take_raw_log_cb());
+        }
+        if !cbs.is_send(DL_CB) {
+            drop(alpm.take_raw_dl_cb());
+        }
+        if !cbs.is_send(EVENT_CB) {
+            drop(alpm.take_raw_event_cb());
+        }
+        if !cbs.is_send(PROGRESS_CB) {
+            drop(alpm.take_raw_progress_cb());
+        }
+        if !cbs.is_send(QUESTION_CB) {
+            drop(alpm.take_raw_question_cb());
+        }
+        if !cbs.is_send(FETCH_CB) {
+            drop(alpm.take_raw_fetch_cb());
+        }
+
+        non_send
+    }
+}
+
+/// A [`SendAlpm`] that can be shared between threads.
+///
+/// Access to the handle is serialised through a mutex. Cloning a `SharedAlpm` gives another
+/// reference to the same handle.
+#[derive(Debug, Clone)]
+pub struct SharedAlpm {
+    inner: Arc<Mutex<SendAlpm>>,
+}
+
+impl From<SendAlpm> for SharedAlpm {
+    fn from(alpm: SendAlpm) -> SharedAlpm {
+        SharedAlpm::new(alpm)
+    }
+}
+
+impl SharedAlpm {
+    pub fn new(alpm: SendAlpm) -> SharedAlpm {
+        SharedAlpm {
+            inner: Arc::new(Mutex::new(alpm)),
+        }
+    }
+
+    /// Locks the handle and calls `f` with it.
+    ///
+    /// A panic in another call to `with` does not poison the handle.
+    pub fn with<R: Send, F: FnOnce(&mut Alpm) -> R>(&self, f: F) -> R {
+        let mut alpm = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        alpm.with(f)
+    }
+
+    /// Locks the handle and calls `f` with the [`SendAlpm`], for example to set callbacks.
+    pub fn with_send<R, F: FnOnce(&mut SendAlpm) -> R>(&self, f: F) -> R {
+        let mut alpm = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut alpm)
+    }
+
+    /// Returns the [`SendAlpm`] if this is the only reference to it.
+    pub fn try_unwrap(self) -> Result<SendAlpm, SharedAlpm> {
+        Arc::try_unwrap(self.inner)
+            .map(|m| m.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .map_err(|inner| SharedAlpm { inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_send_alpm() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let mut handle = SendAlpm::new(handle).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        handle.set_log_cb(count.clone(), |_, _, count| {
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let mut handle = thread::spawn(move || {
+            let name = handle.with(|alpm| alpm.localdb().pkg("pacman").unwrap().name().to_string());
+            assert_eq!(name, "pacman");
+            handle
+        })
+        .join()
+        .unwrap();
+
+        handle.with(|alpm| {
+            alpm.register_syncdb("core", crate::SigLevel::NONE)
+                .unwrap()
+                .pkgs()
+                .len()
+        });
+        assert!(count.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_send_alpm_non_send() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        handle.set_event_cb(Rc::new(()), |_, _| ());
+        let handle = SendAlpm::new(handle).unwrap_err();
+        drop(handle.take_raw_event_cb());
+        let mut handle = SendAlpm::new(handle).unwrap();
+
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            handle.with(|alpm| alpm.set_event_cb(Rc::new(()), |_, _| ()))
+        }));
+        assert!(ret.is_err());
+        assert_eq!(handle.alpm.cbs.non_send.get(), 0);
+        handle.with(|alpm| assert!(alpm.take_raw_event_cb().cb.is_none()));
+    }
+
+    #[test]
+    fn test_send_alpm_listeners() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let rc = Rc::new(());
+        handle.add_log_listener(move |_, _| drop(rc.clone()));
+        assert!(SendAlpm::new(handle).is_err());
+
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let mut handle = SendAlpm::new(handle).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = count.clone();
+        handle.add_log_listener(move |_, _| {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            handle.with(|alpm| {
+                let rc = Rc::new(());
+                alpm.add_log_listener(move |_, _| drop(rc.clone()))
+            })
+        }));
+        assert!(ret.is_err());
+        assert!(handle.alpm.cbs.listeners.is_send());
+
+        let mut handle = thread::spawn(move || handle).join().unwrap();
+        handle.with(|alpm| alpm.localdb().pkgs().len());
+        assert!(count.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn test_shared_alpm() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let handle = SharedAlpm::new(SendAlpm::new(handle).unwrap());
+
+        let threads = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || handle.with(|alpm| alpm.localdb().pkgs().len()))
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            assert!(thread.join().unwrap() > 0);
+        }
+
+        let handle = handle.try_unwrap().unwrap();
+        handle.release().unwrap();
+    }
+}
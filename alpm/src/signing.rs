@@ -1,4 +1,4 @@
-use crate::{Alpm, AlpmListMut, Db, Result, free};
+use crate::{Alpm, AlpmListMut, Db, Error, Result, SigLevel, TempDir, free};
 use crate::{Pkg, utils::*};
 
 use alpm_sys::_alpm_sigstatus_t::*;
@@ -7,7 +7,9 @@ use alpm_sys::*;
 
 use std::ffi::{CString, c_void};
 use std::mem::transmute;
-use std::{fmt, ptr, slice};
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::{fmt, fs, io, ptr, slice};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Ord, PartialOrd, Hash)]
 pub struct SignatureDecodeError;
@@ -205,7 +207,239 @@ impl Db {
     }
 }
 
+/// An owned copy of a [`SigResult`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignatureInfo {
+    pub status: SigStatus,
+    pub validity: SigValidity,
+    pub fingerprint: String,
+    pub uid: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub created: i64,
+    /// 0 if the key does not expire.
+    pub expires: i64,
+    pub revoked: bool,
+}
+
+impl From<&SigResult> for SignatureInfo {
+    fn from(res: &SigResult) -> SignatureInfo {
+        let key = res.key();
+        SignatureInfo {
+            status: res.status(),
+            validity: res.validity(),
+            fingerprint: key.fingerprint().to_string(),
+            uid: key.uid().map(|s| s.to_string()),
+            name: key.name().map(|s| s.to_string()),
+            email: key.email().map(|s| s.to_string()),
+            created: key.created(),
+            expires: key.expires(),
+            revoked: key.revoked() != 0,
+        }
+    }
+}
+
+/// The result of verifying the signatures of a package or database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationReport {
+    pub signatures: Vec<SignatureInfo>,
+    /// The signature level the signatures were judged by.
+    pub sig_level: SigLevel,
+    /// The error libalpm returned while checking, such as [`Error::SigMissing`].
+    pub error: Option<Error>,
+    /// Whether the signatures are acceptable under `sig_level`.
+    ///
+    /// A missing signature is trusted if `sig_level` does not require one. Any other error
+    /// makes the report untrusted.
+    pub trusted: bool,
+}
+
+impl VerificationReport {
+    fn new(
+        siglist: &SigList,
+        error: Option<Error>,
+        sig_level: SigLevel,
+        package: bool,
+    ) -> VerificationReport {
+        let signatures = siglist
+            .results()
+            .iter()
+            .map(SignatureInfo::from)
+            .collect::<Vec<_>>();
+        let trusted = match error {
+            None => is_trusted(&signatures, sig_level, package),
+            Some(Error::SigMissing) => is_trusted(&[], sig_level, package),
+            Some(_) => false,
+        };
+
+        VerificationReport {
+            signatures,
+            sig_level,
+            error,
+            trusted,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        !self.signatures.is_empty()
+    }
+}
+
+// the same rules libalpm uses when validating packages and databases
+fn is_trusted(signatures: &[SignatureInfo], level: SigLevel, package: bool) -> bool {
+    let (required, optional, marginal_ok, unknown_ok) = if package {
+        (
+            SigLevel::PACKAGE,
+            SigLevel::PACKAGE_OPTIONAL,
+            SigLevel::PACKAGE_MARGINAL_OK,
+            SigLevel::PACKAGE_UNKNOWN_OK,
+        )
+    } else {
+        (
+            SigLevel::DATABASE,
+            SigLevel::DATABASE_OPTIONAL,
+            SigLevel::DATABASE_MARGINAL_OK,
+            SigLevel::DATABASE_UNKNOWN_OK,
+        )
+    };
+
+    if !level.contains(required) {
+        return true;
+    }
+    if signatures.is_empty() {
+        return level.contains(optional);
+    }
+
+    signatures.iter().all(|sig| {
+        sig.status == SigStatus::Valid
+            && match sig.validity {
+                SigValidity::Full => true,
+                SigValidity::Marginal => level.contains(marginal_ok),
+                SigValidity::Unknown => level.contains(unknown_ok),
+                SigValidity::Never => false,
+            }
+    })
+}
+
+fn effective_siglevel(level: SigLevel, handle: *mut alpm_handle_t) -> SigLevel {
+    if level.contains(SigLevel::USE_DEFAULT) {
+        let level = unsafe { alpm_option_get_default_siglevel(handle) };
+        SigLevel::from_bits(level as u32).unwrap()
+    } else {
+        level
+    }
+}
+
+impl Pkg {
+    /// Checks the signature of the package.
+    ///
+    /// The signatures are judged by the signature level of the package's db, or the local
+    /// file signature level for packages loaded from a file.
+    pub fn verify(&self) -> VerificationReport {
+        let level = match self.db() {
+            Some(db) => db.siglevel(),
+            None => {
+                let level = unsafe { alpm_option_get_local_file_siglevel(self.handle_ptr()) };
+                SigLevel::from_bits(level as u32).unwrap()
+            }
+        };
+        self.verify_with_level(level)
+    }
+
+    /// Checks the signature of the package, judged by `level` instead of the package's
+    /// signature level.
+    pub fn verify_with_level(&self, level: SigLevel) -> VerificationReport {
+        let level = effective_siglevel(level, self.handle_ptr());
+
+        let mut siglist = SigList::new();
+        let error = self.check_signature(&mut siglist).err();
+        VerificationReport::new(&siglist, error, level, true)
+    }
+}
+
+impl Db {
+    /// Checks the signature of the database, judged by the database's signature level.
+    pub fn verify(&self) -> VerificationReport {
+        let level = effective_siglevel(self.siglevel(), self.handle_ptr());
+
+        let mut siglist = SigList::new();
+        let error = self.check_signature(&mut siglist).err();
+        VerificationReport::new(&siglist, error, level, false)
+    }
+}
+
+/// A detached signature passed to [`Alpm::verify_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetachedSignature<'a> {
+    /// The path to a binary signature file.
+    Path(&'a Path),
+    /// The raw bytes of a binary signature.
+    Bytes(&'a [u8]),
+}
+
+impl<'a> From<&'a Path> for DetachedSignature<'a> {
+    fn from(path: &'a Path) -> Self {
+        DetachedSignature::Path(path)
+    }
+}
+
+impl<'a> From<&'a [u8]> for DetachedSignature<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        DetachedSignature::Bytes(bytes)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for DetachedSignature<'a> {
+    fn from(bytes: &'a Vec<u8>) -> Self {
+        DetachedSignature::Bytes(bytes)
+    }
+}
+
 impl Alpm {
+    /// Checks a detached signature of an arbitrary file against the keyring in
+    /// [`Alpm::gpgdir`].
+    ///
+    /// A signature is always required. Whether marginal and unknown trust is accepted is
+    /// taken from the database part of the default signature level.
+    ///
+    /// libalpm can only check signatures of packages and databases, so the file is
+    /// checked as the database of a temporary handle that shares this handle's gpgdir.
+    pub fn verify_file<'a, P: AsRef<Path>, S: Into<DetachedSignature<'a>>>(
+        &self,
+        path: P,
+        sig: S,
+    ) -> io::Result<VerificationReport> {
+        let default = effective_siglevel(self.default_siglevel(), self.as_ptr());
+        let level = SigLevel::DATABASE
+            | default & (SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK);
+
+        let dir = TempDir::new("alpm-verify")?;
+        let sync = dir.path().join("sync");
+        fs::create_dir(&sync)?;
+
+        symlink(fs::canonicalize(path)?, sync.join("verify.db"))?;
+        let sig_path = sync.join("verify.db.sig");
+        match sig.into() {
+            DetachedSignature::Path(sig) => symlink(fs::canonicalize(sig)?, sig_path)?,
+            DetachedSignature::Bytes(sig) => fs::write(sig_path, sig)?,
+        }
+
+        let dbpath = dir.path().to_str().ok_or(io::ErrorKind::InvalidInput)?;
+        let mut siglist = SigList::new();
+        let error = (|| {
+            let mut handle = Alpm::new("/", dbpath)?;
+            if let Some(gpgdir) = self.gpgdir() {
+                handle.set_gpgdir(gpgdir)?;
+            }
+            handle.set_dbext(".db");
+            let db = handle.register_syncdb("verify", level)?;
+            db.check_signature(&mut siglist)
+        })()
+        .err();
+
+        Ok(VerificationReport::new(&siglist, error, level, false))
+    }
+
     pub fn extract_keyid<S: Into<Vec<u8>>>(
         &self,
         ident: S,
@@ -228,3 +462,177 @@ impl Alpm {
         unsafe { Ok(AlpmListMut::from_ptr(keys)) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "E967A26CDC84A0571A9F579BEA590B343DCECB4D";
+
+    fn sig(status: SigStatus, validity: SigValidity) -> SignatureInfo {
+        SignatureInfo {
+            status,
+            validity,
+            fingerprint: "8218F88849AAC522E94CF470A5E9288C4FA415FA".into(),
+            uid: None,
+            name: None,
+            email: None,
+            created: 0,
+            expires: 0,
+            revoked: false,
+        }
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let full = sig(SigStatus::Valid, SigValidity::Full);
+        let marginal = sig(SigStatus::Valid, SigValidity::Marginal);
+        let expired = sig(SigStatus::KeyExpired, SigValidity::Full);
+
+        assert!(is_trusted(&[], SigLevel::NONE, true));
+        assert!(!is_trusted(&[], SigLevel::PACKAGE, true));
+        assert!(is_trusted(&[], SigLevel::PACKAGE, false));
+        assert!(is_trusted(
+            &[],
+            SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL,
+            true
+        ));
+        assert!(is_trusted(
+            std::slice::from_ref(&full),
+            SigLevel::PACKAGE,
+            true
+        ));
+        assert!(!is_trusted(
+            &[full.clone(), marginal.clone()],
+            SigLevel::PACKAGE,
+            true
+        ));
+        assert!(is_trusted(
+            &[full, marginal],
+            SigLevel::PACKAGE | SigLevel::PACKAGE_MARGINAL_OK,
+            true
+        ));
+        assert!(!is_trusted(
+            &[expired],
+            SigLevel::DATABASE | SigLevel::DATABASE_UNKNOWN_OK,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let db = handle.register_syncdb("core", SigLevel::NONE).unwrap();
+        let pkg = db.pkg("linux").unwrap();
+        assert!(pkg.base64_sig().is_some());
+
+        let report = pkg.verify();
+        assert_eq!(report.sig_level, SigLevel::NONE);
+        assert!(report.trusted);
+
+        let report = db.verify();
+        assert!(!report.is_signed());
+        assert!(report.error.is_some());
+        assert!(report.trusted);
+
+        let handle = Alpm::new("/", "tests/db").unwrap();
+        let level = SigLevel::PACKAGE | SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL;
+        let db = handle.register_syncdb("core", level).unwrap();
+        assert!(db.verify().trusted);
+        let report = db.pkg("linux").unwrap().verify();
+        assert_eq!(report.sig_level, level);
+        assert!(!report.trusted);
+
+        let db = handle
+            .register_syncdb("extra", SigLevel::USE_DEFAULT)
+            .unwrap();
+        assert_eq!(db.verify().sig_level, handle.default_siglevel());
+    }
+
+    #[test]
+    fn test_verify_signed() {
+        let mut handle = Alpm::new("/", "tests/signed").unwrap();
+        handle.set_gpgdir("tests/signed/gnupg").unwrap();
+
+        let db = handle
+            .register_syncdb("signed", SigLevel::DATABASE)
+            .unwrap();
+        let report = db.verify();
+        assert_eq!(report.error, None);
+        assert!(report.trusted);
+        assert_eq!(report.signatures.len(), 1);
+
+        let sig = &report.signatures[0];
+        assert_eq!(sig.status, SigStatus::Valid);
+        assert_eq!(sig.validity, SigValidity::Full);
+        assert_eq!(sig.fingerprint, FINGERPRINT);
+        assert_eq!(sig.uid.as_deref(), Some("alpm.rs test key <test@alpm.rs>"));
+        assert_eq!(sig.name.as_deref(), Some("alpm.rs test key"));
+        assert_eq!(sig.email.as_deref(), Some("test@alpm.rs"));
+        assert_eq!(sig.created, 1792365340);
+        assert_eq!(sig.expires, 4102401600);
+        assert!(!sig.revoked);
+
+        let pkg = handle
+            .pkg_load(
+                "tests/signed/pacman-5.1.3-1-x86_64.pkg.tar.xz",
+                false,
+                SigLevel::NONE,
+            )
+            .unwrap();
+        let report = pkg.verify_with_level(SigLevel::PACKAGE);
+        assert_eq!(report.error, None);
+        assert!(report.trusted);
+        assert_eq!(report.signatures, std::slice::from_ref(sig));
+
+        let pkg = handle
+            .pkg_load(
+                "tests/pacman-5.1.3-1-x86_64.pkg.tar.xz",
+                false,
+                SigLevel::NONE,
+            )
+            .unwrap();
+        let report = pkg.verify_with_level(SigLevel::PACKAGE);
+        assert_eq!(report.error, Some(Error::SigMissing));
+        assert!(!report.trusted);
+        let report = pkg.verify_with_level(SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL);
+        assert_eq!(report.error, Some(Error::SigMissing));
+        assert!(report.trusted);
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let handle = Alpm::new("/", "tests/db").unwrap();
+
+        let path = Path::new("tests/db/sync/core.db");
+        let report = handle.verify_file(path, &b"not a signature"[..]).unwrap();
+        assert!(report.sig_level.contains(SigLevel::DATABASE));
+        assert!(!report.is_signed());
+        assert!(report.error.is_some());
+        assert!(!report.trusted);
+
+        let sig = Path::new("tests/db/sync/missing.sig");
+        assert!(handle.verify_file(path, sig).is_err());
+
+        let mut handle = Alpm::new("/", "tests/db").unwrap();
+        handle.set_gpgdir("tests/signed/gnupg").unwrap();
+        let file = Path::new("tests/signed/file.txt");
+        let sig = Path::new("tests/signed/file.txt.sig");
+
+        let report = handle.verify_file(file, sig).unwrap();
+        assert_eq!(report.error, None);
+        assert!(report.trusted);
+        assert_eq!(report.signatures.len(), 1);
+        assert_eq!(report.signatures[0].status, SigStatus::Valid);
+        assert_eq!(report.signatures[0].fingerprint, FINGERPRINT);
+
+        let bytes = fs::read(sig).unwrap();
+        assert_eq!(handle.verify_file(file, &bytes).unwrap(), report);
+
+        let report = handle.verify_file(path, sig).unwrap();
+        assert!(!report.trusted);
+        assert_eq!(report.signatures.len(), 1);
+        assert_eq!(report.signatures[0].status, SigStatus::Invalid);
+        assert_eq!(report.signatures[0].fingerprint, FINGERPRINT);
+    }
+}
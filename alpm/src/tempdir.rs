@@ -0,0 +1,93 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, DirBuilder};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A private temporary directory that is removed when dropped.
+///
+/// The directory is created in [`std::env::temp_dir`] with a random name and mode 0700.
+/// Creation fails instead of reusing a path that already exists, so another user can not
+/// prepare the directory or its contents in advance.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new directory whose name starts with `prefix`.
+    pub fn new(prefix: &str) -> io::Result<TempDir> {
+        let base = std::env::temp_dir();
+
+        for _ in 0..64 {
+            let path = base.join(format!("{}-{:016x}", prefix, random()));
+            match DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "failed to create a unique temporary directory",
+        ))
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// RandomState is seeded from the OS, the counter and time keep names distinct within a process
+fn random() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u64(time);
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_tempdir() {
+        let dir = TempDir::new("alpm-test").unwrap();
+        let path = dir.path().to_path_buf();
+        let other = TempDir::new("alpm-test").unwrap();
+
+        assert_ne!(path, other.path());
+        let meta = fs::symlink_metadata(&path).unwrap();
+        assert!(meta.is_dir());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o700);
+
+        fs::write(path.join("file"), "file").unwrap();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
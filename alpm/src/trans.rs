@@ -191,6 +191,9 @@ impl Alpm {
     }
 
     pub fn trans_prepare(&mut self) -> std::result::Result<(), PrepareError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(target: crate::LOG_TARGET, "trans_prepare").entered();
+
         let mut list = ptr::null_mut();
         let ret = unsafe { alpm_trans_prepare(self.as_ptr(), &mut list) };
         let err = self.check_ret(ret);
@@ -207,6 +210,9 @@ impl Alpm {
     }
 
     pub fn trans_commit(&mut self) -> std::result::Result<(), CommitError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(target: crate::LOG_TARGET, "trans_commit").entered();
+
         let mut list = ptr::null_mut();
         let ret = unsafe { alpm_trans_commit(self.as_ptr(), &mut list) };
         let err = self.check_ret(ret);
@@ -11,6 +11,7 @@ use std::mem::transmute;
 use std::os::raw::c_uchar;
 use std::ptr::NonNull;
 use std::slice;
+use std::str::FromStr;
 use std::{cmp::Ordering, ops::Deref};
 
 use _alpm_db_usage_t::*;
@@ -50,6 +51,147 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
+pub struct SigLevelParseError {
+    token: String,
+}
+
+impl SigLevelParseError {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl fmt::Display for SigLevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid siglevel: {}", self.token)
+    }
+}
+
+impl std::error::Error for SigLevelParseError {}
+
+impl SigLevel {
+    const PACKAGE_TRUST_ALL: SigLevel =
+        SigLevel::PACKAGE_MARGINAL_OK.union(SigLevel::PACKAGE_UNKNOWN_OK);
+    const DATABASE_TRUST_ALL: SigLevel =
+        SigLevel::DATABASE_MARGINAL_OK.union(SigLevel::DATABASE_UNKNOWN_OK);
+}
+
+/// Parses a SigLevel using the syntax of pacman.conf.
+///
+/// Tokens are applied in order starting from [`SigLevel::NONE`]. Tokens without a `Package` or
+/// `Database` prefix apply to both. `UseDefault` sets [`SigLevel::USE_DEFAULT`].
+impl FromStr for SigLevel {
+    type Err = SigLevelParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sig = SigLevel::NONE;
+
+        for token in s.split_whitespace() {
+            if token == "UseDefault" {
+                sig.insert(SigLevel::USE_DEFAULT);
+                continue;
+            }
+
+            let (pkg, db, word) = if let Some(word) = token.strip_prefix("Package") {
+                (true, false, word)
+            } else if let Some(word) = token.strip_prefix("Database") {
+                (false, true, word)
+            } else {
+                (true, true, token)
+            };
+
+            let sides = [
+                (
+                    pkg,
+                    SigLevel::PACKAGE,
+                    SigLevel::PACKAGE_OPTIONAL,
+                    SigLevel::PACKAGE_TRUST_ALL,
+                ),
+                (
+                    db,
+                    SigLevel::DATABASE,
+                    SigLevel::DATABASE_OPTIONAL,
+                    SigLevel::DATABASE_TRUST_ALL,
+                ),
+            ];
+
+            for (enabled, check, optional, trust_all) in sides {
+                if !enabled {
+                    continue;
+                }
+
+                match word {
+                    "Never" => sig.remove(check),
+                    "Optional" => sig.insert(check | optional),
+                    "Required" => {
+                        sig.insert(check);
+                        sig.remove(optional);
+                    }
+                    "TrustedOnly" => sig.remove(trust_all),
+                    "TrustAll" => sig.insert(trust_all),
+                    _ => {
+                        return Err(SigLevelParseError {
+                            token: token.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(sig)
+    }
+}
+
+/// Formats a SigLevel using the syntax of pacman.conf.
+///
+/// libalpm ignores every other flag when [`SigLevel::USE_DEFAULT`] is set, so it is shown as
+/// `UseDefault` alone. Likewise the optional flags mean nothing without their check flag, such
+/// a side is shown as `Never`.
+impl fmt::Display for SigLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(SigLevel::USE_DEFAULT) {
+            return f.write_str("UseDefault");
+        }
+
+        let side = |check, optional, trust_all| {
+            let mut words = Vec::new();
+            if !self.contains(check) {
+                words.push("Never");
+            } else if self.contains(optional) {
+                words.push("Optional");
+            } else {
+                words.push("Required");
+            }
+            if self.contains(trust_all) {
+                words.push("TrustAll");
+            }
+            words
+        };
+
+        let pkg = side(
+            SigLevel::PACKAGE,
+            SigLevel::PACKAGE_OPTIONAL,
+            SigLevel::PACKAGE_TRUST_ALL,
+        );
+        let db = side(
+            SigLevel::DATABASE,
+            SigLevel::DATABASE_OPTIONAL,
+            SigLevel::DATABASE_TRUST_ALL,
+        );
+
+        let mut tokens = Vec::new();
+        if pkg == db {
+            tokens.extend(pkg.iter().map(|w| w.to_string()));
+        } else {
+            tokens.extend(pkg.iter().map(|w| format!("Package{}", w)));
+            tokens.extend(db.iter().map(|w| format!("Database{}", w)));
+        }
+
+        f.write_str(&tokens.join(" "))
+    }
+}
+
 bitflags! {
     #[derive(Debug, PartialEq, Eq, Clone, Copy)]
     pub struct Usage: u32 {
@@ -267,6 +409,10 @@ impl<'a> AnyDownloadEvent<'a> {
         }
     }
 
+    pub(crate) fn reborrow(&self) -> AnyDownloadEvent<'a> {
+        unsafe { AnyDownloadEvent::new(self.event, self.data) }
+    }
+
     #[allow(clippy::useless_conversion)]
     pub fn event(&self) -> DownloadEvent {
         let event =
@@ -390,3 +536,74 @@ impl Drop for Signature {
         unsafe { crate::free(self.as_ptr() as _) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_siglevel_parse() {
+        let sig: SigLevel = "Required DatabaseOptional TrustedOnly".parse().unwrap();
+        assert_eq!(
+            sig,
+            SigLevel::PACKAGE | SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL
+        );
+
+        let sig: SigLevel = "Optional TrustAll".parse().unwrap();
+        assert_eq!(
+            sig,
+            SigLevel::PACKAGE
+                | SigLevel::PACKAGE_OPTIONAL
+                | SigLevel::PACKAGE_MARGINAL_OK
+                | SigLevel::PACKAGE_UNKNOWN_OK
+                | SigLevel::DATABASE
+                | SigLevel::DATABASE_OPTIONAL
+                | SigLevel::DATABASE_MARGINAL_OK
+                | SigLevel::DATABASE_UNKNOWN_OK
+        );
+
+        assert_eq!("Never".parse::<SigLevel>().unwrap(), SigLevel::NONE);
+        assert_eq!("".parse::<SigLevel>().unwrap(), SigLevel::NONE);
+
+        let err = "Required PackageSometimes".parse::<SigLevel>().unwrap_err();
+        assert_eq!(err.token(), "PackageSometimes");
+        assert!("PackageTrustOnly".parse::<SigLevel>().is_err());
+    }
+
+    #[test]
+    fn test_siglevel_display() {
+        let sig = SigLevel::PACKAGE | SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL;
+        assert_eq!(sig.to_string(), "PackageRequired DatabaseOptional");
+        assert_eq!(SigLevel::NONE.to_string(), "Never");
+        assert_eq!(SigLevel::USE_DEFAULT.to_string(), "UseDefault");
+        assert_eq!(
+            (SigLevel::USE_DEFAULT | SigLevel::PACKAGE).to_string(),
+            "UseDefault"
+        );
+
+        for s in [
+            "UseDefault",
+            "Required",
+            "Optional TrustAll",
+            "PackageRequired PackageTrustAll DatabaseNever",
+            "PackageOptional DatabaseNever",
+        ] {
+            let sig = s.parse::<SigLevel>().unwrap();
+            assert_eq!(sig.to_string(), s);
+            assert_eq!(sig.to_string().parse::<SigLevel>().unwrap(), sig);
+        }
+
+        let sig = "PackageOptional DatabaseOptional DatabaseNever"
+            .parse::<SigLevel>()
+            .unwrap();
+        assert!(sig.contains(SigLevel::DATABASE_OPTIONAL));
+        assert_eq!(sig.to_string(), "PackageOptional DatabaseNever");
+        let parsed = sig.to_string().parse::<SigLevel>().unwrap();
+        assert_eq!(parsed, sig - SigLevel::DATABASE_OPTIONAL);
+        assert_eq!(parsed.to_string(), sig.to_string());
+        assert_eq!(
+            (SigLevel::PACKAGE_OPTIONAL | SigLevel::DATABASE_OPTIONAL).to_string(),
+            "Never"
+        );
+    }
+}
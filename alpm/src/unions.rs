@@ -270,6 +270,10 @@ impl<'a> AnyEvent<'a> {
         }
     }
 
+    pub(crate) fn reborrow(&self) -> AnyEvent<'a> {
+        unsafe { AnyEvent::new(self.inner) }
+    }
+
     pub fn event(&self) -> Event<'a> {
         let event = self.inner;
         let event_type = self.event_type();